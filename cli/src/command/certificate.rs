@@ -1,6 +1,13 @@
 use crate::config::Config;
+use crate::util::print_table;
+use chrono::{DateTime, Utc};
 use clap::{Arg, ArgMatches, Command};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::thread::sleep;
+use std::time::Duration;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
 
 pub fn sub_command() -> Command {
   Command::new("certificate")
@@ -8,31 +15,139 @@ pub fn sub_command() -> Command {
     .subcommand_required(true)
     .subcommand(
       Command::new("new")
-        .about("New certificate")
-        .arg(Arg::new("name").index(1).required(true)),
+        .about("New certificate, covering every given domain name")
+        .arg(Arg::new("name").index(1).required(true).num_args(1..)),
+    )
+    .subcommand(Command::new("list").about("List certificates"))
+    .subcommand(
+      Command::new("rm")
+        .about("Remove a certificate")
+        .arg(Arg::new("id").index(1).required(true)),
     )
 }
 
 pub fn new_certificate(config: &'static Config, arg_matches: &ArgMatches) {
-  let name = arg_matches.get_one::<String>("name").expect("required");
+  let names: Vec<&String> = arg_matches
+    .get_many::<String>("name")
+    .expect("required")
+    .collect();
   let response = config
     .cluster_api_client()
     .expect("Client connection failed")
     .post(format!("{}/certificate", config.api_base_url))
-    .json(&json!({"domain_name": name}))
+    .json(&json!({"domain_names": names}))
+    .bearer_auth(config.bearer_token())
+    .send()
+    .unwrap();
+  if !response.status().is_success() {
+    eprintln!("Failed to create certificate: {}", response.status());
+    return;
+  }
+  let mut certificate_order = response.json::<CertificateOrder>().unwrap();
+  let mut last_status = String::new();
+  // Issuance takes from seconds to minutes, the order is polled until it is done.
+  loop {
+    if certificate_order.status != last_status {
+      last_status = certificate_order.status.clone();
+      match certificate_order.status.as_str() {
+        "pending_dns" => println!(
+          "Waiting for {} to resolve to the cluster and challenges to propagate...",
+          certificate_order.domain_names.join(", ")
+        ),
+        "challenge_served" => println!("Challenges served, asking the CA to validate them..."),
+        "validating" => println!("Validating..."),
+        "issued" => {
+          println!(
+            "Certificate issued for {}",
+            certificate_order.domain_names.join(", ")
+          );
+          return;
+        }
+        "failed" => {
+          eprintln!(
+            "Certificate failed: {}",
+            certificate_order.failure_reason.unwrap_or_default()
+          );
+          return;
+        }
+        status => println!("{}...", status),
+      }
+    }
+    sleep(POLL_INTERVAL);
+    let response = config
+      .cluster_api_client()
+      .expect("Client connection failed")
+      .get(format!(
+        "{}/certificate-orders/{}",
+        config.api_base_url, certificate_order.id
+      ))
+      .bearer_auth(config.bearer_token())
+      .send()
+      .unwrap();
+    if !response.status().is_success() {
+      eprintln!("Failed to get certificate order: {}", response.status());
+      return;
+    }
+    certificate_order = response.json::<CertificateOrder>().unwrap();
+  }
+}
+
+pub fn list_certificates(config: &'static Config) {
+  let response = config
+    .cluster_api_client()
+    .expect("Client connection failed")
+    .get(format!("{}/certificates", config.api_base_url))
     .bearer_auth(config.bearer_token())
     .send()
     .unwrap();
   if response.status().is_success() {
-    println!(
-      "
-    {name}
+    let certificates = response.json::<Vec<Certificate>>().unwrap();
 
-    Set the following record on your DNS provider to continue:
+    let headers = vec!["ID", "Domains", "Expires"];
+    let mut rows = vec![];
+    for certificate in certificates {
+      let mut domain_names = vec![certificate.domain_name];
+      domain_names.extend(certificate.alt_names);
+      rows.push(vec![
+        certificate.id,
+        domain_names.join(", "),
+        certificate.expires_at.format("%Y-%m-%d").to_string(),
+      ]);
+    }
+    print_table(headers, rows);
+  } else {
+    eprintln!("Failed to list certificates: {}", response.status());
+  }
+}
 
-    Type Name Value
-    A    @    34.175.93.38
-    "
-    );
+pub fn remove_certificate(config: &'static Config, arg_matches: &ArgMatches) {
+  let id = arg_matches.get_one::<String>("id").expect("required");
+  let response = config
+    .cluster_api_client()
+    .expect("Client connection failed")
+    .delete(format!("{}/certificates/{}", config.api_base_url, id))
+    .bearer_auth(config.bearer_token())
+    .send()
+    .unwrap();
+  if response.status().is_success() {
+    println!("Certificate {} removed", id);
+  } else {
+    eprintln!("Failed to remove certificate: {}", response.status());
   }
 }
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Certificate {
+  id: String,
+  domain_name: String,
+  alt_names: Vec<String>,
+  expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CertificateOrder {
+  id: String,
+  domain_names: Vec<String>,
+  status: String,
+  failure_reason: Option<String>,
+}
@@ -0,0 +1,136 @@
+use crate::config::Config;
+use crate::util::{format_time_ago, print_table};
+use chrono::{DateTime, Utc};
+use clap::{Arg, ArgMatches, Command};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+pub fn sub_command() -> Command {
+  Command::new("cluster")
+    .about("Cluster commands")
+    .subcommand_required(true)
+    .subcommand(
+      Command::new("join-token")
+        .about("Create a one-time token for a new node to join the cluster")
+        .arg(
+          Arg::new("expires-in")
+            .long("expires-in")
+            .help("Minutes until the token expires")
+            .value_parser(clap::value_parser!(i64))
+            .default_value("60"),
+        ),
+    )
+    .subcommand(Command::new("nodes").about("List cluster nodes and their health"))
+}
+
+pub fn list_nodes(config: &'static Config) {
+  let response = config
+    .cluster_api_client()
+    .expect("Client connection failed")
+    .get(format!("{}/cluster/nodes", config.api_base_url))
+    .bearer_auth(config.bearer_token())
+    .send()
+    .unwrap();
+  if response.status().is_success() {
+    let nodes = response.json::<Vec<ClusterNode>>().unwrap();
+
+    let headers = vec![
+      "ID",
+      "Type",
+      "Address",
+      "Status",
+      "CPU",
+      "Memory",
+      "Containers",
+      "Last heartbeat",
+    ];
+    let mut rows = vec![];
+    for node in nodes {
+      rows.push(vec![
+        node.id,
+        if node.leader {
+          format!("{} (leader)", node.node_type)
+        } else {
+          node.node_type
+        },
+        node.address,
+        node.status,
+        format!(
+          "{:.1}% of {}",
+          node.resources.cpu_usage, node.resources.cpu_count
+        ),
+        format!(
+          "{} / {} MiB",
+          node.resources.memory_used / 1024 / 1024,
+          node.resources.memory_total / 1024 / 1024
+        ),
+        node.resources.running_containers.to_string(),
+        format_time_ago(node.last_heartbeat),
+      ]);
+    }
+    print_table(headers, rows);
+  } else {
+    eprintln!("Failed to list cluster nodes: {}", response.status());
+  }
+}
+
+pub fn new_join_token(config: &'static Config, arg_matches: &ArgMatches) {
+  let expires_in = arg_matches
+    .get_one::<i64>("expires-in")
+    .expect("default value");
+  let response = config
+    .cluster_api_client()
+    .expect("Client connection failed")
+    .post(format!("{}/cluster/join-tokens", config.api_base_url))
+    .json(&json!({ "expires_in_minutes": expires_in }))
+    .bearer_auth(config.bearer_token())
+    .send()
+    .unwrap();
+  if response.status().is_success() {
+    let join_token = response.json::<JoinToken>().unwrap();
+    let primary_address = config
+      .api_base_url
+      .trim_start_matches("http://")
+      .trim_start_matches("https://");
+    println!(
+      "
+    Join token (expires {}):
+
+    {}
+
+    Start a new node with:
+
+    doseid --connect {}@{}
+    ",
+      join_token.expires_at, join_token.token, join_token.token, primary_address
+    );
+  } else {
+    eprintln!("Failed to create join token: {}", response.status());
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JoinToken {
+  token: String,
+  expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ClusterNode {
+  id: String,
+  node_type: String,
+  address: String,
+  leader: bool,
+  status: String,
+  last_heartbeat: DateTime<Utc>,
+  resources: NodeResources,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct NodeResources {
+  cpu_usage: f32,
+  cpu_count: u32,
+  memory_total: u64,
+  memory_used: u64,
+  running_containers: u32,
+}
@@ -1,6 +1,7 @@
 use crate::config::Config;
 use crate::util::write_tar_gz;
 use clap::Command;
+use serde::Deserialize;
 use std::env;
 use std::fs::create_dir_all;
 use std::time::Duration;
@@ -26,7 +27,7 @@ pub fn deploy(config: &'static Config) -> anyhow::Result<()> {
     .file("file", dst_path)
     .expect("failed");
 
-  config
+  let deployment = config
     .cluster_api_client()
     .expect("Client connection failed")
     .post(format!("{}/deploy", config.api_base_url))
@@ -34,7 +35,18 @@ pub fn deploy(config: &'static Config) -> anyhow::Result<()> {
     .timeout(Duration::from_secs(3600))
     .bearer_auth(config.bearer_token())
     .send()?
-    .error_for_status()?;
-  println!("Do deploy thing");
+    .error_for_status()?
+    .json::<Deployment>()?;
+  println!("Deployment {} is ready", deployment.id);
+  for url in deployment.urls {
+    println!("  {}", url);
+  }
   Ok(())
 }
+
+#[derive(Debug, Deserialize)]
+struct Deployment {
+  id: String,
+  #[serde(default)]
+  urls: Vec<String>,
+}
@@ -0,0 +1,117 @@
+use crate::config::Config;
+use crate::util::print_table;
+use clap::{Arg, ArgMatches, Command};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+pub fn sub_command() -> Command {
+  Command::new("domain")
+    .about("Domains commands")
+    .subcommand_required(true)
+    .subcommand(
+      Command::new("add")
+        .about("Add a domain to a project")
+        .arg(Arg::new("name").index(1).required(true))
+        .arg(
+          Arg::new("project")
+            .long("project")
+            .short('p')
+            .required(true)
+            .help("Name of the project the domain routes to"),
+        ),
+    )
+    .subcommand(Command::new("list").about("List domains"))
+    .subcommand(
+      Command::new("rm")
+        .about("Remove a domain")
+        .arg(Arg::new("id").index(1).required(true)),
+    )
+}
+
+pub fn add_domain(config: &'static Config, arg_matches: &ArgMatches) {
+  let name = arg_matches.get_one::<String>("name").expect("required");
+  let project = arg_matches.get_one::<String>("project").expect("required");
+  let response = config
+    .cluster_api_client()
+    .expect("Client connection failed")
+    .post(format!("{}/domains", config.api_base_url))
+    .json(&json!({"name": name, "project_name": project}))
+    .bearer_auth(config.bearer_token())
+    .send()
+    .unwrap();
+  if !response.status().is_success() {
+    eprintln!("Failed to add domain: {}", response.status());
+    return;
+  }
+  let domain = response.json::<Domain>().unwrap();
+  match domain.verification_record {
+    None => println!("Domain {} added and verified", domain.name),
+    Some(record) => {
+      println!(
+        "Domain {} added, add this DNS record to verify it:",
+        domain.name
+      );
+      print_table(
+        vec!["Type", "Name", "Value"],
+        vec![vec!["TXT".to_string(), record.name, record.value]],
+      );
+      println!("Its certificate is ordered as soon as the record is found.");
+    }
+  }
+}
+
+pub fn list_domains(config: &'static Config) {
+  let response = config
+    .cluster_api_client()
+    .expect("Client connection failed")
+    .get(format!("{}/domains", config.api_base_url))
+    .bearer_auth(config.bearer_token())
+    .send()
+    .unwrap();
+  if response.status().is_success() {
+    let domains = response.json::<Vec<Domain>>().unwrap();
+
+    let headers = vec!["ID", "Name", "Verified"];
+    let mut rows = vec![];
+    for domain in domains {
+      rows.push(vec![
+        domain.id,
+        domain.name,
+        if domain.verified { "yes" } else { "no" }.to_string(),
+      ]);
+    }
+    print_table(headers, rows);
+  } else {
+    eprintln!("Failed to list domains: {}", response.status());
+  }
+}
+
+pub fn remove_domain(config: &'static Config, arg_matches: &ArgMatches) {
+  let id = arg_matches.get_one::<String>("id").expect("required");
+  let response = config
+    .cluster_api_client()
+    .expect("Client connection failed")
+    .delete(format!("{}/domains/{}", config.api_base_url, id))
+    .bearer_auth(config.bearer_token())
+    .send()
+    .unwrap();
+  if response.status().is_success() {
+    println!("Domain {} removed", id);
+  } else {
+    eprintln!("Failed to remove domain: {}", response.status());
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Domain {
+  id: String,
+  name: String,
+  verified: bool,
+  verification_record: Option<VerificationRecord>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct VerificationRecord {
+  name: String,
+  value: String,
+}
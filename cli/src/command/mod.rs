@@ -2,7 +2,9 @@ use std::fs;
 use std::path::Path;
 
 pub(crate) mod certificate;
+pub(crate) mod cluster;
 pub(crate) mod deploy;
+pub(crate) mod domain;
 pub(crate) mod env;
 pub(crate) mod info;
 pub(crate) mod login;
@@ -32,10 +32,31 @@ pub fn run(arg_matches: &ArgMatches) {
           eprintln!("{:?}", err);
         };
       }
-      "js" | "mjs" | "cjs" | ".ts" | "tsx" => {
-        todo!()
+      "js" | "mjs" | "cjs" | "ts" | "tsx" => {
+        if function.is_some() {
+          eprintln!("Running a single function is only supported for Python apps.");
+          return;
+        }
+        // Node.js runs JavaScript as is, TypeScript goes through `tsx`.
+        let file_name = format!("dosei.{}", extension);
+        let mut command = if matches!(extension.as_str(), "ts" | "tsx") {
+          let mut command = std::process::Command::new("npx");
+          command.arg("tsx").arg(file_name);
+          command
+        } else {
+          let mut command = std::process::Command::new("node");
+          command.arg(file_name);
+          command
+        };
+        if let Err(err) = command
+          .stdout(Stdio::inherit())
+          .stderr(Stdio::inherit())
+          .output()
+        {
+          eprintln!("{:?}", err);
+        };
       }
-      _ => unreachable!(),
+      extension => eprintln!("Unsupported 'dosei.{}' file.", extension),
     },
     Err(e) => eprintln!("{}", e),
   }
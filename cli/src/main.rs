@@ -8,8 +8,10 @@ mod git;
 mod test;
 mod util;
 
-use crate::command::certificate::new_certificate;
+use crate::command::certificate::{list_certificates, new_certificate, remove_certificate};
+use crate::command::cluster::{list_nodes, new_join_token};
 use crate::command::deploy::deploy;
+use crate::command::domain::{add_domain, list_domains, remove_domain};
 use crate::command::login::login;
 use crate::command::logout::logout;
 use crate::command::new::new;
@@ -17,7 +19,7 @@ use crate::command::run::run;
 use crate::command::service::list_services;
 use crate::command::session::session;
 use crate::command::token::list_token;
-use crate::command::{certificate, deploy, env, info, new, run, service, token};
+use crate::command::{certificate, deploy, domain, env, info, new, run, service, token};
 use crate::config::{Config, VERSION};
 use clap::Command;
 
@@ -37,6 +39,8 @@ fn cli() -> Command {
     .subcommand(Command::new("info").about("Print cluster information."))
     .subcommand(token::sub_command())
     .subcommand(certificate::sub_command())
+    .subcommand(domain::sub_command())
+    .subcommand(command::cluster::sub_command())
 }
 
 fn main() -> anyhow::Result<()> {
@@ -60,6 +64,19 @@ fn main() -> anyhow::Result<()> {
     },
     Some(("certificate", params)) => match params.subcommand() {
       Some(("new", arg_matches)) => new_certificate(config, arg_matches),
+      Some(("list", _)) => list_certificates(config),
+      Some(("rm", arg_matches)) => remove_certificate(config, arg_matches),
+      _ => unreachable!(),
+    },
+    Some(("domain", params)) => match params.subcommand() {
+      Some(("add", arg_matches)) => add_domain(config, arg_matches),
+      Some(("list", _)) => list_domains(config),
+      Some(("rm", arg_matches)) => remove_domain(config, arg_matches),
+      _ => unreachable!(),
+    },
+    Some(("cluster", params)) => match params.subcommand() {
+      Some(("join-token", arg_matches)) => new_join_token(config, arg_matches),
+      Some(("nodes", _)) => list_nodes(config),
       _ => unreachable!(),
     },
     Some(("token", params)) => match params.subcommand() {
@@ -1,4 +1,5 @@
 use anyhow::Result;
+use chrono::{DateTime, Utc};
 use flate2::write::GzEncoder;
 use flate2::Compression;
 use ignore::WalkBuilder;
@@ -35,3 +36,44 @@ pub fn write_tar_gz(input_path: &Path, output_path: &Path) -> Result<()> {
   tar.into_inner()?.finish()?;
   Ok(())
 }
+
+pub fn print_table(headers: Vec<&str>, rows: Vec<Vec<String>>) {
+  let num_columns = headers.len();
+  let mut column_widths = vec![0; num_columns];
+  for (i, header) in headers.iter().enumerate() {
+    column_widths[i] = header.len();
+  }
+  for row in &rows {
+    for (i, item) in row.iter().enumerate() {
+      column_widths[i] = column_widths[i].max(item.len());
+    }
+  }
+  for (i, header) in headers.iter().enumerate() {
+    print!("{:<width$}   ", header, width = column_widths[i]);
+  }
+  println!();
+
+  for row in rows {
+    for (i, item) in row.iter().enumerate() {
+      print!("{:<width$}   ", item, width = column_widths[i]);
+    }
+    println!();
+  }
+}
+
+pub fn format_time_ago(from_datetime: DateTime<Utc>) -> String {
+  let now = Utc::now();
+  let duration = now.signed_duration_since(from_datetime);
+
+  if duration.num_days() >= 1 {
+    format!("{}d", duration.num_days())
+  } else if duration.num_hours() >= 1 {
+    format!("{}h", duration.num_hours())
+  } else if duration.num_minutes() >= 1 {
+    format!("{}m", duration.num_minutes())
+  } else if duration.num_seconds() >= 1 {
+    format!("{}s", duration.num_seconds())
+  } else {
+    "just now".to_string()
+  }
+}
@@ -0,0 +1,22 @@
+CREATE TABLE IF NOT EXISTS cluster_certificate_authority (
+    id UUID NOT NULL,
+    certificate TEXT NOT NULL,
+    private_key TEXT NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE TABLE IF NOT EXISTS cluster_join_token (
+    id UUID NOT NULL,
+    --- SHA-256 of the token, the token itself is only shown once
+    token_hash TEXT NOT NULL,
+    owner_id UUID NOT NULL,
+    used_by UUID,
+    used_at TIMESTAMP WITH TIME ZONE,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    PRIMARY KEY (id),
+    UNIQUE (token_hash)
+);
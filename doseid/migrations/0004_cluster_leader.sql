@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS cluster_leader (
+    id INT DEFAULT 1 NOT NULL CHECK (id = 1),
+    node_id UUID NOT NULL,
+    address TEXT NOT NULL,
+    node_address TEXT NOT NULL,
+    term BIGINT NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    PRIMARY KEY (id)
+);
@@ -0,0 +1,18 @@
+DO $$
+    BEGIN
+        IF NOT EXISTS (SELECT 1 FROM pg_type WHERE typname = 'event_type') THEN
+            CREATE TYPE event_type AS ENUM ('certificate_issued', 'certificate_failed');
+        END IF;
+    END
+$$;
+
+CREATE TABLE IF NOT EXISTS event (
+    id UUID NOT NULL,
+    event_type event_type NOT NULL,
+    message TEXT NOT NULL,
+    owner_id UUID NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX IF NOT EXISTS event_owner_id_created_at ON event (owner_id, created_at DESC);
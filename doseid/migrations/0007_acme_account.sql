@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS acme_account (
+    id UUID NOT NULL,
+    owner_id UUID NOT NULL,
+    directory_url TEXT NOT NULL,
+    --- Serialized instant_acme::AccountCredentials, including the account private key
+    credentials jsonb NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    PRIMARY KEY (id),
+    UNIQUE (owner_id, directory_url)
+);
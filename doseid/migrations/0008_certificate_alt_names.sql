@@ -0,0 +1 @@
+ALTER TABLE certificate ADD COLUMN IF NOT EXISTS alt_names TEXT[] DEFAULT '{}' NOT NULL;
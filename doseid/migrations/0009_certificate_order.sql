@@ -0,0 +1,19 @@
+DO $$
+    BEGIN
+        IF NOT EXISTS (SELECT 1 FROM pg_type WHERE typname = 'certificate_order_status') THEN
+            CREATE TYPE certificate_order_status AS ENUM ('pending_dns', 'challenge_served', 'validating', 'issued', 'failed');
+        END IF;
+    END
+$$;
+
+CREATE TABLE IF NOT EXISTS certificate_order (
+    id UUID NOT NULL,
+    domain_names TEXT[] NOT NULL,
+    status certificate_order_status NOT NULL,
+    failure_reason TEXT,
+    certificate_id UUID,
+    owner_id UUID NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    PRIMARY KEY (id)
+);
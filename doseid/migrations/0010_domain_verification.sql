@@ -0,0 +1,4 @@
+ALTER TABLE domain ADD COLUMN IF NOT EXISTS verification_token TEXT DEFAULT md5(random()::text) NOT NULL;
+--- Domains only route once their ownership is verified, existing ones are trusted
+ALTER TABLE domain ADD COLUMN IF NOT EXISTS verified_at TIMESTAMP WITH TIME ZONE;
+UPDATE domain SET verified_at = created_at WHERE verified_at IS NULL;
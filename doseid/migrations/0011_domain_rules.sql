@@ -0,0 +1,6 @@
+--- Token bucket per client IP, refilled at rate_limit_per_second up to rate_limit_burst
+ALTER TABLE domain ADD COLUMN IF NOT EXISTS rate_limit_per_second INTEGER;
+ALTER TABLE domain ADD COLUMN IF NOT EXISTS rate_limit_burst INTEGER;
+--- CIDR blocks, e.g. 10.0.0.0/8, denied ones taking precedence over allowed ones
+ALTER TABLE domain ADD COLUMN IF NOT EXISTS allowed_ips TEXT[] DEFAULT '{}' NOT NULL;
+ALTER TABLE domain ADD COLUMN IF NOT EXISTS denied_ips TEXT[] DEFAULT '{}' NOT NULL;
@@ -0,0 +1,2 @@
+--- Whether the proxy forwards requests to the deployment over HTTP/2 without TLS (h2c)
+ALTER TABLE domain ADD COLUMN IF NOT EXISTS upstream_http2 BOOLEAN DEFAULT false NOT NULL;
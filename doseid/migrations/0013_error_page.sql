@@ -0,0 +1,9 @@
+--- Pages the proxy responds with instead of its own, by owner and status code
+CREATE TABLE IF NOT EXISTS error_page (
+    owner_id UUID NOT NULL,
+    status_code SMALLINT NOT NULL,
+    content TEXT NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    PRIMARY KEY (owner_id, status_code)
+);
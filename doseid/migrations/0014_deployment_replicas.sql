@@ -0,0 +1,33 @@
+DO $$
+    BEGIN
+        IF NOT EXISTS (SELECT 1 FROM pg_type WHERE typname = 'load_balancing') THEN
+            CREATE TYPE load_balancing AS ENUM ('round_robin', 'least_connections');
+        END IF;
+    END
+$$;
+
+--- Containers started for each deployment of the project, and how requests are spread across them
+ALTER TABLE project ADD COLUMN IF NOT EXISTS replicas SMALLINT DEFAULT 1 NOT NULL;
+ALTER TABLE project ADD COLUMN IF NOT EXISTS load_balancing load_balancing DEFAULT 'round_robin' NOT NULL;
+
+CREATE TABLE IF NOT EXISTS deployment_instance (
+    id UUID NOT NULL,
+    deployment_id UUID NOT NULL,
+    node_id UUID,
+    exposed_host TEXT,
+    exposed_port SMALLINT NOT NULL,
+    --- Whether the instance accepted connections on its last health checks
+    healthy BOOLEAN DEFAULT true NOT NULL,
+    checked_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX IF NOT EXISTS deployment_instance_deployment_id ON deployment_instance (deployment_id);
+
+--- Deployments started before replicas ran a single container
+INSERT INTO deployment_instance (id, deployment_id, node_id, exposed_host, exposed_port, created_at)
+SELECT md5(random()::text || d.id::text)::uuid, d.id, d.node_id, d.exposed_host, d.exposed_port, d.created_at
+FROM deployment d
+WHERE d.exposed_port IS NOT NULL
+  AND NOT EXISTS (SELECT 1 FROM deployment_instance i WHERE i.deployment_id = d.id);
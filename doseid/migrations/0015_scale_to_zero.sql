@@ -0,0 +1,6 @@
+--- Seconds without requests after which the containers of the project are stopped, never when null
+ALTER TABLE project ADD COLUMN IF NOT EXISTS idle_timeout_seconds INTEGER;
+--- Last time a proxy forwarded a request to the deployment, reported at most once a minute
+ALTER TABLE deployment ADD COLUMN IF NOT EXISTS last_request_at TIMESTAMP WITH TIME ZONE;
+ALTER TABLE deployment_instance ADD COLUMN IF NOT EXISTS container_id TEXT;
+ALTER TABLE deployment_instance ADD COLUMN IF NOT EXISTS stopped_at TIMESTAMP WITH TIME ZONE;
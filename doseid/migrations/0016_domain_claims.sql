@@ -0,0 +1,4 @@
+--- Unverified domains are claims, only a verified domain owns its name
+ALTER TABLE domain DROP CONSTRAINT IF EXISTS domain_name_key;
+CREATE UNIQUE INDEX IF NOT EXISTS domain_name_verified_idx ON domain (name) WHERE verified_at IS NOT NULL;
+CREATE UNIQUE INDEX IF NOT EXISTS domain_owner_id_name_idx ON domain (owner_id, name);
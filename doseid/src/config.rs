@@ -1,9 +1,14 @@
 use crate::server::integration::github::GithubIntegration;
 use anyhow::Context;
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
 use clap::Parser;
 use dosei_proto::ping::NodeType;
+use dosei_proxy_core::client_ip::parse_trusted_proxy;
 use dotenv::dotenv;
 use home::home_dir;
+use instant_acme::{LetsEncrypt, ZeroSsl};
+use ipnet::IpNet;
 use rand::distributions::Alphanumeric;
 use rand::Rng;
 use reqwest::header::CONTENT_TYPE;
@@ -14,7 +19,9 @@ use std::fmt::Formatter;
 use std::fs::{create_dir_all, File};
 use std::io::Read;
 use std::io::Write;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
 use std::path::PathBuf;
+use std::time::Duration;
 use std::{env, fmt, fs, write};
 use tracing::warn;
 use uuid::Uuid;
@@ -26,6 +33,8 @@ const DEFAULT_CONFIG_PATH: &str = ".dosei/doseid/data/doseid.toml";
 
 pub const DEPLOYMENT_LOG_PATH: &str = ".dosei/doseid/data/deployments/logs";
 const TELEMETRY_ID_PATH: &str = ".dosei/doseid/data/id";
+pub const CLUSTER_DATA_PATH: &str = ".dosei/doseid/data/cluster";
+const NODE_ID_PATH: &str = ".dosei/doseid/data/cluster/node_id";
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None, disable_help_flag = true)]
@@ -34,8 +43,17 @@ struct Args {
   host: String,
   #[arg(short, long, default_value = "8844", help = "The port number to use.")]
   port: u16,
-  #[arg(short, long, help = "Primary cluster node's address to connect to.")]
+  #[arg(
+    short,
+    long,
+    help = "Primary cluster node's address to connect to, as <join-token>@<host>:<port>."
+  )]
   connect: Option<String>,
+  #[arg(
+    long,
+    help = "The host other nodes reach this one on, when it differs from the host address."
+  )]
+  advertise_host: Option<String>,
   #[arg(long, hide = true, action = clap::ArgAction::SetTrue)]
   disable_telemetry: Option<bool>,
   #[arg(long, action = clap::ArgAction::Help, help = "Print help")]
@@ -46,14 +64,24 @@ struct Args {
 
 pub struct Config {
   pub address: Address,
+  /// Host other nodes reach this one on, as the address is the one it binds to.
+  pub advertise_host: String,
   pub node_info: NodeInfo,
   pub primary_address: Option<String>,
+  pub join_token: Option<String>,
   pub database_url: String,
   pub jwt_secret: String,
   pub container_registry_url: String,
   pub telemetry: Telemetry,
   pub github_integration: Option<GithubIntegration>,
   pub console: bool,
+  pub cluster: ClusterConfig,
+  pub proxy: Option<ProxyConfig>,
+  pub acme: AcmeConfig,
+  /// Domain every deployment gets a preview subdomain of, e.g. `apps.example.com`.
+  pub base_domain: Option<String>,
+  /// Shared with Dosei Proxy instances, which authenticate with it to wake idle deployments.
+  pub proxy_token: Option<String>,
 }
 
 impl Config {
@@ -90,6 +118,10 @@ impl Config {
     }
     let mut console = false;
     let mut github_integration = None;
+    let mut cluster = ClusterConfig::from(ClusterTOML::default());
+    let mut proxy = None;
+    let mut acme = AcmeConfig::try_from(AcmeTOML::default())?;
+    let mut base_domain = None;
     // So ugly, wtf, but right now it works
     if cfg!(test) {
       github_integration = Some(GithubIntegration::new()?);
@@ -98,16 +130,55 @@ impl Config {
         github_integration = Some(GithubIntegration::new()?);
       }
       console = toml_config.console.enabled;
+      cluster = ClusterConfig::from(toml_config.cluster);
+      acme = AcmeConfig::try_from(toml_config.acme)?;
+      base_domain = toml_config
+        .proxy
+        .base_domain
+        .as_deref()
+        .map(base_domain_name);
+      if toml_config.proxy.enabled {
+        proxy = Some(ProxyConfig {
+          address: Address {
+            host: args.host.clone(),
+            port: toml_config.proxy.port,
+          },
+          trusted_proxies: toml_config
+            .proxy
+            .trusted_proxies
+            .iter()
+            .map(|proxy| {
+              parse_trusted_proxy(proxy)
+                .ok_or_else(|| anyhow::Error::msg(format!("Invalid trusted proxy {}", proxy)))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?,
+        });
+      }
+    };
+
+    // The join token is only needed the first time a replica joins the cluster.
+    let (join_token, primary_address) = match args.connect {
+      Some(connect) => match connect.split_once('@') {
+        Some((join_token, address)) => (Some(join_token.to_string()), Some(address.to_string())),
+        None => (None, Some(connect)),
+      },
+      None => (None, None),
     };
 
+    let advertise_host = args
+      .advertise_host
+      .or_else(|| env::var("ADVERTISE_HOST").ok())
+      .filter(|host| !host.is_empty())
+      .unwrap_or_else(|| advertise_host(&args.host));
     Ok(Config {
       address: Address {
         host: args.host.clone(),
         port: args.port,
       },
+      advertise_host,
       node_info: NodeInfo {
-        id: Uuid::new_v4(),
-        node_type: if args.connect.is_some() {
+        id: node_id(),
+        node_type: if primary_address.is_some() {
           NodeType::Replica
         } else {
           NodeType::Primary
@@ -117,7 +188,8 @@ impl Config {
           port: args.port + 10000,
         },
       },
-      primary_address: args.connect,
+      primary_address,
+      join_token,
       database_url: env::var("DATABASE_URL").context("DATABASE_URL is required.")?,
       jwt_secret: env::var("JWT_SECRET").unwrap_or_else(|_| {
         let random_id: String = rand::thread_rng()
@@ -144,15 +216,19 @@ impl Config {
         .build(),
       github_integration,
       console,
+      cluster,
+      proxy,
+      acme,
+      base_domain,
+      proxy_token: env::var("PROXY_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty()),
     })
   }
 
   pub fn is_primary(&self) -> bool {
     self.node_info.node_type == NodeType::Primary
   }
-  pub fn is_replica(&self) -> bool {
-    self.node_info.node_type == NodeType::Replica
-  }
   pub fn get_primary_node_address(&self) -> Address {
     if let Some(primary_addr) = self.get_primary_address() {
       Address {
@@ -164,18 +240,69 @@ impl Config {
     }
   }
 
-  fn get_primary_address(&self) -> Option<Address> {
-    self.primary_address.as_ref().and_then(|addr| {
-      let parts: Vec<&str> = addr.split(':').collect();
-      if parts.len() == 2 {
-        let host = parts[0].to_string();
-        if let Ok(port) = parts[1].parse::<u16>() {
-          return Some(Address { host, port });
-        }
-      }
-      None
-    })
+  pub fn get_primary_address(&self) -> Option<Address> {
+    self.primary_address.as_deref().and_then(Address::parse)
+  }
+
+  /// The API address other nodes forward requests to.
+  pub fn advertised_address(&self) -> Address {
+    Address {
+      host: self.advertise_host.clone(),
+      port: self.address.port,
+    }
+  }
+
+  /// The host the containers of this node are published on, as reached from other nodes.
+  pub fn exposed_host(&self) -> String {
+    match self.address.host.parse::<IpAddr>() {
+      Ok(ip) if ip.is_unspecified() => self.advertise_host.clone(),
+      _ => self.address.host.clone(),
+    }
   }
+
+  /// The cluster address other nodes connect to.
+  pub fn advertised_node_address(&self) -> Address {
+    Address {
+      host: self.advertise_host.clone(),
+      port: self.node_info.address.port,
+    }
+  }
+}
+
+/// The host to advertise for a bind host. Binding every interface, e.g. `0.0.0.0`, is reachable
+/// on the IP of the interface routing outbound traffic, any other host is advertised as is.
+pub(crate) fn advertise_host(bind_host: &str) -> String {
+  match bind_host.parse::<IpAddr>() {
+    Ok(ip) if ip.is_unspecified() => outbound_ip()
+      .unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST))
+      .to_string(),
+    _ => bind_host.to_string(),
+  }
+}
+
+/// Connecting a UDP socket sends nothing, it only picks the local IP routing to the address.
+fn outbound_ip() -> Option<IpAddr> {
+  let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+  socket.connect("8.8.8.8:80").ok()?;
+  Some(socket.local_addr().ok()?.ip()).filter(|ip| !ip.is_unspecified())
+}
+
+/// Returns the persisted id of this node, so it keeps its identity across restarts.
+fn node_id() -> Uuid {
+  let mut path = home_dir().unwrap_or_else(|| PathBuf::from("/tmp"));
+  path.push(NODE_ID_PATH);
+  if let Some(id) = fs::read_to_string(&path)
+    .ok()
+    .and_then(|content| Uuid::parse_str(content.trim()).ok())
+  {
+    return id;
+  }
+  let id = Uuid::new_v4();
+  if let Some(dir) = path.parent() {
+    let _ = create_dir_all(dir);
+  }
+  let _ = fs::write(&path, id.to_string());
+  id
 }
 
 #[derive(Debug, Clone)]
@@ -185,12 +312,159 @@ pub struct NodeInfo {
   pub address: Address,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Heartbeat timeouts used to track the health of replica nodes, and the labels of this node
+/// matched against deployments when placing them.
+#[derive(Debug, Clone)]
+pub struct ClusterConfig {
+  pub suspect_timeout: Duration,
+  pub dead_timeout: Duration,
+  pub eviction_timeout: Duration,
+  pub labels: HashMap<String, String>,
+  /// Users allowed to manage the cluster itself, e.g. to let new nodes join it.
+  pub admins: Vec<Uuid>,
+}
+
+impl ClusterConfig {
+  pub fn is_admin(&self, owner_id: Uuid) -> bool {
+    self.admins.contains(&owner_id)
+  }
+}
+
+impl From<ClusterTOML> for ClusterConfig {
+  fn from(cluster: ClusterTOML) -> Self {
+    ClusterConfig {
+      suspect_timeout: Duration::from_secs(cluster.suspect_timeout),
+      dead_timeout: Duration::from_secs(cluster.dead_timeout),
+      eviction_timeout: Duration::from_secs(cluster.eviction_timeout),
+      labels: cluster.labels,
+      admins: cluster.admins,
+    }
+  }
+}
+
+/// The built-in reverse proxy routing domains to deployments.
+#[derive(Debug, Clone)]
+pub struct ProxyConfig {
+  pub address: Address,
+  /// Proxies in front, whose `X-Forwarded-For` tells the client requests come from.
+  pub trusted_proxies: Vec<IpNet>,
+}
+
+/// Accepts the base domain as a wildcard too, e.g. `*.apps.example.com`.
+fn base_domain_name(base_domain: &str) -> String {
+  base_domain
+    .trim()
+    .trim_start_matches("*.")
+    .trim_end_matches('.')
+    .to_lowercase()
+}
+
+/// The ACME server certificates are ordered from.
+#[derive(Debug, Clone)]
+pub struct AcmeConfig {
+  pub directory_url: String,
+  pub external_account: Option<ExternalAccount>,
+  /// Publishes DNS-01 challenges, required for wildcard certificates.
+  pub rfc2136: Option<Rfc2136Config>,
+}
+
+/// External account binding, required by CAs such as ZeroSSL to create an account.
+#[derive(Debug, Clone)]
+pub struct ExternalAccount {
+  pub key_id: String,
+  pub hmac_key: Vec<u8>,
+}
+
+/// Name server accepting dynamic updates for the zone DNS-01 challenges are published on.
+#[derive(Debug, Clone)]
+pub struct Rfc2136Config {
+  pub nameserver: SocketAddr,
+  pub zone: String,
+  pub tsig_key: Option<TsigKey>,
+  /// How long to wait for a published record to reach every authoritative name server.
+  pub propagation_delay: Duration,
+}
+
+/// HMAC-SHA256 key signing dynamic updates, e.g. as generated by `tsig-keygen`.
+#[derive(Debug, Clone)]
+pub struct TsigKey {
+  pub name: String,
+  pub secret: Vec<u8>,
+}
+
+impl TryFrom<AcmeTOML> for AcmeConfig {
+  type Error = anyhow::Error;
+
+  fn try_from(acme: AcmeTOML) -> Result<Self, Self::Error> {
+    let directory_url = match acme.directory_url.as_str() {
+      "letsencrypt" => LetsEncrypt::Production.url().to_string(),
+      "letsencrypt-staging" => LetsEncrypt::Staging.url().to_string(),
+      "zerossl" => ZeroSsl::Production.url().to_string(),
+      url => url.to_string(),
+    };
+    let external_account = match (acme.eab_key_id, acme.eab_hmac_key) {
+      (Some(key_id), Some(hmac_key)) => Some(ExternalAccount {
+        key_id,
+        hmac_key: URL_SAFE_NO_PAD
+          .decode(hmac_key.trim_end_matches('='))
+          .context("acme.eab_hmac_key must be base64url encoded.")?,
+      }),
+      (None, None) => None,
+      _ => {
+        return Err(anyhow::Error::msg(
+          "acme.eab_key_id and acme.eab_hmac_key must be set together.",
+        ))
+      }
+    };
+    let rfc2136 = match acme.rfc2136 {
+      None => None,
+      Some(rfc2136) => Some(Rfc2136Config {
+        nameserver: rfc2136
+          .nameserver
+          .parse()
+          .context("acme.rfc2136.nameserver must be an <ip>:<port> address.")?,
+        zone: rfc2136.zone,
+        tsig_key: match (rfc2136.tsig_key_name, rfc2136.tsig_secret) {
+          (Some(name), Some(secret)) => Some(TsigKey {
+            name,
+            secret: STANDARD
+              .decode(secret)
+              .context("acme.rfc2136.tsig_secret must be base64 encoded.")?,
+          }),
+          (None, None) => None,
+          _ => {
+            return Err(anyhow::Error::msg(
+              "acme.rfc2136.tsig_key_name and acme.rfc2136.tsig_secret must be set together.",
+            ))
+          }
+        },
+        propagation_delay: Duration::from_secs(rfc2136.propagation_delay),
+      }),
+    };
+    Ok(AcmeConfig {
+      directory_url,
+      external_account,
+      rfc2136,
+    })
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Address {
   pub host: String,
   pub port: u16,
 }
 
+impl Address {
+  pub fn parse(address: &str) -> Option<Address> {
+    let (host, port) = address.rsplit_once(':')?;
+    Some(Address {
+      host: host.to_string(),
+      port: port.parse::<u16>().ok()?,
+    })
+  }
+}
+
 impl fmt::Display for Address {
   fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
     write!(f, "{}:{}", self.host, self.port)
@@ -288,6 +562,12 @@ impl PostHogClient {
 pub struct TOMLConfig {
   console: ConsoleTOML,
   github: GithubTOML,
+  #[serde(default)]
+  cluster: ClusterTOML,
+  #[serde(default)]
+  proxy: ProxyTOML,
+  #[serde(default)]
+  acme: AcmeTOML,
 }
 
 #[derive(Deserialize)]
@@ -305,6 +585,82 @@ pub struct GithubUnstableTOML {
   enabled: bool,
 }
 
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct ClusterTOML {
+  suspect_timeout: u64,
+  dead_timeout: u64,
+  eviction_timeout: u64,
+  labels: HashMap<String, String>,
+  admins: Vec<Uuid>,
+}
+
+impl Default for ClusterTOML {
+  fn default() -> Self {
+    ClusterTOML {
+      suspect_timeout: 5,
+      dead_timeout: 30,
+      eviction_timeout: 300,
+      labels: HashMap::new(),
+      admins: vec![],
+    }
+  }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct ProxyTOML {
+  enabled: bool,
+  port: u16,
+  base_domain: Option<String>,
+  trusted_proxies: Vec<String>,
+}
+
+impl Default for ProxyTOML {
+  fn default() -> Self {
+    ProxyTOML {
+      enabled: false,
+      port: 80,
+      base_domain: None,
+      trusted_proxies: Vec::new(),
+    }
+  }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct AcmeTOML {
+  directory_url: String,
+  eab_key_id: Option<String>,
+  eab_hmac_key: Option<String>,
+  rfc2136: Option<Rfc2136TOML>,
+}
+
+#[derive(Deserialize)]
+pub struct Rfc2136TOML {
+  nameserver: String,
+  zone: String,
+  tsig_key_name: Option<String>,
+  tsig_secret: Option<String>,
+  #[serde(default = "default_propagation_delay")]
+  propagation_delay: u64,
+}
+
+fn default_propagation_delay() -> u64 {
+  10
+}
+
+impl Default for AcmeTOML {
+  fn default() -> Self {
+    AcmeTOML {
+      directory_url: "letsencrypt".to_string(),
+      eab_key_id: None,
+      eab_hmac_key: None,
+      rfc2136: None,
+    }
+  }
+}
+
 impl TOMLConfig {
   pub fn new(config: Option<String>) -> anyhow::Result<TOMLConfig> {
     let filename = match config {
@@ -318,3 +674,55 @@ impl TOMLConfig {
     Ok(data)
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use crate::config::{advertise_host, AcmeConfig, AcmeTOML};
+  use crate::test;
+
+  #[test]
+  fn test_advertise_host() {
+    assert_ne!(advertise_host("0.0.0.0"), "0.0.0.0");
+    assert_eq!(advertise_host("10.0.0.2"), "10.0.0.2");
+    assert_eq!(advertise_host("node.local"), "node.local");
+  }
+
+  #[test]
+  fn test_exposed_host() {
+    let mut config = test::config();
+    config.advertise_host = "10.0.0.2".to_string();
+    assert_eq!(config.exposed_host(), "127.0.0.1");
+    config.address.host = "0.0.0.0".to_string();
+    assert_eq!(config.exposed_host(), "10.0.0.2");
+  }
+
+  #[test]
+  fn test_acme_config() {
+    let acme = AcmeConfig::try_from(AcmeTOML {
+      directory_url: "letsencrypt-staging".to_string(),
+      ..AcmeTOML::default()
+    })
+    .unwrap();
+    assert_eq!(
+      acme.directory_url,
+      "https://acme-staging-v02.api.letsencrypt.org/directory"
+    );
+    assert!(acme.external_account.is_none());
+
+    let acme = AcmeConfig::try_from(AcmeTOML {
+      directory_url: "https://localhost:14000/dir".to_string(),
+      eab_key_id: Some("kid".to_string()),
+      eab_hmac_key: Some("c2VjcmV0".to_string()),
+      rfc2136: None,
+    })
+    .unwrap();
+    assert_eq!(acme.directory_url, "https://localhost:14000/dir");
+    assert_eq!(acme.external_account.unwrap().hmac_key, b"secret");
+
+    let result = AcmeConfig::try_from(AcmeTOML {
+      eab_key_id: Some("kid".to_string()),
+      ..AcmeTOML::default()
+    });
+    assert!(result.is_err());
+  }
+}
@@ -4,6 +4,7 @@ use bollard::Docker;
 use dosei_util::Framework;
 use futures_util::StreamExt;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::Path;
 use tracing::info;
 
@@ -72,6 +73,9 @@ pub struct DoseiApp {
   pub run: String,
   pub port: u16,
   pub cron_jobs: Vec<CronJob>,
+  /// Labels a node must have for the app to be placed on it.
+  #[serde(default)]
+  pub node_labels: HashMap<String, String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -1,6 +1,7 @@
 pub(crate) mod app;
 
 use crate::docker::build_image;
+use anyhow::anyhow;
 use home::home_dir;
 use std::path::{Path, PathBuf};
 use tempfile::tempdir;
@@ -50,14 +51,25 @@ pub async fn build_from_github(
   drop(temp_dir);
 }
 
+/// Writes a `Dockerfile` for the detected package manager and framework, unless the project
+/// brings its own. Returns whether one was generated.
+pub async fn ensure_dockerfile(folder_path: &Path) -> anyhow::Result<bool> {
+  if dosei_util::package_manager::_resolve_docker(folder_path) {
+    info!("Detected `Dockerfile`");
+    return Ok(false);
+  }
+  let dockerfile = dosei_util::dockerfile::_generate_dockerfile(folder_path)
+    .map_err(|err| anyhow!("Failed to detect `Dockerfile`: {}", err))?;
+  tokio::fs::write(folder_path.join("Dockerfile"), dockerfile).await?;
+  info!("Generated `Dockerfile`");
+  Ok(true)
+}
+
 async fn build(owner_id: Uuid, project_id: Uuid, deployment_id: String, folder_path: &Path) {
-  let detected_docker_file = dosei_util::package_manager::_resolve_docker(folder_path);
-  if !detected_docker_file {
-    // TODO: Implement docker file templates.
-    error!("Failed to detect `Dockerfile`");
+  if let Err(err) = ensure_dockerfile(folder_path).await {
+    error!("{}", err);
     return;
   }
-  info!("Detected `Dockerfile`");
   let image_tag = format!("{}/{}:{}", owner_id, project_id, deployment_id);
   build_image(&image_tag, folder_path).await;
 }
@@ -2,7 +2,7 @@ pub(crate) mod credentials;
 pub(crate) mod event;
 
 use bollard::auth::DockerCredentials;
-use bollard::image::{BuildImageOptions, PushImageOptions};
+use bollard::image::{BuildImageOptions, CreateImageOptions, PushImageOptions, TagImageOptions};
 use bollard::Docker;
 
 use crate::util::{read_tar_gz_content, write_tar_gz};
@@ -70,8 +70,12 @@ pub async fn build_image_raw(image_tag: &str, tar: &[u8]) -> anyhow::Result<Vec<
   Ok(logs)
 }
 
-pub async fn push_image(name: &str, tag: &str, docker_credentials: DockerCredentials) {
-  let docker = Docker::connect_with_socket_defaults().unwrap();
+pub async fn push_image(
+  name: &str,
+  tag: &str,
+  docker_credentials: DockerCredentials,
+) -> anyhow::Result<()> {
+  let docker = Docker::connect_with_socket_defaults()?;
   let mut stream = docker.push_image(
     name,
     Some(PushImageOptions { tag }),
@@ -82,8 +86,38 @@ pub async fn push_image(name: &str, tag: &str, docker_credentials: DockerCredent
       Ok(output) => info!("{:?}", output),
       Err(e) => {
         error!("Push error: {:?}", e);
-        break;
+        return Err(e.into());
       }
     }
   }
+  Ok(())
+}
+
+pub async fn tag_image(source: &str, name: &str, tag: &str) -> anyhow::Result<()> {
+  let docker = Docker::connect_with_socket_defaults()?;
+  docker
+    .tag_image(source, Some(TagImageOptions { repo: name, tag }))
+    .await?;
+  Ok(())
+}
+
+pub async fn pull_image(
+  name: &str,
+  tag: &str,
+  docker_credentials: DockerCredentials,
+) -> anyhow::Result<()> {
+  let docker = Docker::connect_with_socket_defaults()?;
+  let options = Some(CreateImageOptions {
+    from_image: name,
+    tag,
+    ..Default::default()
+  });
+  let mut stream = docker.create_image(options, None, Some(docker_credentials));
+  while let Some(result) = stream.next().await {
+    if let Err(e) = result {
+      error!("Error occurred while downloading image: {}", e);
+      return Err(e.into());
+    }
+  }
+  Ok(())
 }
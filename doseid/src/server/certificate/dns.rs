@@ -0,0 +1,175 @@
+use crate::config::{AcmeConfig, Rfc2136Config};
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use trust_dns_client::client::{AsyncClient, ClientHandle};
+use trust_dns_client::op::ResponseCode;
+use trust_dns_client::proto::rr::dnssec::rdata::tsig::TsigAlgorithm;
+use trust_dns_client::proto::rr::dnssec::tsig::TSigner;
+use trust_dns_client::rr::rdata::TXT;
+use trust_dns_client::rr::{Name, RData, Record};
+use trust_dns_client::udp::UdpClientStream;
+
+const TXT_RECORD_TTL: u32 = 60;
+const UPDATE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Allowed clock difference with the name server when signing updates.
+const TSIG_FUDGE: u16 = 300;
+
+/// Publishes the TXT records answering DNS-01 challenges.
+#[async_trait]
+pub trait DnsProvider: Send + Sync {
+  /// Whether records of this name can be published, i.e. it belongs to a managed zone.
+  fn manages(&self, name: &str) -> bool;
+  /// How long to wait before a published record is visible to the CA.
+  fn propagation_delay(&self) -> Duration;
+  async fn set_txt_record(&self, name: &str, value: &str) -> anyhow::Result<()>;
+  async fn remove_txt_record(&self, name: &str, value: &str) -> anyhow::Result<()>;
+}
+
+/// The DNS provider configured for DNS-01 challenges, if any.
+pub fn dns_provider(acme: &AcmeConfig) -> anyhow::Result<Option<Arc<dyn DnsProvider>>> {
+  Ok(match &acme.rfc2136 {
+    Some(rfc2136) => Some(Arc::new(Rfc2136Provider::new(rfc2136)?)),
+    None => None,
+  })
+}
+
+/// The name the TXT record of a DNS-01 challenge is looked up on, shared by wildcards and their
+/// base domain.
+pub fn challenge_record_name(domain_name: &str) -> String {
+  format!(
+    "_acme-challenge.{}",
+    domain_name.strip_prefix("*.").unwrap_or(domain_name)
+  )
+}
+
+/// Dynamic DNS updates (RFC 2136) sent to the primary name server of a zone, signed with TSIG
+/// when a key is configured.
+pub struct Rfc2136Provider {
+  nameserver: std::net::SocketAddr,
+  zone: Name,
+  signer: Option<Arc<TSigner>>,
+  propagation_delay: Duration,
+}
+
+impl Rfc2136Provider {
+  pub fn new(config: &Rfc2136Config) -> anyhow::Result<Rfc2136Provider> {
+    let zone = Name::from_str(&config.zone)
+      .with_context(|| format!("Invalid zone {}", config.zone))?
+      .to_lowercase();
+    let signer = match &config.tsig_key {
+      Some(tsig_key) => Some(Arc::new(TSigner::new(
+        tsig_key.secret.clone(),
+        TsigAlgorithm::HmacSha256,
+        Name::from_str(&tsig_key.name)?,
+        TSIG_FUDGE,
+      )?)),
+      None => None,
+    };
+    Ok(Rfc2136Provider {
+      nameserver: config.nameserver,
+      zone,
+      signer,
+      propagation_delay: config.propagation_delay,
+    })
+  }
+
+  fn txt_record(&self, name: &str, value: &str) -> anyhow::Result<Record> {
+    let name = Name::from_str(name)?.to_lowercase();
+    if !self.zone.zone_of(&name) {
+      return Err(anyhow!("{} is not in zone {}", name, self.zone));
+    }
+    Ok(Record::from_rdata(
+      name,
+      TXT_RECORD_TTL,
+      RData::TXT(TXT::new(vec![value.to_string()])),
+    ))
+  }
+
+  async fn client(&self) -> anyhow::Result<AsyncClient> {
+    let stream = UdpClientStream::<UdpSocket, TSigner>::with_timeout_and_signer(
+      self.nameserver,
+      UPDATE_TIMEOUT,
+      self.signer.clone(),
+    );
+    let (client, background) = AsyncClient::connect(stream).await?;
+    tokio::spawn(background);
+    Ok(client)
+  }
+}
+
+#[async_trait]
+impl DnsProvider for Rfc2136Provider {
+  fn manages(&self, name: &str) -> bool {
+    Name::from_str(name).is_ok_and(|name| self.zone.zone_of(&name.to_lowercase()))
+  }
+
+  fn propagation_delay(&self) -> Duration {
+    self.propagation_delay
+  }
+
+  async fn set_txt_record(&self, name: &str, value: &str) -> anyhow::Result<()> {
+    let record = self.txt_record(name, value)?;
+    let response = self
+      .client()
+      .await?
+      .append(record, self.zone.clone(), false)
+      .await?;
+    match response.response_code() {
+      ResponseCode::NoError => Ok(()),
+      code => Err(anyhow!("Failed to add TXT record {}: {}", name, code)),
+    }
+  }
+
+  async fn remove_txt_record(&self, name: &str, value: &str) -> anyhow::Result<()> {
+    let record = self.txt_record(name, value)?;
+    let response = self
+      .client()
+      .await?
+      .delete_by_rdata(record, self.zone.clone())
+      .await?;
+    match response.response_code() {
+      ResponseCode::NoError => Ok(()),
+      code => Err(anyhow!("Failed to remove TXT record {}: {}", name, code)),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::config::Rfc2136Config;
+  use crate::server::certificate::dns::{challenge_record_name, DnsProvider, Rfc2136Provider};
+  use std::time::Duration;
+
+  #[test]
+  fn test_challenge_record_name() {
+    assert_eq!(
+      challenge_record_name("example.com"),
+      "_acme-challenge.example.com"
+    );
+    assert_eq!(
+      challenge_record_name("*.example.com"),
+      "_acme-challenge.example.com"
+    );
+  }
+
+  #[test]
+  fn test_rfc2136_provider_zone() {
+    let provider = Rfc2136Provider::new(&Rfc2136Config {
+      nameserver: "127.0.0.1:53".parse().unwrap(),
+      zone: "Example.com".to_string(),
+      tsig_key: None,
+      propagation_delay: Duration::ZERO,
+    })
+    .unwrap();
+    assert!(provider.manages("_acme-challenge.example.com"));
+    assert!(provider.manages("_acme-challenge.app.example.com"));
+    assert!(!provider.manages("_acme-challenge.example.org"));
+    assert!(provider
+      .txt_record("_acme-challenge.example.org", "value")
+      .is_err());
+  }
+}
@@ -1,21 +1,35 @@
+pub(crate) mod dns;
+mod order;
 pub(crate) mod route;
 pub(crate) mod schema;
 
+use crate::config::{AcmeConfig, Config};
+use crate::server::certificate::dns::{challenge_record_name, dns_provider, DnsProvider};
+use crate::server::certificate::order::{
+  insert_certificate_order, set_certificate_order_failed, set_certificate_order_issued,
+  set_certificate_order_status, unfinished_certificate_orders,
+};
+use crate::server::certificate::schema::{CertificateOrder, CertificateOrderStatus};
+use crate::server::cluster::leader::is_leader;
+use crate::server::event::record_event;
+use crate::server::event::schema::EventType;
+use crate::server::user::get_user;
+use anyhow::anyhow;
 use cached::{Cached, TimedCache};
 use chrono::{DateTime, Utc};
 use instant_acme::{
-  Account, AccountCredentials, ChallengeType, Identifier, LetsEncrypt, NewAccount, NewOrder, Order,
-  OrderStatus,
+  Account, AccountCredentials, AuthorizationStatus, ChallengeType, ExternalAccountKey, Identifier,
+  NewAccount, NewOrder, Order, OrderStatus,
 };
 use once_cell::sync::Lazy;
 use rcgen::{Certificate, CertificateParams, DistinguishedName};
-use sqlx::testing::TestTermination;
 use sqlx::{Pool, Postgres};
+use std::collections::HashSet;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::Mutex;
 use tokio::time::sleep;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 use trust_dns_resolver::config::{ResolverConfig, ResolverOpts};
 use trust_dns_resolver::TokioAsyncResolver;
 use uuid::Uuid;
@@ -23,162 +37,465 @@ use uuid::Uuid;
 const CACHE_LIFESPAN: u64 = 600;
 const INTERNAL_CHECK_SPAN: u64 = 5;
 const EXTERNAL_MAX_CHECKS: u64 = 10;
+/// Postgres channel notified whenever a certificate is issued, renewed or removed.
+const CERTIFICATE_CHANNEL: &str = "dosei_certificates";
+/// Certificates expiring within this many days are renewed.
+const RENEWAL_WINDOW_DAYS: i64 = 30;
+const RENEWAL_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// A domain is not renewed again while its previous order may still be in flight.
+const RENEWAL_RETRY_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+const RESUME_CHECK_INTERVAL: Duration = Duration::from_secs(30);
 
-pub async fn create_acme_account(email: &str) -> anyhow::Result<AccountCredentials> {
-  let server_url = LetsEncrypt::Production.url().to_string();
+/// Returns the ACME account of an owner on the configured directory, creating it on first use
+/// so orders are not rate limited by new accounts.
+pub async fn acme_account_credentials(
+  config: &Config,
+  pool: &Pool<Postgres>,
+  owner_id: Uuid,
+  email: &str,
+) -> anyhow::Result<AccountCredentials> {
+  let directory_url = &config.acme.directory_url;
+  if let Some(record) = sqlx::query!(
+    "SELECT credentials FROM acme_account WHERE owner_id = $1 AND directory_url = $2",
+    owner_id,
+    directory_url
+  )
+  .fetch_optional(pool)
+  .await?
+  {
+    return Ok(serde_json::from_value(record.credentials)?);
+  }
+  let credentials = create_acme_account(&config.acme, email).await?;
+  // Another node may have created an account concurrently, every node then uses the one stored.
+  let record = sqlx::query!(
+    "
+    INSERT INTO acme_account (id, owner_id, directory_url, credentials)
+    VALUES ($1, $2, $3, $4)
+    ON CONFLICT (owner_id, directory_url) DO UPDATE SET owner_id = acme_account.owner_id
+    RETURNING credentials, (xmax = 0) AS \"inserted!\"
+    ",
+    Uuid::new_v4(),
+    owner_id,
+    directory_url,
+    serde_json::to_value(&credentials)?
+  )
+  .fetch_one(pool)
+  .await?;
+  if record.inserted {
+    info!("Created ACME account on {}", directory_url);
+  }
+  Ok(serde_json::from_value(record.credentials)?)
+}
 
+async fn create_acme_account(acme: &AcmeConfig, email: &str) -> anyhow::Result<AccountCredentials> {
+  let external_account = acme
+    .external_account
+    .as_ref()
+    .map(|account| ExternalAccountKey::new(account.key_id.clone(), &account.hmac_key));
   let new_account_info = NewAccount {
     contact: &[&format!("mailto:{}", email)],
     terms_of_service_agreed: true,
     only_return_existing: false,
   };
 
-  let result = Account::create(&new_account_info, &server_url, None).await?;
+  let result = Account::create(
+    &new_account_info,
+    &acme.directory_url,
+    external_account.as_ref(),
+  )
+  .await?;
   Ok(result.1)
 }
 
-pub async fn create_acme_certificate(
+/// Returns the names an owner may not get a certificate for. Each name must be a domain the owner
+/// verified, and a wildcard name one whose subdomains it covers, e.g. `example.com` for
+/// `*.example.com`. The base domain and its wildcard are reserved to cluster admins, since every
+/// preview subdomain falls back to that certificate.
+pub async fn unauthorized_domain_names(
+  config: &Config,
+  pool: &Pool<Postgres>,
   owner_id: Uuid,
-  domain_name: &str,
-  credentials: AccountCredentials,
+  domain_names: &[String],
+) -> anyhow::Result<Vec<String>> {
+  let is_reserved = |name: &str| {
+    config
+      .base_domain
+      .as_deref()
+      .is_some_and(|base_domain| name.strip_prefix("*.").unwrap_or(name) == base_domain)
+  };
+  let is_admin = config.cluster.is_admin(owner_id);
+  let verified_names: Vec<String> = domain_names
+    .iter()
+    .map(|name| name.strip_prefix("*.").unwrap_or(name).to_string())
+    .collect();
+  let owned = sqlx::query_scalar!(
+    "SELECT name FROM domain WHERE owner_id = $1 AND name = ANY($2) AND verified_at IS NOT NULL",
+    owner_id,
+    &verified_names
+  )
+  .fetch_all(pool)
+  .await?;
+  Ok(
+    domain_names
+      .iter()
+      .zip(verified_names)
+      .filter(|(name, verified_name)| {
+        if is_reserved(name) {
+          !is_admin
+        } else {
+          !owned.contains(verified_name)
+        }
+      })
+      .map(|(name, _)| name.clone())
+      .collect(),
+  )
+}
+
+/// Records an order for a certificate covering every domain name, the first one being its
+/// primary name, and starts issuing it.
+pub async fn new_certificate_order(
+  config: &'static Config,
   pool: Arc<Pool<Postgres>>,
-) -> anyhow::Result<()> {
+  owner_id: Uuid,
+  domain_names: &[String],
+) -> anyhow::Result<CertificateOrder> {
+  if domain_names.is_empty() {
+    return Err(anyhow::Error::msg(
+      "no domain name to order a certificate for",
+    ));
+  }
+  let certificate_order = insert_certificate_order(&pool, owner_id, domain_names).await?;
+  run_certificate_order(
+    config,
+    certificate_order.id,
+    certificate_order.owner_id,
+    certificate_order.domain_names.clone(),
+    pool,
+  );
+  Ok(certificate_order)
+}
+
+/// Resumes the orders a previous leader did not finish, as challenges only live in its memory.
+pub fn start_certificate_orders(config: &'static Config, pool: Arc<Pool<Postgres>>) {
+  tokio::spawn(async move {
+    loop {
+      if is_leader() {
+        match unfinished_certificate_orders(&pool).await {
+          Ok(certificate_orders) => {
+            for certificate_order in certificate_orders {
+              run_certificate_order(
+                config,
+                certificate_order.id,
+                certificate_order.owner_id,
+                certificate_order.domain_names,
+                Arc::clone(&pool),
+              );
+            }
+          }
+          Err(err) => error!("Failed to get unfinished certificate orders: {}", err),
+        }
+      }
+      sleep(RESUME_CHECK_INTERVAL).await;
+    }
+  });
+}
+
+/// Issues the certificate of an order in the background, unless this node is already on it.
+fn run_certificate_order(
+  config: &'static Config,
+  order_id: Uuid,
+  owner_id: Uuid,
+  domain_names: Vec<String>,
+  pool: Arc<Pool<Postgres>>,
+) {
+  if !ORDERS_IN_PROGRESS.lock().unwrap().insert(order_id) {
+    return;
+  }
+  tokio::spawn(async move {
+    let names = domain_names.join(", ");
+    info!("Ordering certificate for {}", names);
+    match issue_certificate(config, order_id, owner_id, &domain_names, &pool).await {
+      Ok(certificate_id) => {
+        if let Err(err) = set_certificate_order_issued(&pool, order_id, certificate_id).await {
+          error!("Failed to update certificate order {}: {}", order_id, err);
+        }
+      }
+      Err(err) => {
+        error!("Certificate for {} failed: {}", names, err);
+        if let Err(err) = set_certificate_order_failed(&pool, order_id, &err.to_string()).await {
+          error!("Failed to update certificate order {}: {}", order_id, err);
+        }
+        let message = format!("Certificate for {} failed: {}", names, err);
+        record_event(&pool, owner_id, EventType::CertificateFailed, &message).await;
+      }
+    }
+    ORDERS_IN_PROGRESS.lock().unwrap().remove(&order_id);
+  });
+}
+
+async fn issue_certificate(
+  config: &'static Config,
+  order_id: Uuid,
+  owner_id: Uuid,
+  domain_names: &[String],
+  pool: &Pool<Postgres>,
+) -> anyhow::Result<Uuid> {
+  set_certificate_order_status(pool, order_id, CertificateOrderStatus::PendingDns).await?;
+  let email = get_user(owner_id, Arc::new(pool.clone()))
+    .await
+    .map_err(|err| anyhow::Error::msg(err.to_string()))?
+    .email;
+  let credentials = acme_account_credentials(config, pool, owner_id, &email).await?;
+  let identifiers: Vec<Identifier> = domain_names
+    .iter()
+    .map(|domain_name| Identifier::Dns(domain_name.clone()))
+    .collect();
   let mut order = Account::from_credentials(credentials)
     .await?
     .new_order(&NewOrder {
-      identifiers: &[Identifier::Dns(domain_name.to_string())],
+      identifiers: &identifiers,
     })
     .await?;
 
-  let authorizations = order.authorizations().await?;
-  let authorization = &authorizations
-    .first()
-    .ok_or_else(|| anyhow::Error::msg("authorization not found"))?;
-  let challenge = authorization
-    .challenges
-    .iter()
-    .find(|ch| ch.r#type == ChallengeType::Http01)
-    .ok_or_else(|| anyhow::Error::msg("http-01 challenge not found"))?;
-  order.set_challenge_ready(&challenge.url).await?;
-  let http1_challenge_token_cache = Arc::clone(&HTTP1_CHALLENGE_TOKEN_CACHE);
-  {
-    let mut cache = http1_challenge_token_cache.lock().await;
-    cache.cache_set(
-      challenge.token.clone(),
-      order.key_authorization(challenge).as_str().to_string(),
-    );
+  let mut challenges = Vec::new();
+  let result = async {
+    prepare_challenges(config, pool, owner_id, &mut order, &mut challenges).await?;
+    validate_challenges(&challenges, &mut order, order_id, pool).await?;
+    wait_for_order(&mut order).await?;
+    provision_certification(owner_id, domain_names, &mut order, pool).await
   }
-  internal_check(
-    owner_id,
-    domain_name,
-    &challenge.token,
-    order.key_authorization(challenge).as_str(),
-    Arc::new(Mutex::new(order)),
-    pool,
-  );
-  Ok(())
+  .await;
+  remove_dns_records(&challenges).await;
+  result
+}
+
+/// A challenge answered by this node, one per pending authorization of an order.
+enum PendingChallenge {
+  Http {
+    url: String,
+    domain_name: String,
+    token: String,
+    key_authorization: String,
+  },
+  Dns {
+    url: String,
+    record: DnsRecord,
+  },
+}
+
+impl PendingChallenge {
+  fn url(&self) -> &str {
+    match self {
+      PendingChallenge::Http { url, .. } | PendingChallenge::Dns { url, .. } => url,
+    }
+  }
+}
+
+/// The TXT record answering a DNS-01 challenge, removed once the order is done.
+struct DnsRecord {
+  name: String,
+  value: String,
+  provider: Arc<dyn DnsProvider>,
 }
 
-pub fn internal_check(
+async fn prepare_challenges(
+  config: &'static Config,
+  pool: &Pool<Postgres>,
   owner_id: Uuid,
-  domain_name: &str,
-  token: &str,
-  token_value: &str,
-  order: Arc<Mutex<Order>>,
-  pool: Arc<Pool<Postgres>>,
-) {
-  let domain_name = domain_name.to_string();
-  let token = token.to_string();
-  let token_value = token_value.to_string();
-  let order = Arc::clone(&order);
+  order: &mut Order,
+  challenges: &mut Vec<PendingChallenge>,
+) -> anyhow::Result<()> {
+  let dns_provider = dns_provider(&config.acme)?;
+  for authorization in order.authorizations().await? {
+    if !matches!(authorization.status, AuthorizationStatus::Pending) {
+      continue;
+    }
+    let Identifier::Dns(domain_name) = &authorization.identifier;
+    let find_challenge = |challenge_type| {
+      authorization
+        .challenges
+        .iter()
+        .find(|ch| ch.r#type == challenge_type)
+    };
+    // DNS-01 is used whenever the zone of the domain is managed, and is the only way to get a
+    // wildcard certificate. The cluster answers it on its own zone, so only for names the owner
+    // may get a certificate for.
+    let record_name = challenge_record_name(domain_name);
+    let authorized = unauthorized_domain_names(config, pool, owner_id, &[domain_name.clone()])
+      .await?
+      .is_empty();
+    let provider = dns_provider
+      .as_ref()
+      .filter(|provider| authorized && provider.manages(&record_name));
+    match (
+      provider,
+      find_challenge(ChallengeType::Dns01),
+      find_challenge(ChallengeType::Http01),
+    ) {
+      (Some(provider), Some(challenge), _) => {
+        let record_value = order.key_authorization(challenge).dns_value();
+        provider.set_txt_record(&record_name, &record_value).await?;
+        challenges.push(PendingChallenge::Dns {
+          url: challenge.url.clone(),
+          record: DnsRecord {
+            name: record_name,
+            value: record_value,
+            provider: Arc::clone(provider),
+          },
+        });
+      }
+      (_, _, Some(challenge)) => {
+        let key_authorization = order.key_authorization(challenge).as_str().to_string();
+        HTTP1_CHALLENGE_TOKEN_CACHE
+          .lock()
+          .await
+          .cache_set(challenge.token.clone(), key_authorization.clone());
+        challenges.push(PendingChallenge::Http {
+          url: challenge.url.clone(),
+          domain_name: domain_name.clone(),
+          token: challenge.token.clone(),
+          key_authorization,
+        });
+      }
+      _ => {
+        return Err(anyhow::Error::msg(format!(
+          "no supported challenge for {}, wildcard certificates require a DNS provider",
+          domain_name
+        )))
+      }
+    }
+  }
+  Ok(())
+}
 
+/// Waits for challenge records to propagate and tokens to be reachable before telling the CA
+/// the challenges are ready, since a failed validation fails the whole order.
+async fn validate_challenges(
+  challenges: &[PendingChallenge],
+  order: &mut Order,
+  order_id: Uuid,
+  pool: &Pool<Postgres>,
+) -> anyhow::Result<()> {
+  let propagation_delay = challenges
+    .iter()
+    .filter_map(|challenge| match challenge {
+      PendingChallenge::Dns { record, .. } => Some(record.provider.propagation_delay()),
+      PendingChallenge::Http { .. } => None,
+    })
+    .max();
+  if let Some(propagation_delay) = propagation_delay {
+    sleep(propagation_delay).await;
+  }
+  for challenge in challenges {
+    if let PendingChallenge::Http {
+      domain_name,
+      token,
+      key_authorization,
+      ..
+    } = challenge
+    {
+      if !internal_check(domain_name, token, key_authorization).await {
+        return Err(anyhow::Error::msg(format!(
+          "the HTTP-01 challenge of {} was not reachable",
+          domain_name
+        )));
+      }
+    }
+  }
+  set_certificate_order_status(pool, order_id, CertificateOrderStatus::ChallengeServed).await?;
+  for challenge in challenges {
+    order.set_challenge_ready(challenge.url()).await?;
+  }
+  set_certificate_order_status(pool, order_id, CertificateOrderStatus::Validating).await?;
+  Ok(())
+}
+
+/// Checks the HTTP-01 token is served on the domain, as the CA is about to.
+async fn internal_check(domain_name: &str, token: &str, token_value: &str) -> bool {
   let mut attempts = 0;
-  tokio::spawn(async move {
-    loop {
-      sleep(Duration::from_secs(INTERNAL_CHECK_SPAN)).await;
-      let resolver = TokioAsyncResolver::tokio(ResolverConfig::default(), ResolverOpts::default());
-      if let Ok(response) = resolver.lookup_ip(&domain_name).await {
-        if let Some(address) = response.iter().next() {
-          let url = format!("http://{}/.well-known/acme-challenge/{}", address, token);
-          let client = reqwest::Client::builder().no_proxy().build().unwrap();
-          let response = client.get(&url).send().await;
-
-          if response.is_success() {
-            if let Ok(response_text) = response.unwrap().text().await {
-              if response_text == token_value {
-                external_check(owner_id, &domain_name, order, pool);
-                break;
-              }
+  loop {
+    sleep(Duration::from_secs(INTERNAL_CHECK_SPAN)).await;
+    let resolver = TokioAsyncResolver::tokio(ResolverConfig::default(), ResolverOpts::default());
+    if let Ok(response) = resolver.lookup_ip(domain_name).await {
+      if let Some(address) = response.iter().next() {
+        let url = format!("http://{}/.well-known/acme-challenge/{}", address, token);
+        let client = reqwest::Client::builder().no_proxy().build().unwrap();
+        if let Ok(response) = client.get(&url).send().await {
+          if let Ok(response_text) = response.text().await {
+            if response_text == token_value {
+              return true;
             }
           }
         }
       }
-      error!("Failed to get fetch {}, trying again", &domain_name);
-      if CACHE_LIFESPAN <= attempts {
-        error!("Too many tries, giving up");
-        break;
-      }
-      attempts += INTERNAL_CHECK_SPAN;
     }
-  });
+    error!("Failed to get fetch {}, trying again", domain_name);
+    if CACHE_LIFESPAN <= attempts {
+      error!("Too many tries, giving up");
+      return false;
+    }
+    attempts += INTERNAL_CHECK_SPAN;
+  }
 }
 
-pub fn external_check(
-  owner_id: Uuid,
-  domain_name: &str,
-  order: Arc<Mutex<Order>>,
-  pool: Arc<Pool<Postgres>>,
-) {
-  let domain_name = domain_name.to_string();
-  let order = Arc::clone(&order);
+async fn remove_dns_records(challenges: &[PendingChallenge]) {
+  for challenge in challenges {
+    if let PendingChallenge::Dns { record, .. } = challenge {
+      if let Err(err) = record
+        .provider
+        .remove_txt_record(&record.name, &record.value)
+        .await
+      {
+        warn!("Failed to remove challenge record {}: {}", record.name, err);
+      }
+    }
+  }
+}
 
+/// Polls the order until the CA validated every challenge.
+async fn wait_for_order(order: &mut Order) -> anyhow::Result<()> {
   let mut attempts = 1;
   let mut backoff_duration = Duration::from_millis(250);
-  tokio::spawn(async move {
-    loop {
-      sleep(backoff_duration).await;
-      let mut order_guard = order.lock().await;
-      let order_state = order_guard.refresh().await.unwrap();
-      match order_state.status {
-        OrderStatus::Ready => {
-          drop(order_guard);
-          match provision_certification(owner_id, &domain_name, order, pool).await {
-            Ok(_) => {}
-            Err(_) => {
-              error!("Something went wrong when generating CERT");
-            }
-          };
-          break;
-        }
-        order_status => {
-          error!("Order Status: {:?}", order_status);
-          backoff_duration *= 4;
-          attempts += 1;
-
-          if EXTERNAL_MAX_CHECKS <= attempts {
-            error!("Order is not yet ready after {EXTERNAL_MAX_CHECKS} attempts, Giving up.");
-            break;
-          }
-          info!("Order is not ready, waiting {backoff_duration:?}");
+  loop {
+    sleep(backoff_duration).await;
+    match order.refresh().await?.status {
+      OrderStatus::Ready => return Ok(()),
+      OrderStatus::Invalid => {
+        return Err(anyhow::Error::msg(
+          "the CA could not validate the challenges",
+        ))
+      }
+      order_status => {
+        error!("Order Status: {:?}", order_status);
+        backoff_duration *= 4;
+        attempts += 1;
+
+        if EXTERNAL_MAX_CHECKS <= attempts {
+          error!("Order is not yet ready after {EXTERNAL_MAX_CHECKS} attempts, Giving up.");
+          return Err(anyhow::Error::msg(format!(
+            "order is {:?} after {} attempts",
+            order_status, EXTERNAL_MAX_CHECKS
+          )));
         }
+        info!("Order is not ready, waiting {backoff_duration:?}");
       }
     }
-  });
+  }
 }
 
+/// Finalizes the order and stores its certificate, returning the certificate id.
 async fn provision_certification(
   owner_id: Uuid,
-  domain_name: &str,
-  order: Arc<Mutex<Order>>,
-  pool: Arc<Pool<Postgres>>,
-) -> anyhow::Result<()> {
+  domain_names: &[String],
+  order: &mut Order,
+  pool: &Pool<Postgres>,
+) -> anyhow::Result<Uuid> {
   let certificate = {
-    let mut params = CertificateParams::new(vec![domain_name.to_owned()]);
+    let mut params = CertificateParams::new(domain_names.to_vec());
     params.distinguished_name = DistinguishedName::new();
     Certificate::from_params(params)?
   };
   let signing_request = certificate.serialize_request_der()?;
-  let mut order = order.lock().await;
   order.finalize(&signing_request).await?;
 
   let cert_chain_pem = loop {
@@ -204,8 +521,10 @@ async fn provision_certification(
 
   let certificate = schema::Certificate {
     id: Uuid::new_v4(),
-    domain_name: domain_name.to_string(),
-    certificate: certificates[0].to_string(),
+    domain_name: domain_names[0].clone(),
+    alt_names: domain_names[1..].to_vec(),
+    // The full chain is kept so it can be served as is.
+    certificate: cert_chain_pem.clone(),
     private_key: certificate.serialize_private_key_pem(),
     expires_at,
     owner_id,
@@ -213,28 +532,125 @@ async fn provision_certification(
     created_at: Utc::now(),
   };
 
-  match sqlx::query_as!(
-      schema::Certificate,
-      r#"INSERT INTO certificate (id, domain_name, certificate, private_key, expires_at, owner_id, updated_at, created_at)
-       VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
-       RETURNING id, domain_name, certificate, private_key, expires_at, owner_id, updated_at, created_at"#,
-      certificate.id,
-      certificate.domain_name,
-      certificate.certificate,
-      certificate.private_key,
-      certificate.expires_at,
-      certificate.owner_id,
-      certificate.updated_at,
-      certificate.created_at,
-    ).fetch_one(&*pool).await {
-    Ok(recs) => {
-      info!("{:?}", recs);
-    },
-    Err(err) => {
-      error!("Error in creating certificate: {:?}", err);
+  // Renewals replace the certificate of the domain in place, only ever for the same owner.
+  let record = sqlx::query!(
+    "
+    INSERT INTO certificate (id, domain_name, alt_names, certificate, private_key, expires_at, owner_id, updated_at, created_at)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+    ON CONFLICT (domain_name) DO UPDATE SET
+      alt_names = EXCLUDED.alt_names,
+      certificate = EXCLUDED.certificate,
+      private_key = EXCLUDED.private_key,
+      expires_at = EXCLUDED.expires_at,
+      updated_at = EXCLUDED.updated_at
+    WHERE certificate.owner_id = EXCLUDED.owner_id
+    RETURNING id
+    ",
+    certificate.id,
+    certificate.domain_name,
+    &certificate.alt_names,
+    certificate.certificate,
+    certificate.private_key,
+    certificate.expires_at,
+    certificate.owner_id,
+    certificate.updated_at,
+    certificate.created_at,
+  )
+  .fetch_optional(pool)
+  .await?
+  .ok_or_else(|| {
+    anyhow!(
+      "Certificate for {} belongs to another owner",
+      certificate.domain_name
+    )
+  })?;
+  let domain_names = domain_names.join(", ");
+  info!(
+    "Certificate for {} issued, expires at {}",
+    domain_names, expires_at
+  );
+  if let Err(err) = notify_certificate_change(pool).await {
+    error!("Failed to notify certificate change: {}", err);
+  }
+  let message = format!(
+    "Certificate for {} issued, expires at {}",
+    domain_names, expires_at
+  );
+  record_event(pool, owner_id, EventType::CertificateIssued, &message).await;
+  Ok(record.id)
+}
+
+/// Tells proxies serving certificates from the `certificate` table to reload them.
+pub async fn notify_certificate_change(pool: &Pool<Postgres>) -> anyhow::Result<()> {
+  sqlx::query!("SELECT pg_notify($1, '')", CERTIFICATE_CHANNEL)
+    .execute(pool)
+    .await?;
+  Ok(())
+}
+
+/// Renews certificates before they expire, retrying failed renewals once a day.
+pub fn start_certificate_renewal(config: &'static Config, pool: Arc<Pool<Postgres>>) {
+  tokio::spawn(async move {
+    loop {
+      // Challenges are answered by the leader, so it is the one ordering certificates. Other
+      // nodes check back soon, so a new leader renews right away.
+      if !is_leader() {
+        sleep(RESUME_CHECK_INTERVAL).await;
+        continue;
+      }
+      if let Err(err) = renew_certificates(config, Arc::clone(&pool)).await {
+        error!("Failed to renew certificates: {}", err);
+      }
+      sleep(RENEWAL_CHECK_INTERVAL).await;
+    }
+  });
+}
+
+async fn renew_certificates(
+  config: &'static Config,
+  pool: Arc<Pool<Postgres>>,
+) -> anyhow::Result<()> {
+  let renew_before = Utc::now() + chrono::Duration::days(RENEWAL_WINDOW_DAYS);
+  // Domains with a recent order are either being renewed, or failed to and wait for a retry.
+  let retry_after = Utc::now() - chrono::Duration::from_std(RENEWAL_RETRY_INTERVAL)?;
+  let records = sqlx::query!(
+    "
+    SELECT c.domain_name, c.alt_names, c.owner_id
+    FROM certificate c
+    WHERE c.expires_at < $1 AND NOT EXISTS (
+      SELECT 1 FROM certificate_order o
+      WHERE c.domain_name = ANY(o.domain_names) AND o.created_at > $2
+    )
+    ",
+    renew_before,
+    retry_after
+  )
+  .fetch_all(&*pool)
+  .await?;
+  for record in records {
+    info!("Renewing certificate for {}", record.domain_name);
+    let mut domain_names = vec![record.domain_name.clone()];
+    domain_names.extend(record.alt_names);
+    if let Err(err) =
+      new_certificate_order(config, Arc::clone(&pool), record.owner_id, &domain_names).await
+    {
+      error!(
+        "Failed to renew certificate for {}: {}",
+        record.domain_name, err
+      );
+      let message = format!(
+        "Certificate for {} failed to renew: {}",
+        record.domain_name, err
+      );
+      record_event(
+        &pool,
+        record.owner_id,
+        EventType::CertificateFailed,
+        &message,
+      )
+      .await;
     }
   }
-  // TODO: Send email and notify.
   Ok(())
 }
 
@@ -255,3 +671,7 @@ static HTTP1_CHALLENGE_TOKEN_CACHE: Lazy<Arc<Mutex<TimedCache<String, String>>>>
     let cache = TimedCache::with_lifespan(CACHE_LIFESPAN);
     Arc::new(Mutex::new(cache))
   });
+
+/// Orders being issued by this node, so resuming unfinished orders does not start them twice.
+static ORDERS_IN_PROGRESS: Lazy<std::sync::Mutex<HashSet<Uuid>>> =
+  Lazy::new(|| std::sync::Mutex::new(HashSet::new()));
@@ -0,0 +1,115 @@
+use crate::server::certificate::schema::{CertificateOrder, CertificateOrderStatus};
+use chrono::Utc;
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+pub async fn insert_certificate_order(
+  pool: &Pool<Postgres>,
+  owner_id: Uuid,
+  domain_names: &[String],
+) -> anyhow::Result<CertificateOrder> {
+  let now = Utc::now();
+  let certificate_order = sqlx::query_as!(
+    CertificateOrder,
+    r#"
+    INSERT INTO certificate_order (id, domain_names, status, owner_id, updated_at, created_at)
+    VALUES ($1, $2, $3, $4, $5, $6)
+    RETURNING id, domain_names, status AS "status: CertificateOrderStatus", failure_reason, certificate_id, owner_id, updated_at, created_at
+    "#,
+    Uuid::new_v4(),
+    domain_names,
+    CertificateOrderStatus::PendingDns as CertificateOrderStatus,
+    owner_id,
+    now,
+    now
+  )
+  .fetch_one(pool)
+  .await?;
+  Ok(certificate_order)
+}
+
+pub async fn get_certificate_order(
+  pool: &Pool<Postgres>,
+  id: Uuid,
+  owner_id: Uuid,
+) -> anyhow::Result<Option<CertificateOrder>> {
+  let certificate_order = sqlx::query_as!(
+    CertificateOrder,
+    r#"
+    SELECT id, domain_names, status AS "status: CertificateOrderStatus", failure_reason, certificate_id, owner_id, updated_at, created_at
+    FROM certificate_order
+    WHERE id = $1 AND owner_id = $2
+    "#,
+    id,
+    owner_id
+  )
+  .fetch_optional(pool)
+  .await?;
+  Ok(certificate_order)
+}
+
+/// Orders interrupted before they were issued or failed, e.g. by a restart.
+pub async fn unfinished_certificate_orders(
+  pool: &Pool<Postgres>,
+) -> anyhow::Result<Vec<CertificateOrder>> {
+  let certificate_orders = sqlx::query_as!(
+    CertificateOrder,
+    r#"
+    SELECT id, domain_names, status AS "status: CertificateOrderStatus", failure_reason, certificate_id, owner_id, updated_at, created_at
+    FROM certificate_order
+    WHERE status NOT IN ('issued', 'failed')
+    ORDER BY created_at
+    "#
+  )
+  .fetch_all(pool)
+  .await?;
+  Ok(certificate_orders)
+}
+
+pub async fn set_certificate_order_status(
+  pool: &Pool<Postgres>,
+  id: Uuid,
+  status: CertificateOrderStatus,
+) -> anyhow::Result<()> {
+  sqlx::query!(
+    "UPDATE certificate_order SET status = $2, updated_at = $3 WHERE id = $1",
+    id,
+    status as CertificateOrderStatus,
+    Utc::now()
+  )
+  .execute(pool)
+  .await?;
+  Ok(())
+}
+
+pub async fn set_certificate_order_issued(
+  pool: &Pool<Postgres>,
+  id: Uuid,
+  certificate_id: Uuid,
+) -> anyhow::Result<()> {
+  sqlx::query!(
+    "UPDATE certificate_order SET status = 'issued', certificate_id = $2, updated_at = $3 WHERE id = $1",
+    id,
+    certificate_id,
+    Utc::now()
+  )
+  .execute(pool)
+  .await?;
+  Ok(())
+}
+
+pub async fn set_certificate_order_failed(
+  pool: &Pool<Postgres>,
+  id: Uuid,
+  failure_reason: &str,
+) -> anyhow::Result<()> {
+  sqlx::query!(
+    "UPDATE certificate_order SET status = 'failed', failure_reason = $2, updated_at = $3 WHERE id = $1",
+    id,
+    failure_reason,
+    Utc::now()
+  )
+  .execute(pool)
+  .await?;
+  Ok(())
+}
@@ -1,16 +1,21 @@
 use crate::config::Config;
+use crate::server::certificate::order::get_certificate_order;
+use crate::server::certificate::schema::{Certificate, CertificateOrder, CertificateResponse};
 use crate::server::certificate::{
-  create_acme_account, create_acme_certificate, get_http01_challenge_token_value,
+  get_http01_challenge_token_value, new_certificate_order, notify_certificate_change,
+  unauthorized_domain_names,
 };
 use crate::server::session::validate_session;
-use crate::server::user::get_user;
-use axum::extract::Path;
+use axum::extract::{Path, Query};
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
 use axum::{Extension, Json};
 use serde::Deserialize;
+use serde_json::json;
 use sqlx::{Pool, Postgres};
 use std::sync::Arc;
+use tracing::error;
+use uuid::Uuid;
 
 pub async fn api_new_certificate(
   pool: Extension<Arc<Pool<Postgres>>>,
@@ -21,26 +26,169 @@ pub async fn api_new_certificate(
   let session = validate_session(Arc::clone(&pool), &config, headers)
     .await
     .map_err(|e| e.into_response())?;
-  let user = get_user(session.owner_id, Arc::clone(&pool))
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
-  let acme_account_credentials = create_acme_account(&user.email)
+  let domain_names = body.domain_names();
+  if domain_names.is_empty() {
+    return Err(
+      (
+        StatusCode::BAD_REQUEST,
+        Json(json!({"message": "domain_name or domain_names is required"})),
+      )
+        .into_response(),
+    );
+  }
+  // Certificates are served for every name they cover, and their private key is readable.
+  let unauthorized = unauthorized_domain_names(&config, &pool, session.owner_id, &domain_names)
     .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
-  create_acme_certificate(
-    user.id,
-    &body.domain_name,
-    acme_account_credentials,
-    Arc::clone(&pool),
+    .map_err(|err| {
+      error!("Error in checking certificate domains: {:?}", err);
+      StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    })?;
+  if !unauthorized.is_empty() {
+    return Err(
+      (
+        StatusCode::FORBIDDEN,
+        Json(json!({
+          "message": format!("Domains not verified by you: {}", unauthorized.join(", "))
+        })),
+      )
+        .into_response(),
+    );
+  }
+  match new_certificate_order(&config, Arc::clone(&pool), session.owner_id, &domain_names).await {
+    Ok(certificate_order) => Ok((StatusCode::ACCEPTED, Json(certificate_order)).into_response()),
+    Err(err) => {
+      error!("Error in ordering certificate: {:?}", err);
+      Err(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+    }
+  }
+}
+
+pub async fn api_get_certificate_order(
+  pool: Extension<Arc<Pool<Postgres>>>,
+  config: Extension<&'static Config>,
+  headers: axum::http::HeaderMap,
+  Path(order_id): Path<Uuid>,
+) -> Result<Json<CertificateOrder>, StatusCode> {
+  let session = validate_session(Arc::clone(&pool), &config, headers).await?;
+  match get_certificate_order(&pool, order_id, session.owner_id).await {
+    Ok(Some(certificate_order)) => Ok(Json(certificate_order)),
+    Ok(None) => Err(StatusCode::NOT_FOUND),
+    Err(err) => {
+      error!("Error in retrieving certificate order: {:?}", err);
+      Err(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+  }
+}
+
+/// Either a single `domain_name`, or several `domain_names` on one certificate.
+#[derive(Deserialize)]
+pub struct CertificateBody {
+  domain_name: Option<String>,
+  #[serde(default)]
+  domain_names: Vec<String>,
+}
+
+impl CertificateBody {
+  fn domain_names(&self) -> Vec<String> {
+    let mut domain_names: Vec<String> = Vec::new();
+    for domain_name in self.domain_name.iter().chain(&self.domain_names) {
+      let domain_name = domain_name.trim().to_lowercase();
+      if !domain_name.is_empty() && !domain_names.contains(&domain_name) {
+        domain_names.push(domain_name);
+      }
+    }
+    domain_names
+  }
+}
+
+pub async fn api_get_certificates(
+  pool: Extension<Arc<Pool<Postgres>>>,
+  config: Extension<&'static Config>,
+  headers: axum::http::HeaderMap,
+) -> Result<Json<Vec<CertificateResponse>>, StatusCode> {
+  let session = validate_session(Arc::clone(&pool), &config, headers).await?;
+  match sqlx::query_as!(
+    Certificate,
+    "SELECT * FROM certificate WHERE owner_id = $1::uuid ORDER BY domain_name",
+    session.owner_id
   )
+  .fetch_all(&**pool)
   .await
-  .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
-  Ok(StatusCode::CREATED.into_response())
+  {
+    Ok(recs) => Ok(Json(
+      recs
+        .into_iter()
+        .map(|certificate| certificate.response(false))
+        .collect(),
+    )),
+    Err(err) => {
+      error!("Error in retrieving certificates: {:?}", err);
+      Err(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+  }
+}
+
+pub async fn api_get_certificate(
+  pool: Extension<Arc<Pool<Postgres>>>,
+  config: Extension<&'static Config>,
+  headers: axum::http::HeaderMap,
+  Path(certificate_id): Path<Uuid>,
+  Query(query): Query<CertificateQuery>,
+) -> Result<Json<CertificateResponse>, StatusCode> {
+  let session = validate_session(Arc::clone(&pool), &config, headers).await?;
+  match sqlx::query_as!(
+    Certificate,
+    "SELECT * FROM certificate WHERE id = $1::uuid AND owner_id = $2::uuid",
+    certificate_id,
+    session.owner_id
+  )
+  .fetch_optional(&**pool)
+  .await
+  {
+    Ok(Some(certificate)) => Ok(Json(certificate.response(query.private_key))),
+    Ok(None) => Err(StatusCode::NOT_FOUND),
+    Err(err) => {
+      error!("Error in retrieving certificate: {:?}", err);
+      Err(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+  }
 }
 
 #[derive(Deserialize)]
-pub struct CertificateBody {
-  domain_name: String,
+pub struct CertificateQuery {
+  #[serde(default)]
+  private_key: bool,
+}
+
+pub async fn api_delete_certificate(
+  pool: Extension<Arc<Pool<Postgres>>>,
+  config: Extension<&'static Config>,
+  headers: axum::http::HeaderMap,
+  Path(certificate_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+  let session = validate_session(Arc::clone(&pool), &config, headers).await?;
+  match sqlx::query!(
+    "DELETE FROM certificate WHERE id = $1::uuid and owner_id = $2::uuid",
+    certificate_id,
+    session.owner_id
+  )
+  .execute(&**pool)
+  .await
+  {
+    Ok(res) => {
+      if res.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+      }
+      if let Err(err) = notify_certificate_change(&pool).await {
+        error!("Failed to notify certificate change: {}", err);
+      }
+      Ok(StatusCode::OK)
+    }
+    Err(err) => {
+      error!("Error in deleting certificate: {:?}", err);
+      Err(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+  }
 }
 
 pub async fn api_http01_challenge(Path(token): Path<String>) -> Result<String, Response> {
@@ -49,3 +197,80 @@ pub async fn api_http01_challenge(Path(token): Path<String>) -> Result<String, R
   }
   Err(StatusCode::NOT_FOUND.into_response())
 }
+
+#[cfg(test)]
+mod tests {
+  use crate::server::certificate::route::{api_new_certificate, CertificateBody};
+  use crate::server::session::schema::Session;
+  use crate::test;
+  use axum::http::{HeaderMap, HeaderValue, StatusCode};
+  use axum::{Extension, Json};
+  use sqlx::{Pool, Postgres};
+  use std::sync::Arc;
+  use uuid::Uuid;
+
+  async fn insert_domain(pool: &Pool<Postgres>, name: &str, owner_id: Uuid, verified: bool) {
+    sqlx::query(
+      "
+      INSERT INTO domain (id, name, service_type, owner_id, verified_at)
+      VALUES ($1, $2, 'project', $3, CASE WHEN $4 THEN now() END)
+      ",
+    )
+    .bind(Uuid::new_v4())
+    .bind(name)
+    .bind(owner_id)
+    .bind(verified)
+    .execute(pool)
+    .await
+    .unwrap();
+  }
+
+  #[test]
+  fn test_certificate_body_domain_names() {
+    let body: CertificateBody = serde_json::from_str(
+      r#"{"domain_name": "Example.com", "domain_names": ["www.example.com", "example.com", " "]}"#,
+    )
+    .unwrap();
+    assert_eq!(body.domain_names(), vec!["example.com", "www.example.com"]);
+
+    let body: CertificateBody = serde_json::from_str(r#"{}"#).unwrap();
+    assert!(body.domain_names().is_empty());
+  }
+
+  #[sqlx::test]
+  async fn test_new_certificate_requires_verified_domains(pool: Pool<Postgres>) {
+    let mut config = test::config();
+    config.base_domain = Some("apps.example.com".to_string());
+    let config = Box::leak(Box::new(config));
+    let (owner_id, other_owner_id) = (Uuid::new_v4(), Uuid::new_v4());
+    insert_domain(&pool, "unverified.example.com", owner_id, false).await;
+    // Even verified, the base domain belongs to the cluster.
+    insert_domain(&pool, "apps.example.com", owner_id, true).await;
+    insert_domain(&pool, "other.example.com", other_owner_id, true).await;
+    let session = Session::new(config, owner_id).unwrap();
+    let pool = Arc::new(pool);
+
+    for domain_name in [
+      "unverified.example.com",
+      "other.example.com",
+      "*.other.example.com",
+      "*.apps.example.com",
+    ] {
+      let mut headers = HeaderMap::new();
+      headers.insert(
+        "authorization",
+        HeaderValue::from_str(&format!("Bearer {}", session.token)).unwrap(),
+      );
+      let body = serde_json::from_value(serde_json::json!({ "domain_name": domain_name })).unwrap();
+      let response = api_new_certificate(
+        Extension(Arc::clone(&pool)),
+        Extension(config),
+        headers,
+        Json(body),
+      )
+      .await
+      .unwrap_err();
+      assert_eq!(response.status(), StatusCode::FORBIDDEN, "{}", domain_name);
+    }
+  }
+}
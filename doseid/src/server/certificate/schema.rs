@@ -6,6 +6,8 @@ use uuid::Uuid;
 pub struct Certificate {
   pub id: Uuid,
   pub domain_name: String,
+  /// Subject alternative names besides the primary domain name.
+  pub alt_names: Vec<String>,
   pub certificate: String,
   pub private_key: String,
   pub expires_at: DateTime<Utc>,
@@ -13,3 +15,61 @@ pub struct Certificate {
   pub updated_at: DateTime<Utc>,
   pub created_at: DateTime<Utc>,
 }
+
+/// A certificate as returned by the API, with its private key only when explicitly asked for.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CertificateResponse {
+  pub id: Uuid,
+  pub domain_name: String,
+  pub alt_names: Vec<String>,
+  pub certificate: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub private_key: Option<String>,
+  pub expires_at: DateTime<Utc>,
+  pub owner_id: Uuid,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl Certificate {
+  pub fn response(self, include_private_key: bool) -> CertificateResponse {
+    CertificateResponse {
+      id: self.id,
+      domain_name: self.domain_name,
+      alt_names: self.alt_names,
+      certificate: self.certificate,
+      private_key: include_private_key.then_some(self.private_key),
+      expires_at: self.expires_at,
+      owner_id: self.owner_id,
+      updated_at: self.updated_at,
+      created_at: self.created_at,
+    }
+  }
+}
+
+/// The issuance of a certificate, persisted so it can be followed and resumed after a restart.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CertificateOrder {
+  pub id: Uuid,
+  pub domain_names: Vec<String>,
+  pub status: CertificateOrderStatus,
+  pub failure_reason: Option<String>,
+  pub certificate_id: Option<Uuid>,
+  pub owner_id: Uuid,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+#[derive(sqlx::Type, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[sqlx(type_name = "certificate_order_status", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum CertificateOrderStatus {
+  /// Waiting for the domains to resolve to the cluster, or for challenge records to propagate.
+  PendingDns,
+  /// Every challenge is served, the CA is about to be asked to validate them.
+  ChallengeServed,
+  /// The CA is validating the challenges and issuing the certificate.
+  Validating,
+  Issued,
+  Failed,
+}
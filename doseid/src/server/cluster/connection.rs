@@ -0,0 +1,88 @@
+use crate::config::Address;
+use crate::server::cluster::tls::ClusterTls;
+use anyhow::{anyhow, Context};
+use dosei_proto::frame::{ClusterCodec, Frame, FrameKind};
+use dosei_proto::ProtoChannel;
+use futures_util::{SinkExt, StreamExt};
+use prost::Message;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_util::codec::Framed;
+use tracing::warn;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A long-lived, mutually authenticated connection to another cluster node.
+///
+/// Requests are sent one at a time and wait for the reply carrying the same correlation id.
+pub struct NodeConnection {
+  framed: Framed<TlsStream<TcpStream>, ClusterCodec>,
+  next_correlation_id: u64,
+}
+
+impl NodeConnection {
+  pub async fn connect(address: &Address, tls: &ClusterTls) -> anyhow::Result<NodeConnection> {
+    let stream = TcpStream::connect(address.to_string())
+      .await
+      .with_context(|| format!("Failed to connect to node {}", address))?;
+    stream.set_nodelay(true)?;
+    let server_name = ServerName::try_from(address.host.as_str())?.to_owned();
+    let stream = tls
+      .connector
+      .connect(server_name, stream)
+      .await
+      .with_context(|| format!("TLS handshake with node {} failed", address))?;
+    Ok(NodeConnection {
+      framed: Framed::new(stream, ClusterCodec),
+      next_correlation_id: 0,
+    })
+  }
+
+  pub async fn request<Req, Res>(&mut self, message: &Req) -> anyhow::Result<Res>
+  where
+    Req: ProtoChannel + Message,
+    Res: ProtoChannel + Message + Default,
+  {
+    self.request_with_timeout(message, REQUEST_TIMEOUT).await
+  }
+
+  /// Like [`NodeConnection::request`], for requests the node takes longer to carry out.
+  pub async fn request_with_timeout<Req, Res>(
+    &mut self,
+    message: &Req,
+    reply_timeout: Duration,
+  ) -> anyhow::Result<Res>
+  where
+    Req: ProtoChannel + Message,
+    Res: ProtoChannel + Message + Default,
+  {
+    self.next_correlation_id = self.next_correlation_id.wrapping_add(1);
+    let correlation_id = self.next_correlation_id;
+    self
+      .framed
+      .send(Frame::request(correlation_id, message))
+      .await?;
+
+    let reply = timeout(reply_timeout, self.read_reply(correlation_id))
+      .await
+      .map_err(|_| anyhow!("Timed out waiting for reply {}", correlation_id))??;
+    Ok(reply.decode_message::<Res>()?)
+  }
+
+  async fn read_reply(&mut self, correlation_id: u64) -> anyhow::Result<Frame> {
+    while let Some(frame) = self.framed.next().await {
+      let frame = frame?;
+      if frame.kind == FrameKind::Reply && frame.correlation_id == correlation_id {
+        return Ok(frame);
+      }
+      warn!(
+        "Discarding unexpected frame {} ({:?})",
+        frame.correlation_id, frame.kind
+      );
+    }
+    Err(anyhow!("Connection closed by peer"))
+  }
+}
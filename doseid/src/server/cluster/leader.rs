@@ -0,0 +1,230 @@
+use crate::config::Config;
+use crate::server::cluster::schema::Leader;
+use axum::body::Body;
+use axum::extract::Request;
+use axum::http::{HeaderName, HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use chrono::Utc;
+use sqlx::{Connection, PgConnection, Pool, Postgres};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+
+/// Key of the Postgres advisory lock held by the cluster leader.
+const LEADER_LOCK_ID: i64 = 0x646f736569;
+const ELECTION_INTERVAL: Duration = Duration::from_secs(2);
+const MAX_FORWARDED_BODY_SIZE: usize = 8 * 1024 * 1024;
+
+static IS_LEADER: AtomicBool = AtomicBool::new(false);
+
+/// Whether this node currently leads the cluster and runs the cluster-wide duties.
+pub fn is_leader() -> bool {
+  IS_LEADER.load(Ordering::SeqCst)
+}
+
+/// Campaigns for leadership by taking a session-level Postgres advisory lock on a dedicated
+/// connection. The lock is released by Postgres when the leader's session ends, letting another
+/// node take over on its next attempt.
+pub fn start_election(config: &'static Config, pool: Arc<Pool<Postgres>>) {
+  tokio::spawn(async move {
+    let mut connection: Option<PgConnection> = None;
+    loop {
+      if let Err(err) = campaign(config, Arc::clone(&pool), &mut connection).await {
+        if is_leader() {
+          error!("Lost cluster leadership: {}", err);
+          IS_LEADER.store(false, Ordering::SeqCst);
+        } else {
+          warn!("Failed to campaign for cluster leadership: {}", err);
+        }
+        connection = None;
+      }
+      sleep(ELECTION_INTERVAL).await;
+    }
+  });
+}
+
+async fn campaign(
+  config: &'static Config,
+  pool: Arc<Pool<Postgres>>,
+  connection: &mut Option<PgConnection>,
+) -> anyhow::Result<()> {
+  let connection = match connection {
+    Some(connection) => connection,
+    None => connection.insert(PgConnection::connect(&config.database_url).await?),
+  };
+  if is_leader() {
+    // The lock lives as long as this session, so a healthy connection means we still hold it.
+    return Ok(connection.ping().await?);
+  }
+  let locked = sqlx::query_scalar!("SELECT pg_try_advisory_lock($1)", LEADER_LOCK_ID)
+    .fetch_one(&mut *connection)
+    .await?
+    .unwrap_or(false);
+  if locked {
+    let leader = register_leader(config, pool).await?;
+    IS_LEADER.store(true, Ordering::SeqCst);
+    info!("This node is now the cluster leader (term {})", leader.term);
+  }
+  Ok(())
+}
+
+async fn register_leader(
+  config: &'static Config,
+  pool: Arc<Pool<Postgres>>,
+) -> anyhow::Result<Leader> {
+  let now = Utc::now();
+  let leader = sqlx::query_as!(
+    Leader,
+    "
+    INSERT INTO cluster_leader (id, node_id, address, node_address, term, updated_at, created_at)
+    VALUES (1, $1, $2, $3, 1, $4, $4)
+    ON CONFLICT (id) DO UPDATE SET
+      node_id = EXCLUDED.node_id,
+      address = EXCLUDED.address,
+      node_address = EXCLUDED.node_address,
+      term = cluster_leader.term + 1,
+      updated_at = EXCLUDED.updated_at
+    RETURNING *
+    ",
+    config.node_info.id,
+    config.advertised_address().to_string(),
+    config.advertised_node_address().to_string(),
+    now,
+  )
+  .fetch_one(&*pool)
+  .await?;
+  Ok(leader)
+}
+
+pub async fn get_leader(pool: Arc<Pool<Postgres>>) -> anyhow::Result<Option<Leader>> {
+  Ok(
+    sqlx::query_as!(Leader, "SELECT * FROM cluster_leader WHERE id = 1")
+      .fetch_optional(&*pool)
+      .await?,
+  )
+}
+
+/// Middleware for routes whose work must happen on the leader, like ACME challenges, webhooks
+/// and deploys, which are placed on the replicas only the leader tracks. Requests reaching any
+/// other node are forwarded to the leader's API.
+pub async fn forward_to_leader(request: Request, next: Next) -> Response {
+  if is_leader() {
+    return next.run(request).await;
+  }
+  let extensions = request.extensions();
+  let (Some(pool), Some(config)) = (
+    extensions.get::<Arc<Pool<Postgres>>>().cloned(),
+    extensions.get::<&'static Config>().copied(),
+  ) else {
+    return next.run(request).await;
+  };
+  let leader = match get_leader(pool).await {
+    // A leader row pointing at this node is stale, the lock is not held by anyone right now.
+    Ok(Some(leader)) if leader.node_id != config.node_info.id => leader,
+    Ok(_) => return StatusCode::SERVICE_UNAVAILABLE.into_response(),
+    Err(err) => {
+      error!("Failed to find the cluster leader: {}", err);
+      return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    }
+  };
+  match forward(&leader, request).await {
+    Ok(response) => response,
+    Err(err) => {
+      error!(
+        "Failed to forward request to leader {}: {}",
+        leader.node_id, err
+      );
+      StatusCode::BAD_GATEWAY.into_response()
+    }
+  }
+}
+
+async fn forward(leader: &Leader, request: Request) -> anyhow::Result<Response> {
+  let (parts, body) = request.into_parts();
+  let path_and_query = parts
+    .uri
+    .path_and_query()
+    .map(|path_and_query| path_and_query.as_str())
+    .unwrap_or("/");
+  let body = axum::body::to_bytes(body, MAX_FORWARDED_BODY_SIZE).await?;
+
+  let mut forwarded_request = reqwest::Client::new()
+    .request(
+      reqwest::Method::from_bytes(parts.method.as_str().as_bytes())?,
+      format!("http://{}{}", leader.address, path_and_query),
+    )
+    .body(body);
+  for (name, value) in parts.headers.iter() {
+    if name != axum::http::header::HOST {
+      forwarded_request = forwarded_request.header(name.as_str(), value.as_bytes());
+    }
+  }
+  let leader_response = forwarded_request.send().await?;
+
+  let mut response = Response::builder().status(leader_response.status().as_u16());
+  for (name, value) in leader_response.headers() {
+    if name == reqwest::header::TRANSFER_ENCODING || name == reqwest::header::CONNECTION {
+      continue;
+    }
+    response = response.header(
+      HeaderName::from_bytes(name.as_str().as_bytes())?,
+      HeaderValue::from_bytes(value.as_bytes())?,
+    );
+  }
+  Ok(response.body(Body::from(leader_response.bytes().await?))?)
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::config::{advertise_host, Address};
+  use crate::server::cluster::leader::forward;
+  use crate::server::cluster::schema::Leader;
+  use crate::test;
+  use axum::body::Body;
+  use axum::extract::Request;
+  use axum::http::StatusCode;
+  use axum::routing::get;
+  use axum::Router;
+  use chrono::Utc;
+  use tokio::net::TcpListener;
+  use uuid::Uuid;
+
+  #[tokio::test]
+  async fn test_forward_to_leader_bound_to_every_interface() {
+    let listener = TcpListener::bind("0.0.0.0:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let app = Router::new().route("/leader", get(|| async { "leader" }));
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let mut config = test::config();
+    config.address = Address {
+      host: "0.0.0.0".to_string(),
+      port,
+    };
+    config.advertise_host = advertise_host(&config.address.host);
+    let leader = Leader {
+      id: 1,
+      node_id: Uuid::new_v4(),
+      address: config.advertised_address().to_string(),
+      node_address: config.advertised_node_address().to_string(),
+      term: 1,
+      updated_at: Utc::now(),
+      created_at: Utc::now(),
+    };
+    assert!(!leader.address.starts_with("0.0.0.0"));
+
+    let request = Request::builder()
+      .uri("/leader")
+      .body(Body::empty())
+      .unwrap();
+    let response = forward(&leader, request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), 1024)
+      .await
+      .unwrap();
+    assert_eq!(&body[..], b"leader");
+  }
+}
@@ -0,0 +1,682 @@
+mod connection;
+pub(crate) mod leader;
+pub(crate) mod placement;
+mod resources;
+pub(crate) mod route;
+mod schema;
+pub(crate) mod tls;
+
+use crate::config;
+use crate::config::{Address, ClusterConfig, Config, CLUSTER_DATA_PATH};
+use crate::docker;
+use crate::server::cluster::connection::NodeConnection;
+use crate::server::cluster::leader::{get_leader, is_leader, start_election};
+use crate::server::cluster::resources::node_resources;
+use crate::server::cluster::route::{JoinBody, JoinResponse};
+use crate::server::cluster::schema::JoinToken;
+use crate::server::cluster::tls::{
+  certificate_fingerprint, certificate_node_id, get_or_create_certificate_authority,
+  issue_node_identity, join_connector, new_node_request, ClusterTls, NodeIdentity,
+};
+use crate::server::deployment::{
+  run_deployment_container, start_deployment_container, DeploymentContainer,
+};
+use anyhow::{anyhow, Context};
+use axum::body::{to_bytes, Body};
+use axum::http::{header, Request};
+use axum::{routing, Extension, Router};
+use chrono::{DateTime, Utc};
+use dosei_proto::ack::Ack;
+use dosei_proto::deployment::{ContainerDeployed, DeployContainer, StartContainer};
+use dosei_proto::frame::{ClusterCodec, Frame, FrameKind};
+use dosei_proto::ProtoChannel;
+use dosei_proto::{cron_job, ping};
+use futures_util::{SinkExt, StreamExt};
+use home::home_dir;
+use hyper::client::conn::http1;
+use hyper::server::conn::http1 as server_http1;
+use hyper_util::rt::TokioIo;
+use hyper_util::service::TowerToHyperService;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+use tokio::time::sleep;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::TlsAcceptor;
+use tokio_util::codec::Framed;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+/// Replicas pull, create and start the image before replying, which takes minutes for large images.
+const DEPLOY_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
+pub static CLUSTER_INFO: Lazy<Arc<Mutex<ClusterInfo>>> = Lazy::new(|| {
+  Arc::new(Mutex::new(ClusterInfo {
+    replicas: Vec::new(),
+  }))
+});
+
+pub async fn start_cluster(
+  config: &'static Config,
+  pool: Arc<Pool<Postgres>>,
+) -> anyhow::Result<Arc<ClusterTls>> {
+  let identity = node_identity(config, Arc::clone(&pool)).await?;
+  let tls = Arc::new(ClusterTls::new(&identity).context("Invalid cluster certificates")?);
+  start_node(config, Arc::clone(&pool), Arc::clone(&tls));
+  start_election(config, Arc::clone(&pool));
+  let cluster_tls = Arc::clone(&tls);
+  tokio::spawn(async move {
+    loop {
+      sleep(Duration::from_secs(1)).await;
+      let mut cluster_info = CLUSTER_INFO.lock().await;
+      cluster_info.check_replicas(&config.cluster, Utc::now());
+    }
+  });
+  // Every node other than the leader reports its status to the leader.
+  tokio::spawn(async move {
+    let mut connection = None;
+    loop {
+      sleep(Duration::from_secs(1)).await;
+      if is_leader() {
+        connection = None;
+        continue;
+      }
+      if let Err(err) = update_status(config, Arc::clone(&pool), &tls, &mut connection).await {
+        error!("Failed to update status on leader node: {}", err);
+        connection = None;
+      }
+    }
+  });
+  Ok(cluster_tls)
+}
+
+/// Resolves the certificates this node uses for cluster traffic.
+///
+/// The primary issues its own from the cluster certificate authority, replicas reuse the ones
+/// stored on a previous join or exchange their one-time join token for new ones.
+async fn node_identity(
+  config: &'static Config,
+  pool: Arc<Pool<Postgres>>,
+) -> anyhow::Result<NodeIdentity> {
+  if config.is_primary() {
+    let certificate_authority = get_or_create_certificate_authority(pool).await?;
+    return issue_node_identity(
+      &certificate_authority,
+      config.node_info.id,
+      &config.node_info.address.host,
+    );
+  }
+  let mut path = home_dir().unwrap_or_else(|| PathBuf::from("/tmp"));
+  path.push(CLUSTER_DATA_PATH);
+  if let Some(identity) = NodeIdentity::load(&path) {
+    return Ok(identity);
+  }
+  let join_token = config.join_token.as_ref().ok_or_else(|| {
+    anyhow!(
+      "A join token is required to join the cluster, use --connect <join-token>@<host>:<port>"
+    )
+  })?;
+  let identity = join_cluster(config, join_token).await?;
+  identity.save(&path)?;
+  info!("Successfully joined the cluster");
+  Ok(identity)
+}
+
+async fn join_cluster(config: &'static Config, join_token: &str) -> anyhow::Result<NodeIdentity> {
+  let (token, ca_fingerprint) = JoinToken::decode(join_token).ok_or_else(|| {
+    anyhow!("Invalid join token, create a new one with `dosei cluster join-token`")
+  })?;
+  let primary_address = config.get_primary_node_address();
+  let (csr, private_key) = new_node_request(config.node_info.id, &config.node_info.address.host)?;
+  let body = serde_json::to_vec(&JoinBody {
+    token: token.to_string(),
+    node_id: config.node_info.id,
+    csr,
+  })?;
+
+  let stream = TcpStream::connect(primary_address.to_string())
+    .await
+    .context("Failed to reach primary node")?;
+  let server_name = ServerName::try_from(primary_address.host.as_str())?.to_owned();
+  let stream = join_connector(ca_fingerprint)
+    .connect(server_name, stream)
+    .await
+    .context("Primary node is not part of the cluster the join token was issued for")?;
+  let (mut sender, connection) = http1::handshake(TokioIo::new(stream)).await?;
+  tokio::spawn(connection);
+  let request = Request::post("/cluster/join")
+    .header(header::HOST, primary_address.to_string())
+    .header(header::CONTENT_TYPE, "application/json")
+    .body(Body::from(body))?;
+  let response = sender.send_request(request).await?;
+  if !response.status().is_success() {
+    return Err(anyhow!(
+      "Primary node rejected the join request: {}",
+      response.status()
+    ));
+  }
+  let response = to_bytes(Body::new(response.into_body()), usize::MAX).await?;
+  let response: JoinResponse = serde_json::from_slice(&response)?;
+  if certificate_fingerprint(&response.ca_certificate)? != ca_fingerprint.to_lowercase() {
+    return Err(anyhow!(
+      "Primary node sent a certificate authority other than the one pinned by the join token"
+    ));
+  }
+  Ok(NodeIdentity {
+    ca_certificate: response.ca_certificate,
+    certificate: response.certificate,
+    private_key,
+  })
+}
+
+pub fn start_node(config: &'static Config, pool: Arc<Pool<Postgres>>, tls: Arc<ClusterTls>) {
+  let address = config.node_info.address.clone();
+  tokio::spawn(async move {
+    let listener = match TcpListener::bind((address.host.as_str(), address.port)).await {
+      Ok(listener) => listener,
+      Err(err) => {
+        error!("Failed to start node on {}: {}", address, err);
+        return;
+      }
+    };
+    loop {
+      match listener.accept().await {
+        Ok((socket, peer_address)) => {
+          tokio::spawn(handle_connection(
+            config,
+            Arc::clone(&pool),
+            tls.acceptor.clone(),
+            socket,
+            peer_address,
+          ));
+        }
+        Err(err) => error!("Failed to accept node connection: {}", err),
+      }
+    }
+  });
+}
+
+async fn handle_connection(
+  config: &'static Config,
+  pool: Arc<Pool<Postgres>>,
+  acceptor: TlsAcceptor,
+  socket: TcpStream,
+  peer_address: SocketAddr,
+) {
+  let socket = match acceptor.accept(socket).await {
+    Ok(socket) => socket,
+    Err(err) => {
+      warn!(
+        "Rejected unauthenticated node connection from {}: {}",
+        peer_address, err
+      );
+      return;
+    }
+  };
+  if socket.get_ref().1.peer_certificates().is_none() {
+    // Nodes without a certificate can only exchange their join token for one, over HTTPS.
+    let app = Router::new()
+      .route("/cluster/join", routing::post(route::api_join))
+      .layer(Extension(pool));
+    if let Err(err) = server_http1::Builder::new()
+      .serve_connection(TokioIo::new(socket), TowerToHyperService::new(app))
+      .await
+    {
+      warn!(
+        "Failed to serve join request from {}: {}",
+        peer_address, err
+      );
+    }
+    return;
+  }
+  // Nodes are only trusted to speak for themselves, as named by their certificate.
+  let peer_id = match socket
+    .get_ref()
+    .1
+    .peer_certificates()
+    .and_then(|certificates| certificates.first())
+    .map(certificate_node_id)
+  {
+    Some(Ok(peer_id)) => peer_id,
+    _ => {
+      warn!("Rejected node connection from {}: no node id", peer_address);
+      return;
+    }
+  };
+  let mut framed = Framed::new(socket, ClusterCodec);
+  while let Some(frame) = framed.next().await {
+    let frame = match frame {
+      Ok(frame) => frame,
+      Err(err) => {
+        error!("Closing connection from {}: {}", peer_address, err);
+        return;
+      }
+    };
+    if frame.kind == FrameKind::Reply {
+      warn!("Unexpected reply from {}, ignoring", peer_address);
+      continue;
+    }
+    let reply = handle_request(config, peer_id, peer_address.ip(), &frame).await;
+    if let Err(err) = framed.send(reply).await {
+      error!("Failed to reply to {}: {}", peer_address, err);
+      return;
+    }
+  }
+}
+
+async fn handle_request(
+  config: &'static Config,
+  peer_id: Uuid,
+  peer_ip: IpAddr,
+  frame: &Frame,
+) -> Frame {
+  match frame.message_id {
+    ping::Ping::PROTO_ID => match frame.decode_message::<ping::Ping>() {
+      Ok(received_data) if received_data.id != peer_id.to_string() => {
+        warn!(
+          "Node {} reported the status of node {}, ignoring",
+          peer_id, received_data.id
+        );
+        frame.reply(&Ack::error("Node id does not match its certificate"))
+      }
+      Ok(received_data) => {
+        let mut cluster_info = CLUSTER_INFO.lock().await;
+        cluster_info.add_or_update_replica(received_data, peer_ip, Utc::now());
+        frame.reply(&Ack::ok())
+      }
+      Err(err) => {
+        error!("Failed to decode ClusterNode: {}", err);
+        frame.reply(&Ack::error(err))
+      }
+    },
+    cron_job::CronJob::PROTO_ID => match frame.decode_message::<cron_job::CronJob>() {
+      Ok(received_data) => {
+        info!("Received CronJob: {:?}", received_data);
+        frame.reply(&Ack::ok())
+      }
+      Err(err) => {
+        error!("Failed to decode CronJob: {}", err);
+        frame.reply(&Ack::error(err))
+      }
+    },
+    DeployContainer::PROTO_ID => match frame.decode_message::<DeployContainer>() {
+      Ok(received_data) => frame.reply(&deploy_container(config, received_data).await),
+      Err(err) => {
+        error!("Failed to decode DeployContainer: {}", err);
+        frame.reply(&ContainerDeployed {
+          ok: false,
+          message: err.to_string(),
+          exposed_port: 0,
+          container_id: String::new(),
+        })
+      }
+    },
+    StartContainer::PROTO_ID => match frame.decode_message::<StartContainer>() {
+      Ok(received_data) => {
+        info!(
+          "Starting container {} on this node",
+          received_data.container_id
+        );
+        match start_deployment_container(&received_data.container_id).await {
+          Ok(()) => frame.reply(&Ack::ok()),
+          Err(err) => {
+            error!(
+              "Failed to start container {}: {}",
+              received_data.container_id, err
+            );
+            frame.reply(&Ack::error(err))
+          }
+        }
+      }
+      Err(err) => {
+        error!("Failed to decode StartContainer: {}", err);
+        frame.reply(&Ack::error(err))
+      }
+    },
+    message_id => {
+      warn!("Received unknown message id: {:#04x}", message_id);
+      frame.reply(&Ack::error(format!(
+        "Unknown message id: {:#04x}",
+        message_id
+      )))
+    }
+  }
+}
+
+/// Pulls the image of a deployment placed on this node and starts its container.
+async fn deploy_container(config: &'static Config, request: DeployContainer) -> ContainerDeployed {
+  info!("Starting deployment {} on this node", request.deployment_id);
+  let result = async {
+    let credentials = docker::credentials::docker_credentials().await?;
+    docker::pull_image(&request.image, &request.tag, credentials).await?;
+    let image = format!("{}:{}", request.image, request.tag);
+    run_deployment_container(config, &image, &request.command, request.port as u16).await
+  }
+  .await;
+  match result {
+    Ok(container) => ContainerDeployed {
+      ok: true,
+      message: String::new(),
+      exposed_port: u32::from(container.exposed_port),
+      container_id: container.id,
+    },
+    Err(err) => {
+      error!(
+        "Failed to start deployment {}: {}",
+        request.deployment_id, err
+      );
+      ContainerDeployed {
+        ok: false,
+        message: err.to_string(),
+        exposed_port: 0,
+        container_id: String::new(),
+      }
+    }
+  }
+}
+
+/// Asks the node at the given cluster address to start a deployment, returning the container
+/// it started.
+pub async fn deploy_on_node(
+  tls: &ClusterTls,
+  address: &Address,
+  request: &DeployContainer,
+) -> anyhow::Result<DeploymentContainer> {
+  let mut connection = NodeConnection::connect(address, tls).await?;
+  let reply: ContainerDeployed = connection
+    .request_with_timeout(request, DEPLOY_TIMEOUT)
+    .await?;
+  if !reply.ok {
+    return Err(anyhow!(
+      "Node {} failed to deploy: {}",
+      address,
+      reply.message
+    ));
+  }
+  Ok(DeploymentContainer {
+    id: reply.container_id,
+    exposed_port: u16::try_from(reply.exposed_port).context("Invalid exposed port")?,
+  })
+}
+
+/// Asks the node at the given cluster address to start a stopped container again.
+pub async fn start_container_on_node(
+  tls: &ClusterTls,
+  address: &Address,
+  container_id: &str,
+) -> anyhow::Result<()> {
+  let mut connection = NodeConnection::connect(address, tls).await?;
+  let reply: Ack = connection
+    .request(&StartContainer {
+      container_id: container_id.to_string(),
+    })
+    .await?;
+  if !reply.ok {
+    return Err(anyhow!(
+      "Node {} failed to start container {}: {}",
+      address,
+      container_id,
+      reply.message
+    ));
+  }
+  Ok(())
+}
+
+/// The cluster address of a healthy replica, as last reported to this node.
+pub async fn replica_address(node_id: Uuid) -> Option<Address> {
+  let cluster_info = CLUSTER_INFO.lock().await;
+  cluster_info
+    .replicas
+    .iter()
+    .find(|replica| replica.status == NodeStatus::Healthy && replica.node.id == node_id.to_string())
+    .and_then(|replica| Address::parse(&replica.node.node_address))
+}
+
+async fn update_status(
+  config: &'static Config,
+  pool: Arc<Pool<Postgres>>,
+  tls: &ClusterTls,
+  connection: &mut Option<(Uuid, NodeConnection)>,
+) -> anyhow::Result<()> {
+  let node_info = ping::Ping {
+    id: config.node_info.id.to_string(),
+    node_type: i32::from(config.node_info.node_type),
+    address: config.address.to_string(),
+    version: config::VERSION.to_string(),
+    resources: Some(node_resources().await),
+    labels: config.cluster.labels.clone(),
+    node_address: config.node_info.address.to_string(),
+  };
+
+  // Until a leader is elected, status goes to the primary the node was started with.
+  let (leader_id, leader_address) = match get_leader(pool).await? {
+    Some(leader) => (
+      leader.node_id,
+      Address::parse(&leader.node_address)
+        .ok_or_else(|| anyhow!("Invalid leader address: {}", leader.node_address))?,
+    ),
+    None => (Uuid::nil(), config.get_primary_node_address()),
+  };
+  let connection = match connection {
+    Some((connected_id, connection)) if *connected_id == leader_id => connection,
+    _ => {
+      if leader_id != Uuid::nil() {
+        info!("Reporting status to leader {}", leader_id);
+      }
+      let new_connection = NodeConnection::connect(&leader_address, tls).await?;
+      &mut connection.insert((leader_id, new_connection)).1
+    }
+  };
+  let ack: Ack = connection.request(&node_info).await?;
+  if !ack.ok {
+    warn!("Leader node rejected status update: {}", ack.message);
+  }
+  Ok(())
+}
+
+/// An address a node reported, on the IP its connection came from instead of its bind host.
+fn observed_address(reported: &str, peer_ip: IpAddr) -> String {
+  match Address::parse(reported) {
+    Some(address) => Address {
+      host: peer_ip.to_string(),
+      port: address.port,
+    }
+    .to_string(),
+    None => reported.to_string(),
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct ClusterInfo {
+  pub replicas: Vec<Replica>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Replica {
+  pub node: ping::Ping,
+  pub status: NodeStatus,
+  pub last_heartbeat: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeStatus {
+  Healthy,
+  Suspect,
+  Dead,
+}
+
+impl NodeStatus {
+  fn from_last_heartbeat(
+    last_heartbeat: DateTime<Utc>,
+    now: DateTime<Utc>,
+    config: &ClusterConfig,
+  ) -> NodeStatus {
+    let elapsed = (now - last_heartbeat).to_std().unwrap_or_default();
+    if elapsed >= config.dead_timeout {
+      NodeStatus::Dead
+    } else if elapsed >= config.suspect_timeout {
+      NodeStatus::Suspect
+    } else {
+      NodeStatus::Healthy
+    }
+  }
+}
+
+impl ClusterInfo {
+  /// Records the status a replica reported. Its addresses are the ones it binds to, often
+  /// `0.0.0.0` or a loopback one, so only their ports are kept, on the IP it connected from.
+  pub fn add_or_update_replica(
+    &mut self,
+    mut replica: ping::Ping,
+    peer_ip: IpAddr,
+    now: DateTime<Utc>,
+  ) {
+    replica.address = observed_address(&replica.address, peer_ip);
+    replica.node_address = observed_address(&replica.node_address, peer_ip);
+    match self.replicas.iter_mut().find(|r| r.node.id == replica.id) {
+      Some(existing_replica) => {
+        if existing_replica.status != NodeStatus::Healthy {
+          info!("Replica {} is healthy again", replica.id);
+        }
+        existing_replica.node = replica;
+        existing_replica.status = NodeStatus::Healthy;
+        existing_replica.last_heartbeat = now;
+      }
+      None => {
+        info!("Replica {} joined from {}", replica.id, replica.address);
+        self.replicas.push(Replica {
+          node: replica,
+          status: NodeStatus::Healthy,
+          last_heartbeat: now,
+        });
+      }
+    }
+  }
+
+  /// Updates the status of every replica from its last heartbeat, evicting the ones that have
+  /// not been seen for longer than the eviction timeout.
+  pub fn check_replicas(&mut self, config: &ClusterConfig, now: DateTime<Utc>) {
+    self.replicas.retain(|replica| {
+      let elapsed = (now - replica.last_heartbeat).to_std().unwrap_or_default();
+      if elapsed >= config.eviction_timeout {
+        warn!("Evicting replica {}", replica.node.id);
+        return false;
+      }
+      true
+    });
+    for replica in self.replicas.iter_mut() {
+      let status = NodeStatus::from_last_heartbeat(replica.last_heartbeat, now, config);
+      if status != replica.status {
+        warn!("Replica {} is now {:?}", replica.node.id, status);
+        replica.status = status;
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::config::ClusterConfig;
+  use crate::server::cluster::{handle_request, ClusterInfo, NodeStatus};
+  use crate::test;
+  use chrono::{Duration, Utc};
+  use dosei_proto::ack::Ack;
+  use dosei_proto::frame::Frame;
+  use dosei_proto::ping;
+  use std::net::IpAddr;
+  use uuid::Uuid;
+
+  fn cluster_config() -> ClusterConfig {
+    ClusterConfig {
+      suspect_timeout: std::time::Duration::from_secs(5),
+      dead_timeout: std::time::Duration::from_secs(30),
+      eviction_timeout: std::time::Duration::from_secs(300),
+      labels: Default::default(),
+      admins: vec![],
+    }
+  }
+
+  fn replica_ping(id: &str) -> ping::Ping {
+    ping::Ping {
+      id: id.to_string(),
+      node_type: i32::from(ping::NodeType::Replica),
+      address: "127.0.0.1:8845".to_string(),
+      version: "0.0.0".to_string(),
+      resources: None,
+      labels: Default::default(),
+      node_address: "127.0.0.1:18845".to_string(),
+    }
+  }
+
+  fn peer_ip() -> IpAddr {
+    IpAddr::from([10, 0, 0, 2])
+  }
+
+  #[test]
+  fn test_replica_health_transitions() {
+    let config = cluster_config();
+    let now = Utc::now();
+    let mut cluster_info = ClusterInfo {
+      replicas: Vec::new(),
+    };
+    cluster_info.add_or_update_replica(replica_ping("a"), peer_ip(), now);
+    cluster_info.add_or_update_replica(replica_ping("a"), peer_ip(), now);
+    assert_eq!(cluster_info.replicas.len(), 1);
+
+    cluster_info.check_replicas(&config, now + Duration::seconds(1));
+    assert_eq!(cluster_info.replicas[0].status, NodeStatus::Healthy);
+    cluster_info.check_replicas(&config, now + Duration::seconds(10));
+    assert_eq!(cluster_info.replicas[0].status, NodeStatus::Suspect);
+    cluster_info.check_replicas(&config, now + Duration::seconds(60));
+    assert_eq!(cluster_info.replicas[0].status, NodeStatus::Dead);
+
+    cluster_info.add_or_update_replica(replica_ping("a"), peer_ip(), now + Duration::seconds(61));
+    assert_eq!(cluster_info.replicas[0].status, NodeStatus::Healthy);
+  }
+
+  #[test]
+  fn test_replica_eviction() {
+    let config = cluster_config();
+    let now = Utc::now();
+    let mut cluster_info = ClusterInfo {
+      replicas: Vec::new(),
+    };
+    cluster_info.add_or_update_replica(replica_ping("a"), peer_ip(), now);
+    cluster_info.add_or_update_replica(replica_ping("b"), peer_ip(), now + Duration::seconds(200));
+
+    cluster_info.check_replicas(&config, now + Duration::seconds(300));
+    assert_eq!(cluster_info.replicas.len(), 1);
+    assert_eq!(cluster_info.replicas[0].node.id, "b");
+  }
+
+  #[tokio::test]
+  async fn test_ping_must_match_peer_identity() {
+    let config = Box::leak(Box::new(test::config()));
+    let frame = Frame::request(1, &replica_ping(&Uuid::new_v4().to_string()));
+    let reply = handle_request(config, Uuid::new_v4(), peer_ip(), &frame).await;
+    let ack: Ack = reply.decode_message().unwrap();
+    assert!(!ack.ok);
+  }
+
+  #[test]
+  fn test_replica_addresses_use_peer_ip() {
+    let mut cluster_info = ClusterInfo {
+      replicas: Vec::new(),
+    };
+    for node_address in ["0.0.0.0:18845", "127.0.0.1:18845"] {
+      let mut ping = replica_ping("a");
+      ping.address = "0.0.0.0:8845".to_string();
+      ping.node_address = node_address.to_string();
+      cluster_info.add_or_update_replica(ping, peer_ip(), Utc::now());
+      assert_eq!(cluster_info.replicas[0].node.node_address, "10.0.0.2:18845");
+      assert_eq!(cluster_info.replicas[0].node.address, "10.0.0.2:8845");
+    }
+  }
+}
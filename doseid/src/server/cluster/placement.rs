@@ -0,0 +1,215 @@
+use crate::config::{Address, Config};
+use crate::server::cluster::resources::node_resources;
+use crate::server::cluster::{NodeStatus, CLUSTER_INFO};
+use dosei_proto::ping::NodeResources;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// A node a deployment can be placed on.
+#[derive(Debug, Clone)]
+pub struct NodeCandidate {
+  pub id: Uuid,
+  /// Cluster address of the node, `None` for this node.
+  pub address: Option<Address>,
+  pub labels: HashMap<String, String>,
+  pub resources: NodeResources,
+}
+
+/// Lists this node and every healthy replica as placement candidates.
+pub async fn node_candidates(config: &'static Config) -> Vec<NodeCandidate> {
+  let mut candidates = vec![NodeCandidate {
+    id: config.node_info.id,
+    address: None,
+    labels: config.cluster.labels.clone(),
+    resources: node_resources().await,
+  }];
+  let cluster_info = CLUSTER_INFO.lock().await;
+  for replica in &cluster_info.replicas {
+    if replica.status != NodeStatus::Healthy {
+      continue;
+    }
+    let (Ok(id), Some(address)) = (
+      Uuid::parse_str(&replica.node.id),
+      Address::parse(&replica.node.node_address),
+    ) else {
+      continue;
+    };
+    candidates.push(NodeCandidate {
+      id,
+      address: Some(address),
+      labels: replica.node.labels.clone(),
+      resources: replica.node.resources.clone().unwrap_or_default(),
+    });
+  }
+  candidates
+}
+
+/// Picks the node with the most free memory among the ones carrying every required label,
+/// preferring the one running fewer containers on a tie.
+pub fn select_node(
+  candidates: Vec<NodeCandidate>,
+  required_labels: &HashMap<String, String>,
+) -> Option<NodeCandidate> {
+  candidates
+    .into_iter()
+    .filter(|candidate| has_labels(candidate, required_labels))
+    .min_by_key(|candidate| {
+      let free_memory = candidate
+        .resources
+        .memory_total
+        .saturating_sub(candidate.resources.memory_used);
+      (Reverse(free_memory), candidate.resources.running_containers)
+    })
+}
+
+/// Places the replicas of a deployment on as many different nodes as possible, picking among
+/// the nodes with the fewest replicas the way `select_node` does.
+pub fn place_replicas(
+  candidates: Vec<NodeCandidate>,
+  required_labels: &HashMap<String, String>,
+  replicas: usize,
+) -> Vec<NodeCandidate> {
+  let candidates: Vec<NodeCandidate> = candidates
+    .into_iter()
+    .filter(|candidate| has_labels(candidate, required_labels))
+    .collect();
+  let mut placed: HashMap<Uuid, usize> = HashMap::new();
+  let mut placements = Vec::with_capacity(replicas);
+  for _ in 0..replicas {
+    let Some(fewest) = candidates
+      .iter()
+      .map(|candidate| placed.get(&candidate.id).copied().unwrap_or(0))
+      .min()
+    else {
+      break;
+    };
+    let least_placed = candidates
+      .iter()
+      .filter(|candidate| placed.get(&candidate.id).copied().unwrap_or(0) == fewest)
+      .cloned()
+      .collect();
+    let Some(node) = select_node(least_placed, required_labels) else {
+      break;
+    };
+    *placed.entry(node.id).or_default() += 1;
+    placements.push(node);
+  }
+  placements
+}
+
+fn has_labels(candidate: &NodeCandidate, required_labels: &HashMap<String, String>) -> bool {
+  required_labels
+    .iter()
+    .all(|(key, value)| candidate.labels.get(key) == Some(value))
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::server::cluster::placement::{
+    node_candidates, place_replicas, select_node, NodeCandidate,
+  };
+  use crate::server::cluster::CLUSTER_INFO;
+  use crate::test;
+  use chrono::Utc;
+  use dosei_proto::ping::{NodeResources, NodeType, Ping};
+  use std::collections::HashMap;
+  use std::net::IpAddr;
+  use uuid::Uuid;
+
+  fn candidate(
+    memory_used: u64,
+    running_containers: u32,
+    labels: &[(&str, &str)],
+  ) -> NodeCandidate {
+    NodeCandidate {
+      id: Uuid::new_v4(),
+      address: None,
+      labels: labels
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect(),
+      resources: NodeResources {
+        cpu_usage: 0.0,
+        cpu_count: 4,
+        memory_total: 8192,
+        memory_used,
+        running_containers,
+      },
+    }
+  }
+
+  #[test]
+  fn test_select_node_with_most_free_memory() {
+    let busy = candidate(6144, 1, &[]);
+    let idle = candidate(1024, 5, &[]);
+    let idle_id = idle.id;
+    let selected = select_node(vec![busy, idle], &HashMap::new()).unwrap();
+    assert_eq!(selected.id, idle_id);
+  }
+
+  #[test]
+  fn test_select_node_breaks_ties_by_running_containers() {
+    let crowded = candidate(1024, 5, &[]);
+    let quiet = candidate(1024, 1, &[]);
+    let quiet_id = quiet.id;
+    let selected = select_node(vec![crowded, quiet], &HashMap::new()).unwrap();
+    assert_eq!(selected.id, quiet_id);
+  }
+
+  #[test]
+  fn test_select_node_matching_labels() {
+    let idle = candidate(0, 0, &[("region", "eu")]);
+    let gpu = candidate(4096, 2, &[("region", "eu"), ("gpu", "true")]);
+    let gpu_id = gpu.id;
+    let mut required_labels = HashMap::new();
+    required_labels.insert("gpu".to_string(), "true".to_string());
+    let selected = select_node(vec![idle, gpu], &required_labels).unwrap();
+    assert_eq!(selected.id, gpu_id);
+
+    required_labels.insert("region".to_string(), "us".to_string());
+    assert!(select_node(vec![candidate(0, 0, &[("gpu", "true")])], &required_labels).is_none());
+  }
+
+  #[test]
+  fn test_place_replicas_spreads_across_nodes() {
+    let busy = candidate(6144, 1, &[]);
+    let idle = candidate(1024, 0, &[]);
+    let (busy_id, idle_id) = (busy.id, idle.id);
+    let placements: Vec<Uuid> = place_replicas(vec![busy, idle], &HashMap::new(), 3)
+      .into_iter()
+      .map(|node| node.id)
+      .collect();
+    assert_eq!(placements, vec![idle_id, busy_id, idle_id]);
+
+    let mut required_labels = HashMap::new();
+    required_labels.insert("gpu".to_string(), "true".to_string());
+    assert!(place_replicas(vec![candidate(0, 0, &[])], &required_labels, 2).is_empty());
+  }
+
+  #[tokio::test]
+  async fn test_node_candidates_use_observed_replica_address() {
+    let config = Box::leak(Box::new(test::config()));
+    let replica_id = Uuid::new_v4();
+    CLUSTER_INFO.lock().await.add_or_update_replica(
+      Ping {
+        id: replica_id.to_string(),
+        node_type: i32::from(NodeType::Replica),
+        address: "0.0.0.0:8844".to_string(),
+        version: "0.0.0".to_string(),
+        resources: None,
+        labels: Default::default(),
+        node_address: "0.0.0.0:18844".to_string(),
+      },
+      IpAddr::from([10, 0, 0, 2]),
+      Utc::now(),
+    );
+    let candidate = node_candidates(config)
+      .await
+      .into_iter()
+      .find(|candidate| candidate.id == replica_id)
+      .unwrap();
+    let address = candidate.address.unwrap();
+    assert_eq!((address.host.as_str(), address.port), ("10.0.0.2", 18844));
+  }
+}
@@ -0,0 +1,38 @@
+use bollard::container::ListContainersOptions;
+use bollard::Docker;
+use dosei_proto::ping::NodeResources;
+use once_cell::sync::Lazy;
+use std::sync::Mutex;
+use sysinfo::System;
+
+// CPU usage is computed between two refreshes, so the same instance is kept across heartbeats.
+static SYSTEM: Lazy<Mutex<System>> = Lazy::new(|| Mutex::new(System::new()));
+
+/// Collects the resources of this node reported on every heartbeat.
+pub async fn node_resources() -> NodeResources {
+  let mut resources = {
+    let mut system = SYSTEM.lock().unwrap();
+    system.refresh_cpu_usage();
+    system.refresh_memory();
+    NodeResources {
+      cpu_usage: system.global_cpu_info().cpu_usage(),
+      cpu_count: system.cpus().len() as u32,
+      memory_total: system.total_memory(),
+      memory_used: system.used_memory(),
+      running_containers: 0,
+    }
+  };
+  resources.running_containers = running_containers().await.unwrap_or(0);
+  resources
+}
+
+async fn running_containers() -> anyhow::Result<u32> {
+  let docker = Docker::connect_with_socket_defaults()?;
+  let containers = docker
+    .list_containers(Some(ListContainersOptions::<String> {
+      all: false,
+      ..Default::default()
+    }))
+    .await?;
+  Ok(containers.len() as u32)
+}
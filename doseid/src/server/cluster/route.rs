@@ -0,0 +1,262 @@
+use crate::config::{Config, VERSION};
+use crate::server::cluster::leader::is_leader;
+use crate::server::cluster::resources::node_resources;
+use crate::server::cluster::schema::JoinToken;
+use crate::server::cluster::tls::{
+  certificate_fingerprint, get_certificate_authority, sign_node_request,
+};
+use crate::server::cluster::{NodeStatus, CLUSTER_INFO};
+use crate::server::session::validate_session;
+use axum::http::StatusCode;
+use axum::{Extension, Json};
+use chrono::{DateTime, Duration, Utc};
+use dosei_proto::ping::{NodeResources, NodeType};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+const DEFAULT_JOIN_TOKEN_EXPIRATION_MINUTES: i64 = 60;
+
+pub async fn api_new_join_token(
+  pool: Extension<Arc<Pool<Postgres>>>,
+  config: Extension<&'static Config>,
+  headers: axum::http::HeaderMap,
+  Json(body): Json<JoinTokenBody>,
+) -> Result<Json<NewJoinToken>, StatusCode> {
+  let session = validate_session(Arc::clone(&pool), &config, headers).await?;
+  // Join tokens are exchanged for node certificates, which are trusted by the whole cluster.
+  if !config.cluster.is_admin(session.owner_id) {
+    return Err(StatusCode::FORBIDDEN);
+  }
+  let expires_in_minutes = body
+    .expires_in_minutes
+    .unwrap_or(DEFAULT_JOIN_TOKEN_EXPIRATION_MINUTES);
+  if expires_in_minutes <= 0 {
+    return Err(StatusCode::BAD_REQUEST);
+  }
+  let ca_fingerprint = get_certificate_authority(Arc::clone(&pool))
+    .await
+    .ok()
+    .flatten()
+    .and_then(|certificate_authority| {
+      certificate_fingerprint(&certificate_authority.certificate).ok()
+    })
+    .ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+  let (join_token, token) = JoinToken::new(
+    session.owner_id,
+    Utc::now() + Duration::minutes(expires_in_minutes),
+  );
+  match sqlx::query!(
+    "
+    INSERT INTO cluster_join_token (id, token_hash, owner_id, expires_at, updated_at, created_at)
+    VALUES ($1, $2, $3, $4, $5, $6)
+    ",
+    join_token.id,
+    join_token.token_hash,
+    join_token.owner_id,
+    join_token.expires_at,
+    join_token.updated_at,
+    join_token.created_at,
+  )
+  .execute(&**pool)
+  .await
+  {
+    Ok(_) => Ok(Json(NewJoinToken {
+      id: join_token.id,
+      token: JoinToken::encode(&token, &ca_fingerprint),
+      expires_at: join_token.expires_at,
+    })),
+    Err(err) => {
+      error!("Error in creating join token: {:?}", err);
+      Err(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+  }
+}
+
+/// Exchanges a join token for a node certificate, served over TLS on the cluster port only.
+pub async fn api_join(
+  pool: Extension<Arc<Pool<Postgres>>>,
+  Json(body): Json<JoinBody>,
+) -> Result<Json<JoinResponse>, StatusCode> {
+  let certificate_authority = get_certificate_authority(Arc::clone(&pool))
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+
+  // The token is consumed before anything is signed, and given back if signing fails.
+  let mut transaction = pool
+    .begin()
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+  let join_token = sqlx::query_as!(
+    JoinToken,
+    "
+    UPDATE cluster_join_token SET used_by = $1, used_at = $2, updated_at = $2
+    WHERE token_hash = $3 AND used_at IS NULL AND expires_at >= CURRENT_TIMESTAMP
+    RETURNING *
+    ",
+    body.node_id,
+    Utc::now(),
+    JoinToken::hash(&body.token),
+  )
+  .fetch_optional(&mut *transaction)
+  .await
+  .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+  .ok_or_else(|| {
+    warn!("Rejected join request from {}: invalid token", body.node_id);
+    StatusCode::UNAUTHORIZED
+  })?;
+  let certificate =
+    sign_node_request(&certificate_authority, body.node_id, &body.csr).map_err(|err| {
+      warn!("Rejected join request from {}: {}", body.node_id, err);
+      StatusCode::BAD_REQUEST
+    })?;
+  transaction
+    .commit()
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+  info!(
+    "Node {} joined the cluster using token {}",
+    body.node_id, join_token.id
+  );
+  Ok(Json(JoinResponse {
+    ca_certificate: certificate_authority.certificate,
+    certificate,
+  }))
+}
+
+pub async fn api_get_nodes(
+  pool: Extension<Arc<Pool<Postgres>>>,
+  config: Extension<&'static Config>,
+  headers: axum::http::HeaderMap,
+) -> Result<Json<Vec<ClusterNode>>, StatusCode> {
+  validate_session(Arc::clone(&pool), &config, headers).await?;
+  let mut nodes = vec![ClusterNode {
+    id: config.node_info.id.to_string(),
+    node_type: node_type_name(config.node_info.node_type),
+    address: config.address.to_string(),
+    version: VERSION.to_string(),
+    leader: is_leader(),
+    status: NodeStatus::Healthy,
+    last_heartbeat: Utc::now(),
+    resources: NodeResourcesResponse::from(node_resources().await),
+  }];
+  let cluster_info = CLUSTER_INFO.lock().await;
+  for replica in &cluster_info.replicas {
+    nodes.push(ClusterNode {
+      id: replica.node.id.clone(),
+      node_type: node_type_name(replica.node.node_type()),
+      address: replica.node.address.clone(),
+      version: replica.node.version.clone(),
+      leader: false,
+      status: replica.status,
+      last_heartbeat: replica.last_heartbeat,
+      resources: NodeResourcesResponse::from(replica.node.resources.clone().unwrap_or_default()),
+    });
+  }
+  Ok(Json(nodes))
+}
+
+fn node_type_name(node_type: NodeType) -> String {
+  node_type.as_str_name().to_lowercase()
+}
+
+#[derive(Deserialize)]
+pub struct JoinTokenBody {
+  expires_in_minutes: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct NewJoinToken {
+  id: Uuid,
+  token: String,
+  expires_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct JoinBody {
+  pub token: String,
+  pub node_id: Uuid,
+  pub csr: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct JoinResponse {
+  pub ca_certificate: String,
+  pub certificate: String,
+}
+
+#[derive(Serialize)]
+pub struct ClusterNode {
+  id: String,
+  node_type: String,
+  address: String,
+  version: String,
+  leader: bool,
+  status: NodeStatus,
+  last_heartbeat: DateTime<Utc>,
+  resources: NodeResourcesResponse,
+}
+
+#[derive(Serialize)]
+pub struct NodeResourcesResponse {
+  cpu_usage: f32,
+  cpu_count: u32,
+  memory_total: u64,
+  memory_used: u64,
+  running_containers: u32,
+}
+
+impl From<NodeResources> for NodeResourcesResponse {
+  fn from(resources: NodeResources) -> Self {
+    NodeResourcesResponse {
+      cpu_usage: resources.cpu_usage,
+      cpu_count: resources.cpu_count,
+      memory_total: resources.memory_total,
+      memory_used: resources.memory_used,
+      running_containers: resources.running_containers,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::server::cluster::route::{api_new_join_token, JoinTokenBody};
+  use crate::server::session::schema::Session;
+  use crate::test;
+  use axum::http::{HeaderMap, HeaderValue, StatusCode};
+  use axum::{Extension, Json};
+  use sqlx::postgres::PgPoolOptions;
+  use std::sync::Arc;
+  use uuid::Uuid;
+
+  #[tokio::test]
+  async fn test_new_join_token_requires_cluster_admin() {
+    let mut config = test::config();
+    config.cluster.admins = vec![Uuid::new_v4()];
+    let config = Box::leak(Box::new(config));
+    // Ordinary users are rejected before the database is ever used.
+    let pool = PgPoolOptions::new()
+      .connect_lazy("postgres://localhost/dosei")
+      .unwrap();
+    let session = Session::new(config, Uuid::new_v4()).unwrap();
+    let mut headers = HeaderMap::new();
+    headers.insert(
+      "authorization",
+      HeaderValue::from_str(&format!("Bearer {}", session.token)).unwrap(),
+    );
+    let result = api_new_join_token(
+      Extension(Arc::new(pool)),
+      Extension(config),
+      headers,
+      Json(JoinTokenBody {
+        expires_in_minutes: None,
+      }),
+    )
+    .await;
+    assert_eq!(result.err(), Some(StatusCode::FORBIDDEN));
+  }
+}
@@ -0,0 +1,106 @@
+use chrono::{DateTime, Utc};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CertificateAuthority {
+  pub id: Uuid,
+  pub certificate: String,
+  pub private_key: String,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct JoinToken {
+  pub id: Uuid,
+  pub token_hash: String,
+  pub owner_id: Uuid,
+  pub used_by: Option<Uuid>,
+  pub used_at: Option<DateTime<Utc>>,
+  pub expires_at: DateTime<Utc>,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Leader {
+  pub id: i32,
+  pub node_id: Uuid,
+  pub address: String,
+  pub node_address: String,
+  pub term: i64,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl JoinToken {
+  /// Creates a new join token, returning it alongside the plain token value.
+  pub fn new(owner_id: Uuid, expires_at: DateTime<Utc>) -> (JoinToken, String) {
+    let value: String = thread_rng()
+      .sample_iter(&Alphanumeric)
+      .take(32)
+      .map(char::from)
+      .collect();
+    let now = Utc::now();
+    let join_token = JoinToken {
+      id: Uuid::new_v4(),
+      token_hash: JoinToken::hash(&value),
+      owner_id,
+      used_by: None,
+      used_at: None,
+      expires_at,
+      updated_at: now,
+      created_at: now,
+    };
+    (join_token, value)
+  }
+
+  pub fn hash(value: &str) -> String {
+    hex::encode(Sha256::digest(value.as_bytes()))
+  }
+
+  /// The token handed out to joining nodes, pinning the certificate authority they must trust.
+  pub fn encode(value: &str, ca_fingerprint: &str) -> String {
+    format!("{}-{}", ca_fingerprint, value)
+  }
+
+  /// Splits a token handed out to joining nodes into (value, ca_fingerprint).
+  pub fn decode(token: &str) -> Option<(&str, &str)> {
+    let (ca_fingerprint, value) = token.split_once('-')?;
+    if ca_fingerprint.len() != 64 || !ca_fingerprint.chars().all(|c| c.is_ascii_hexdigit()) {
+      return None;
+    }
+    Some((value, ca_fingerprint))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::server::cluster::schema::JoinToken;
+  use chrono::Utc;
+  use uuid::Uuid;
+
+  #[test]
+  fn test_new_join_token() {
+    let (join_token, value) = JoinToken::new(Uuid::new_v4(), Utc::now());
+    assert_eq!(value.len(), 32);
+    assert_eq!(join_token.token_hash, JoinToken::hash(&value));
+    assert_ne!(join_token.token_hash, value);
+  }
+
+  #[test]
+  fn test_join_token_carries_fingerprint() {
+    let fingerprint = JoinToken::hash("certificate");
+    let token = JoinToken::encode("value", &fingerprint);
+    assert_eq!(
+      JoinToken::decode(&token),
+      Some(("value", fingerprint.as_str()))
+    );
+    assert_eq!(JoinToken::decode("value"), None);
+    assert_eq!(JoinToken::decode("abc-value"), None);
+  }
+}
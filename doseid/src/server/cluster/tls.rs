@@ -0,0 +1,485 @@
+use crate::server::cluster::schema::CertificateAuthority;
+use anyhow::{anyhow, Context};
+use chrono::Utc;
+use openssl::nid::Nid;
+use openssl::x509::X509;
+use rcgen::{
+  BasicConstraints, Certificate, CertificateParams, CertificateSigningRequest, DistinguishedName,
+  DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair, KeyUsagePurpose, SanType,
+};
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Postgres};
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Arc;
+use tokio_rustls::rustls::client::danger::{
+  HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+};
+use tokio_rustls::rustls::client::WebPkiServerVerifier;
+use tokio_rustls::rustls::crypto::{
+  ring, verify_tls12_signature, verify_tls13_signature, WebPkiSupportedAlgorithms,
+};
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{
+  CertificateError, ClientConfig, DigitallySignedStruct, Error, RootCertStore, ServerConfig,
+  SignatureScheme,
+};
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+use uuid::Uuid;
+
+const CA_CERTIFICATE_FILE: &str = "ca.pem";
+const NODE_CERTIFICATE_FILE: &str = "node.pem";
+const NODE_PRIVATE_KEY_FILE: &str = "node.key";
+
+/// The certificates a node uses to authenticate itself to the rest of the cluster.
+pub struct NodeIdentity {
+  pub ca_certificate: String,
+  pub certificate: String,
+  pub private_key: String,
+}
+
+impl NodeIdentity {
+  pub fn load(dir: &Path) -> Option<NodeIdentity> {
+    Some(NodeIdentity {
+      ca_certificate: fs::read_to_string(dir.join(CA_CERTIFICATE_FILE)).ok()?,
+      certificate: fs::read_to_string(dir.join(NODE_CERTIFICATE_FILE)).ok()?,
+      private_key: fs::read_to_string(dir.join(NODE_PRIVATE_KEY_FILE)).ok()?,
+    })
+  }
+
+  pub fn save(&self, dir: &Path) -> anyhow::Result<()> {
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join(CA_CERTIFICATE_FILE), &self.ca_certificate)?;
+    fs::write(dir.join(NODE_CERTIFICATE_FILE), &self.certificate)?;
+    let private_key_path = dir.join(NODE_PRIVATE_KEY_FILE);
+    fs::write(&private_key_path, &self.private_key)?;
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::PermissionsExt;
+      fs::set_permissions(&private_key_path, fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+  }
+}
+
+pub async fn get_or_create_certificate_authority(
+  pool: Arc<Pool<Postgres>>,
+) -> anyhow::Result<CertificateAuthority> {
+  if let Some(certificate_authority) = get_certificate_authority(Arc::clone(&pool)).await? {
+    return Ok(certificate_authority);
+  }
+  let certificate_authority = new_certificate_authority()?;
+  sqlx::query!(
+    "
+    INSERT INTO cluster_certificate_authority (id, certificate, private_key, updated_at, created_at)
+    VALUES ($1, $2, $3, $4, $5)
+    ",
+    certificate_authority.id,
+    certificate_authority.certificate,
+    certificate_authority.private_key,
+    certificate_authority.updated_at,
+    certificate_authority.created_at,
+  )
+  .execute(&*pool)
+  .await?;
+  // Another node may have raced us, the oldest certificate authority always wins.
+  get_certificate_authority(pool)
+    .await?
+    .ok_or_else(|| anyhow!("Cluster certificate authority not found"))
+}
+
+pub async fn get_certificate_authority(
+  pool: Arc<Pool<Postgres>>,
+) -> anyhow::Result<Option<CertificateAuthority>> {
+  Ok(
+    sqlx::query_as!(
+      CertificateAuthority,
+      "SELECT * FROM cluster_certificate_authority ORDER BY created_at ASC LIMIT 1"
+    )
+    .fetch_optional(&*pool)
+    .await?,
+  )
+}
+
+fn new_certificate_authority() -> anyhow::Result<CertificateAuthority> {
+  let mut params = CertificateParams::new(vec![]);
+  params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+  params.distinguished_name = DistinguishedName::new();
+  params
+    .distinguished_name
+    .push(DnType::CommonName, "Dosei Cluster CA");
+  params.key_usages = vec![
+    KeyUsagePurpose::KeyCertSign,
+    KeyUsagePurpose::CrlSign,
+    KeyUsagePurpose::DigitalSignature,
+  ];
+  let certificate = Certificate::from_params(params)?;
+  Ok(CertificateAuthority {
+    id: Uuid::new_v4(),
+    certificate: certificate.serialize_pem()?,
+    private_key: certificate.serialize_private_key_pem(),
+    updated_at: Utc::now(),
+    created_at: Utc::now(),
+  })
+}
+
+fn load_certificate_authority(
+  certificate_authority: &CertificateAuthority,
+) -> anyhow::Result<Certificate> {
+  let key_pair = KeyPair::from_pem(&certificate_authority.private_key)?;
+  let params = CertificateParams::from_ca_cert_pem(&certificate_authority.certificate, key_pair)?;
+  Ok(Certificate::from_params(params)?)
+}
+
+fn node_certificate_params(node_id: Uuid, host: &str) -> CertificateParams {
+  let mut params = CertificateParams::new(vec![]);
+  params.subject_alt_names = vec![SanType::DnsName("localhost".to_string())];
+  params.subject_alt_names.push(match host.parse::<IpAddr>() {
+    Ok(ip_address) => SanType::IpAddress(ip_address),
+    Err(_) => SanType::DnsName(host.to_string()),
+  });
+  params.distinguished_name = DistinguishedName::new();
+  params
+    .distinguished_name
+    .push(DnType::CommonName, node_id.to_string());
+  params
+}
+
+fn node_extended_key_usages() -> Vec<ExtendedKeyUsagePurpose> {
+  vec![
+    ExtendedKeyUsagePurpose::ServerAuth,
+    ExtendedKeyUsagePurpose::ClientAuth,
+  ]
+}
+
+/// Issues a node identity directly from the certificate authority, used by nodes with access to it.
+pub fn issue_node_identity(
+  certificate_authority: &CertificateAuthority,
+  node_id: Uuid,
+  host: &str,
+) -> anyhow::Result<NodeIdentity> {
+  let ca = load_certificate_authority(certificate_authority)?;
+  let mut params = node_certificate_params(node_id, host);
+  params.extended_key_usages = node_extended_key_usages();
+  let certificate = Certificate::from_params(params)?;
+  Ok(NodeIdentity {
+    ca_certificate: certificate_authority.certificate.clone(),
+    certificate: certificate.serialize_pem_with_signer(&ca)?,
+    private_key: certificate.serialize_private_key_pem(),
+  })
+}
+
+/// Generates a private key and its certificate signing request, returns (csr, private_key).
+pub fn new_node_request(node_id: Uuid, host: &str) -> anyhow::Result<(String, String)> {
+  let certificate = Certificate::from_params(node_certificate_params(node_id, host))?;
+  Ok((
+    certificate.serialize_request_pem()?,
+    certificate.serialize_private_key_pem(),
+  ))
+}
+
+pub fn sign_node_request(
+  certificate_authority: &CertificateAuthority,
+  node_id: Uuid,
+  csr: &str,
+) -> anyhow::Result<String> {
+  let ca = load_certificate_authority(certificate_authority)?;
+  let mut csr = CertificateSigningRequest::from_pem(csr)?;
+  // Never trust what the requester asks for beyond its public key and names.
+  csr.params.is_ca = IsCa::NoCa;
+  csr.params.distinguished_name = DistinguishedName::new();
+  csr
+    .params
+    .distinguished_name
+    .push(DnType::CommonName, node_id.to_string());
+  csr.params.extended_key_usages = node_extended_key_usages();
+  Ok(csr.serialize_pem_with_signer(&ca)?)
+}
+
+pub struct ClusterTls {
+  pub acceptor: TlsAcceptor,
+  pub connector: TlsConnector,
+}
+
+impl ClusterTls {
+  pub fn new(identity: &NodeIdentity) -> anyhow::Result<ClusterTls> {
+    let mut roots = RootCertStore::empty();
+    for certificate in parse_certificates(&identity.ca_certificate)? {
+      roots.add(certificate)?;
+    }
+    let roots = Arc::new(roots);
+    let certificate_chain = parse_certificates(&identity.certificate)?;
+    // The certificate authority is sent along so joining nodes can check it against their pin.
+    let mut server_chain = certificate_chain.clone();
+    server_chain.extend(parse_certificates(&identity.ca_certificate)?);
+
+    // Nodes without a certificate yet are let through, but may only ask to join the cluster.
+    let server_config = ServerConfig::builder()
+      .with_client_cert_verifier(
+        WebPkiClientVerifier::builder(Arc::clone(&roots))
+          .allow_unauthenticated()
+          .build()?,
+      )
+      .with_single_cert(server_chain, parse_private_key(&identity.private_key)?)?;
+    let client_config = ClientConfig::builder()
+      .dangerous()
+      .with_custom_certificate_verifier(Arc::new(ClusterServerVerifier {
+        inner: WebPkiServerVerifier::builder(roots).build()?,
+      }))
+      .with_client_auth_cert(certificate_chain, parse_private_key(&identity.private_key)?)?;
+
+    Ok(ClusterTls {
+      acceptor: TlsAcceptor::from(Arc::new(server_config)),
+      connector: TlsConnector::from(Arc::new(client_config)),
+    })
+  }
+}
+
+/// A connector for nodes joining the cluster, which trusts only the certificate authority with
+/// the given fingerprint, as carried in the join token.
+pub fn join_connector(ca_fingerprint: &str) -> TlsConnector {
+  let client_config = ClientConfig::builder()
+    .dangerous()
+    .with_custom_certificate_verifier(Arc::new(PinnedServerVerifier {
+      ca_fingerprint: ca_fingerprint.to_lowercase(),
+      algorithms: ring::default_provider().signature_verification_algorithms,
+    }))
+    .with_no_client_auth();
+  TlsConnector::from(Arc::new(client_config))
+}
+
+/// Hex encoded SHA-256 of the DER encoding of the first certificate in `pem`.
+pub fn certificate_fingerprint(pem: &str) -> anyhow::Result<String> {
+  let certificate = parse_certificates(pem)?
+    .into_iter()
+    .next()
+    .ok_or_else(|| anyhow!("No certificate found"))?;
+  Ok(hex::encode(Sha256::digest(certificate.as_ref())))
+}
+
+/// The id of the node a certificate was issued to, signed into its common name.
+pub fn certificate_node_id(certificate: &CertificateDer) -> anyhow::Result<Uuid> {
+  let certificate = X509::from_der(certificate.as_ref())?;
+  let common_name = certificate
+    .subject_name()
+    .entries_by_nid(Nid::COMMONNAME)
+    .next()
+    .ok_or_else(|| anyhow!("Certificate has no common name"))?;
+  Ok(Uuid::parse_str(&common_name.data().as_utf8()?)?)
+}
+
+fn parse_certificates(pem: &str) -> anyhow::Result<Vec<CertificateDer<'static>>> {
+  rustls_pemfile::certs(&mut pem.as_bytes())
+    .collect::<Result<Vec<_>, _>>()
+    .context("Failed to parse certificate")
+}
+
+fn parse_private_key(pem: &str) -> anyhow::Result<PrivateKeyDer<'static>> {
+  rustls_pemfile::private_key(&mut pem.as_bytes())
+    .context("Failed to parse private key")?
+    .ok_or_else(|| anyhow!("No private key found"))
+}
+
+/// Verifies server certificates against the cluster certificate authority only.
+///
+/// Nodes are identified by holding a certificate issued by the cluster, not by the address they
+/// are reached on, which is often a bind-all or NAT address that can't be known at join time.
+#[derive(Debug)]
+struct ClusterServerVerifier {
+  inner: Arc<WebPkiServerVerifier>,
+}
+
+impl ServerCertVerifier for ClusterServerVerifier {
+  fn verify_server_cert(
+    &self,
+    end_entity: &CertificateDer<'_>,
+    intermediates: &[CertificateDer<'_>],
+    server_name: &ServerName<'_>,
+    ocsp_response: &[u8],
+    now: UnixTime,
+  ) -> Result<ServerCertVerified, Error> {
+    match self
+      .inner
+      .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)
+    {
+      Err(Error::InvalidCertificate(CertificateError::NotValidForName)) => {
+        Ok(ServerCertVerified::assertion())
+      }
+      result => result,
+    }
+  }
+
+  fn verify_tls12_signature(
+    &self,
+    message: &[u8],
+    cert: &CertificateDer<'_>,
+    dss: &DigitallySignedStruct,
+  ) -> Result<HandshakeSignatureValid, Error> {
+    self.inner.verify_tls12_signature(message, cert, dss)
+  }
+
+  fn verify_tls13_signature(
+    &self,
+    message: &[u8],
+    cert: &CertificateDer<'_>,
+    dss: &DigitallySignedStruct,
+  ) -> Result<HandshakeSignatureValid, Error> {
+    self.inner.verify_tls13_signature(message, cert, dss)
+  }
+
+  fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+    self.inner.supported_verify_schemes()
+  }
+}
+
+/// Verifies server certificates against the certificate authority pinned by a join token, the
+/// node joining has no other way to tell the cluster's certificate authority apart from another.
+#[derive(Debug)]
+struct PinnedServerVerifier {
+  ca_fingerprint: String,
+  algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl ServerCertVerifier for PinnedServerVerifier {
+  fn verify_server_cert(
+    &self,
+    end_entity: &CertificateDer<'_>,
+    intermediates: &[CertificateDer<'_>],
+    server_name: &ServerName<'_>,
+    ocsp_response: &[u8],
+    now: UnixTime,
+  ) -> Result<ServerCertVerified, Error> {
+    let certificate_authority = intermediates
+      .iter()
+      .find(|certificate| hex::encode(Sha256::digest(certificate.as_ref())) == self.ca_fingerprint)
+      .ok_or(Error::InvalidCertificate(CertificateError::UnknownIssuer))?;
+    let mut roots = RootCertStore::empty();
+    roots
+      .add(certificate_authority.clone().into_owned())
+      .map_err(|_| Error::InvalidCertificate(CertificateError::BadEncoding))?;
+    let verifier = ClusterServerVerifier {
+      inner: WebPkiServerVerifier::builder(Arc::new(roots))
+        .build()
+        .map_err(|err| Error::General(err.to_string()))?,
+    };
+    verifier.verify_server_cert(end_entity, &[], server_name, ocsp_response, now)
+  }
+
+  fn verify_tls12_signature(
+    &self,
+    message: &[u8],
+    cert: &CertificateDer<'_>,
+    dss: &DigitallySignedStruct,
+  ) -> Result<HandshakeSignatureValid, Error> {
+    verify_tls12_signature(message, cert, dss, &self.algorithms)
+  }
+
+  fn verify_tls13_signature(
+    &self,
+    message: &[u8],
+    cert: &CertificateDer<'_>,
+    dss: &DigitallySignedStruct,
+  ) -> Result<HandshakeSignatureValid, Error> {
+    verify_tls13_signature(message, cert, dss, &self.algorithms)
+  }
+
+  fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+    self.algorithms.supported_schemes()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::server::cluster::tls::{
+    certificate_fingerprint, certificate_node_id, issue_node_identity, join_connector,
+    new_certificate_authority, new_node_request, parse_certificates, sign_node_request, ClusterTls,
+    NodeIdentity,
+  };
+  use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt};
+  use tokio_rustls::rustls::pki_types::ServerName;
+  use tokio_rustls::TlsConnector;
+  use uuid::Uuid;
+
+  async fn handshake(server: &ClusterTls, connector: &TlsConnector) -> bool {
+    let (client_stream, server_stream) = duplex(16 * 1024);
+    let acceptor = server.acceptor.clone();
+    tokio::spawn(async move {
+      if let Ok(mut stream) = acceptor.accept(server_stream).await {
+        let mut buf = [0u8; 4];
+        if stream.read_exact(&mut buf).await.is_ok() {
+          let _ = stream.write_all(&buf).await;
+          let _ = stream.flush().await;
+        }
+      }
+    });
+    let server_name = ServerName::try_from("10.0.0.1").unwrap().to_owned();
+    let mut stream = match connector.connect(server_name, client_stream).await {
+      Ok(stream) => stream,
+      Err(_) => return false,
+    };
+    let mut buf = [0u8; 4];
+    stream.write_all(b"ping").await.is_ok()
+      && stream.flush().await.is_ok()
+      && stream.read_exact(&mut buf).await.is_ok()
+      && &buf == b"ping"
+  }
+
+  #[tokio::test]
+  async fn test_nodes_from_same_cluster_authenticate() {
+    let certificate_authority = new_certificate_authority().unwrap();
+    let primary = issue_node_identity(&certificate_authority, Uuid::new_v4(), "0.0.0.0").unwrap();
+
+    let node_id = Uuid::new_v4();
+    let (csr, private_key) = new_node_request(node_id, "replica.local").unwrap();
+    let replica = NodeIdentity {
+      ca_certificate: certificate_authority.certificate.clone(),
+      certificate: sign_node_request(&certificate_authority, node_id, &csr).unwrap(),
+      private_key,
+    };
+
+    let primary_tls = ClusterTls::new(&primary).unwrap();
+    let replica_tls = ClusterTls::new(&replica).unwrap();
+    assert!(handshake(&primary_tls, &replica_tls.connector).await);
+    assert!(handshake(&replica_tls, &primary_tls.connector).await);
+  }
+
+  #[tokio::test]
+  async fn test_nodes_from_other_cluster_are_rejected() {
+    let certificate_authority = new_certificate_authority().unwrap();
+    let primary = issue_node_identity(&certificate_authority, Uuid::new_v4(), "0.0.0.0").unwrap();
+    let other_certificate_authority = new_certificate_authority().unwrap();
+    let intruder =
+      issue_node_identity(&other_certificate_authority, Uuid::new_v4(), "0.0.0.0").unwrap();
+
+    let primary_tls = ClusterTls::new(&primary).unwrap();
+    let intruder_tls = ClusterTls::new(&intruder).unwrap();
+    assert!(!handshake(&primary_tls, &intruder_tls.connector).await);
+  }
+
+  #[tokio::test]
+  async fn test_joining_node_pins_certificate_authority() {
+    let certificate_authority = new_certificate_authority().unwrap();
+    let primary = issue_node_identity(&certificate_authority, Uuid::new_v4(), "0.0.0.0").unwrap();
+    let primary_tls = ClusterTls::new(&primary).unwrap();
+
+    let fingerprint = certificate_fingerprint(&certificate_authority.certificate).unwrap();
+    assert!(handshake(&primary_tls, &join_connector(&fingerprint)).await);
+
+    let other_certificate_authority = new_certificate_authority().unwrap();
+    let other_fingerprint =
+      certificate_fingerprint(&other_certificate_authority.certificate).unwrap();
+    assert!(!handshake(&primary_tls, &join_connector(&other_fingerprint)).await);
+  }
+
+  #[test]
+  fn test_certificate_node_id() {
+    let certificate_authority = new_certificate_authority().unwrap();
+    let node_id = Uuid::new_v4();
+    let (csr, _) = new_node_request(node_id, "replica.local").unwrap();
+    let certificate = sign_node_request(&certificate_authority, node_id, &csr).unwrap();
+    let certificate = parse_certificates(&certificate).unwrap().remove(0);
+    assert_eq!(certificate_node_id(&certificate).unwrap(), node_id);
+  }
+}
@@ -3,6 +3,7 @@ mod schema;
 
 use crate::config::Config;
 use crate::docker;
+use crate::server::cluster::leader::is_leader;
 use crate::server::cron::schema::{CronJob, Job};
 use axum::Json;
 use bollard::container::{
@@ -26,7 +27,10 @@ use uuid::Uuid;
 pub fn start_job_manager(config: &'static Config, pool: Arc<Pool<Postgres>>) {
   tokio::spawn(async move {
     loop {
-      run_jobs(config, Arc::clone(&pool)).await;
+      // Jobs are scheduled by the cluster leader only, so each one runs once.
+      if is_leader() {
+        run_jobs(config, Arc::clone(&pool)).await;
+      }
       sleep(Duration::from_secs(60)).await;
     }
   });
@@ -0,0 +1,148 @@
+use crate::server::cluster::leader::is_leader;
+use crate::server::proxy::routing::notify_routing_change;
+use chrono::Utc;
+use futures_util::future::join_all;
+use sqlx::{Pool, Postgres};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time::{sleep, timeout};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+/// Failed checks in a row before an instance stops receiving requests.
+const UNHEALTHY_THRESHOLD: u32 = 3;
+
+/// Checks that the running instances of routed deployments accept connections on the port their
+/// `internal_port` is exposed on, so proxies only balance requests across healthy ones.
+pub fn start_health_checks(pool: Arc<Pool<Postgres>>) {
+  tokio::spawn(async move {
+    let mut tracker = HealthTracker::default();
+    loop {
+      sleep(HEALTH_CHECK_INTERVAL).await;
+      if !is_leader() {
+        continue;
+      }
+      if let Err(err) = check_instances(&pool, &mut tracker).await {
+        error!("Failed to check deployment instances: {}", err);
+      }
+    }
+  });
+}
+
+async fn check_instances(pool: &Pool<Postgres>, tracker: &mut HealthTracker) -> anyhow::Result<()> {
+  // The latest ready deployment of each project, and the ones domains are pinned to.
+  let instances = sqlx::query!(
+    "
+    SELECT i.id, i.exposed_host, i.exposed_port, i.healthy, d.project_id
+    FROM deployment_instance i
+    INNER JOIN deployment d ON d.id = i.deployment_id
+    WHERE d.status = 'ready' AND i.stopped_at IS NULL AND (
+      d.id IN (
+        SELECT DISTINCT ON (project_id) id
+        FROM deployment
+        WHERE status = 'ready'
+        ORDER BY project_id, created_at DESC
+      )
+      OR d.id::text IN (SELECT deployment_id FROM domain WHERE deployment_id IS NOT NULL)
+    )
+    "
+  )
+  .fetch_all(pool)
+  .await?;
+  let probes = instances.iter().map(|instance| {
+    let host = match instance.exposed_host.as_deref() {
+      None | Some("0.0.0.0") => "127.0.0.1",
+      Some(host) => host,
+    };
+    probe(format!("{}:{}", host, instance.exposed_port as u16))
+  });
+  let results = join_all(probes).await;
+  tracker.retain(&instances.iter().map(|instance| instance.id).collect());
+
+  let mut changed_projects = HashSet::new();
+  for (instance, reachable) in instances.iter().zip(results) {
+    let Some(healthy) = tracker.observe(instance.id, instance.healthy, reachable) else {
+      continue;
+    };
+    if healthy {
+      info!("Deployment instance {} is healthy again", instance.id);
+    } else {
+      warn!(
+        "Deployment instance {} failed {} health checks, taking it out of rotation",
+        instance.id, UNHEALTHY_THRESHOLD
+      );
+    }
+    sqlx::query!(
+      "UPDATE deployment_instance SET healthy = $1, checked_at = $2 WHERE id = $3",
+      healthy,
+      Utc::now(),
+      instance.id
+    )
+    .execute(pool)
+    .await?;
+    changed_projects.insert(instance.project_id);
+  }
+  for project_id in changed_projects {
+    notify_routing_change(pool, project_id).await?;
+  }
+  Ok(())
+}
+
+async fn probe(address: String) -> bool {
+  matches!(
+    timeout(HEALTH_CHECK_TIMEOUT, TcpStream::connect(&address)).await,
+    Ok(Ok(_))
+  )
+}
+
+/// Consecutive failed checks by instance, kept by the leader.
+#[derive(Debug, Default)]
+struct HealthTracker {
+  failures: HashMap<Uuid, u32>,
+}
+
+impl HealthTracker {
+  /// Records a check, returning the new health of the instance if it changed. A single
+  /// successful check is enough to bring an instance back.
+  fn observe(&mut self, instance_id: Uuid, healthy: bool, reachable: bool) -> Option<bool> {
+    if reachable {
+      self.failures.remove(&instance_id);
+      return (!healthy).then_some(true);
+    }
+    let failures = self.failures.entry(instance_id).or_default();
+    *failures += 1;
+    (healthy && *failures >= UNHEALTHY_THRESHOLD).then_some(false)
+  }
+
+  /// Forgets instances that are no longer checked.
+  fn retain(&mut self, instance_ids: &HashSet<Uuid>) {
+    self.failures.retain(|id, _| instance_ids.contains(id));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::server::deployment::health::{HealthTracker, UNHEALTHY_THRESHOLD};
+  use uuid::Uuid;
+
+  #[test]
+  fn test_health_tracker() {
+    let mut tracker = HealthTracker::default();
+    let instance_id = Uuid::new_v4();
+    for _ in 1..UNHEALTHY_THRESHOLD {
+      assert_eq!(tracker.observe(instance_id, true, false), None);
+    }
+    assert_eq!(tracker.observe(instance_id, true, false), Some(false));
+    assert_eq!(tracker.observe(instance_id, false, false), None);
+    assert_eq!(tracker.observe(instance_id, false, true), Some(true));
+    assert_eq!(tracker.observe(instance_id, true, true), None);
+    // A success resets the count.
+    assert_eq!(tracker.observe(instance_id, true, false), None);
+    assert_eq!(tracker.observe(instance_id, true, true), None);
+    assert_eq!(tracker.observe(instance_id, true, false), None);
+  }
+}
@@ -0,0 +1,181 @@
+use crate::config::Config;
+use crate::server::cluster::tls::ClusterTls;
+use crate::server::cluster::{replica_address, start_container_on_node};
+use crate::server::deployment::{start_deployment_container, stop_deployment_container};
+use crate::server::proxy::routing::notify_routing_change;
+use anyhow::anyhow;
+use sqlx::{Pool, Postgres};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::sleep;
+use tracing::{error, info};
+use uuid::Uuid;
+
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Stops the containers this node runs for deployments that got no request within the idle
+/// timeout of their project. Every node stops its own, as only it can reach their Docker daemon.
+pub fn start_idle_shutdown(config: &'static Config, pool: Arc<Pool<Postgres>>) {
+  tokio::spawn(async move {
+    loop {
+      sleep(IDLE_CHECK_INTERVAL).await;
+      if let Err(err) = stop_idle_instances(config, &pool).await {
+        error!("Failed to stop idle deployments: {}", err);
+      }
+    }
+  });
+}
+
+async fn stop_idle_instances(config: &'static Config, pool: &Pool<Postgres>) -> anyhow::Result<()> {
+  let instances = sqlx::query!(
+    r#"
+    SELECT i.id, i.container_id AS "container_id!", d.id AS deployment_id, d.project_id
+    FROM deployment_instance i
+    INNER JOIN deployment d ON d.id = i.deployment_id
+    INNER JOIN project p ON p.id = d.project_id
+    WHERE i.node_id = $1 AND i.container_id IS NOT NULL AND i.stopped_at IS NULL
+      AND d.status = 'ready' AND p.idle_timeout_seconds IS NOT NULL
+      AND COALESCE(d.last_request_at, d.updated_at) < now() - make_interval(secs => p.idle_timeout_seconds)
+    "#,
+    config.node_info.id
+  )
+  .fetch_all(pool)
+  .await?;
+
+  // Marked stopped first, and only if still idle, so proxies wake the deployment instead of
+  // forwarding to a container about to stop, and a request arriving meanwhile keeps it running.
+  let mut stopping = Vec::new();
+  for instance in instances {
+    let marked = sqlx::query_scalar!(
+      r#"
+      UPDATE deployment_instance i SET stopped_at = now()
+      FROM deployment d, project p
+      WHERE i.id = $1 AND i.stopped_at IS NULL AND d.id = i.deployment_id AND p.id = d.project_id
+        AND COALESCE(d.last_request_at, d.updated_at) < now() - make_interval(secs => p.idle_timeout_seconds)
+      RETURNING i.id
+      "#,
+      instance.id
+    )
+    .fetch_optional(pool)
+    .await?;
+    if marked.is_some() {
+      stopping.push(instance);
+    }
+  }
+  let stopped_projects: HashSet<Uuid> = stopping
+    .iter()
+    .map(|instance| instance.project_id)
+    .collect();
+  for project_id in &stopped_projects {
+    notify_routing_change(pool, *project_id).await?;
+  }
+
+  let mut failed_projects = HashSet::new();
+  for instance in stopping {
+    info!(
+      "Stopping idle container {} of deployment {}",
+      instance.container_id, instance.deployment_id
+    );
+    if let Err(err) = stop_deployment_container(&instance.container_id).await {
+      error!(
+        "Failed to stop container {}: {}",
+        instance.container_id, err
+      );
+      sqlx::query!(
+        "UPDATE deployment_instance SET stopped_at = NULL WHERE id = $1",
+        instance.id
+      )
+      .execute(pool)
+      .await?;
+      failed_projects.insert(instance.project_id);
+      continue;
+    }
+    // A request may have woken it while it was stopping, it has to keep running then.
+    let woken = sqlx::query_scalar!(
+      r#"SELECT stopped_at IS NULL AS "woken!" FROM deployment_instance WHERE id = $1"#,
+      instance.id
+    )
+    .fetch_optional(pool)
+    .await?
+    .unwrap_or(false);
+    if woken {
+      if let Err(err) = start_deployment_container(&instance.container_id).await {
+        error!(
+          "Failed to start woken container {}: {}",
+          instance.container_id, err
+        );
+      }
+    }
+  }
+  for project_id in failed_projects {
+    notify_routing_change(pool, project_id).await?;
+  }
+  Ok(())
+}
+
+/// Starts the stopped containers of a ready deployment again, on whichever node runs them.
+/// Returns how many were started, or `None` if there is no such deployment.
+pub async fn wake_deployment(
+  config: &'static Config,
+  pool: &Pool<Postgres>,
+  tls: &ClusterTls,
+  deployment_id: Uuid,
+) -> anyhow::Result<Option<usize>> {
+  // Counts as a request, so the containers are not stopped again right away.
+  let Some(project_id) = sqlx::query_scalar!(
+    "UPDATE deployment SET last_request_at = now() WHERE id = $1 AND status = 'ready' RETURNING project_id",
+    deployment_id
+  )
+  .fetch_optional(pool)
+  .await?
+  else {
+    return Ok(None);
+  };
+  let instances = sqlx::query!(
+    r#"
+    SELECT id, node_id, container_id AS "container_id!"
+    FROM deployment_instance
+    WHERE deployment_id = $1 AND stopped_at IS NOT NULL AND container_id IS NOT NULL
+    "#,
+    deployment_id
+  )
+  .fetch_all(pool)
+  .await?;
+  if instances.is_empty() {
+    return Ok(Some(0));
+  }
+  info!("Waking deployment {}", deployment_id);
+  let mut started = 0;
+  for instance in &instances {
+    let result = match instance.node_id {
+      Some(node_id) if node_id != config.node_info.id => match replica_address(node_id).await {
+        Some(address) => start_container_on_node(tls, &address, &instance.container_id).await,
+        None => Err(anyhow!("Node {} is not available", node_id)),
+      },
+      _ => start_deployment_container(&instance.container_id).await,
+    };
+    if let Err(err) = result {
+      error!(
+        "Failed to start container {} of deployment {}: {}",
+        instance.container_id, deployment_id, err
+      );
+      continue;
+    }
+    sqlx::query!(
+      "UPDATE deployment_instance SET stopped_at = NULL, healthy = true WHERE id = $1",
+      instance.id
+    )
+    .execute(pool)
+    .await?;
+    started += 1;
+  }
+  if started == 0 {
+    return Err(anyhow!(
+      "None of the containers of deployment {} could be started",
+      deployment_id
+    ));
+  }
+  notify_routing_change(pool, project_id).await?;
+  Ok(Some(started))
+}
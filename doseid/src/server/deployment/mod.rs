@@ -1,2 +1,97 @@
+pub(crate) mod health;
+pub(crate) mod idle;
 pub(crate) mod route;
 pub(crate) mod schema;
+
+use crate::config::Config;
+use crate::util::network::find_available_port;
+use bollard::container::{CreateContainerOptions, StartContainerOptions, StopContainerOptions};
+use bollard::errors::Error::DockerResponseServerError;
+use bollard::models::{HostConfig, PortBinding, PortMap};
+use bollard::Docker;
+use std::collections::HashMap;
+
+/// A deployment container started on a node.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeploymentContainer {
+  pub id: String,
+  /// Host port the container port is bound to, kept when the container is stopped and started.
+  pub exposed_port: u16,
+}
+
+/// Starts a deployment container on this node, returning the host port it is exposed on.
+pub async fn run_deployment_container(
+  config: &'static Config,
+  image: &str,
+  command: &str,
+  port: u16,
+) -> anyhow::Result<DeploymentContainer> {
+  let available_host_port = find_available_port()?;
+
+  // Create the exposed port key
+  let exposed_port = format!("{}/tcp", port);
+
+  // Initialize exposed ports map
+  let empty = HashMap::new();
+  let mut exposed_ports = HashMap::new();
+  exposed_ports.insert(exposed_port.as_str(), empty);
+
+  // Initialize port bindings
+  let port_binding = vec![PortBinding {
+    host_ip: Some(config.address.host.clone()),
+    host_port: Some(available_host_port.to_string()),
+  }];
+  let mut port_map = PortMap::new();
+  port_map.insert(exposed_port.clone(), Some(port_binding));
+
+  let host_config = HostConfig {
+    port_bindings: Some(port_map),
+    ..Default::default()
+  };
+
+  let container_config = bollard::container::Config {
+    image: Some(image),
+    cmd: Some(command.split_whitespace().collect()),
+    exposed_ports: Some(exposed_ports),
+    host_config: Some(host_config),
+    tty: Some(true),
+    ..Default::default()
+  };
+
+  let docker = Docker::connect_with_socket_defaults()?;
+  let container = docker
+    .create_container(None::<CreateContainerOptions<String>>, container_config)
+    .await?;
+  docker
+    .start_container(&container.id, None::<StartContainerOptions<String>>)
+    .await?;
+  Ok(DeploymentContainer {
+    id: container.id,
+    exposed_port: available_host_port,
+  })
+}
+
+/// Starts a stopped deployment container on this node again.
+pub async fn start_deployment_container(container_id: &str) -> anyhow::Result<()> {
+  let docker = Docker::connect_with_socket_defaults()?;
+  match docker
+    .start_container(container_id, None::<StartContainerOptions<String>>)
+    .await
+  {
+    // Already started, e.g. by a concurrent wake.
+    Ok(())
+    | Err(DockerResponseServerError {
+      status_code: 304, ..
+    }) => Ok(()),
+    Err(err) => Err(err.into()),
+  }
+}
+
+/// Stops a deployment container on this node, keeping it to be started again.
+pub async fn stop_deployment_container(container_id: &str) -> anyhow::Result<()> {
+  let docker = Docker::connect_with_socket_defaults()?;
+  docker
+    .stop_container(container_id, Some(StopContainerOptions { t: 10 }))
+    .await?;
+  Ok(())
+}
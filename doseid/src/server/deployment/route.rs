@@ -1,23 +1,30 @@
 use crate::config::Config;
-use crate::deployment::app::import_dosei_app;
+use crate::deployment::app::{import_dosei_app, DoseiApp};
+use crate::deployment::ensure_dockerfile;
+use crate::docker;
 use crate::docker::build_image_raw;
-use crate::server::deployment::schema::{Deployment, DeploymentStatus};
+use crate::docker::credentials::docker_credentials;
+use crate::server::cluster::deploy_on_node;
+use crate::server::cluster::placement::{node_candidates, place_replicas};
+use crate::server::cluster::tls::ClusterTls;
+use crate::server::deployment::idle::wake_deployment;
+use crate::server::deployment::run_deployment_container;
+use crate::server::deployment::schema::{Deployment, DeploymentInstance, DeploymentStatus};
+use crate::server::domain::assign_preview_domains;
 use crate::server::project::create_project;
-use crate::server::session::validate_session;
+use crate::server::proxy::routing::notify_routing_change;
+use crate::server::session::{validate_proxy_token, validate_session};
 use crate::server::user::get_user;
-use crate::util::extract_tar_gz_from_memory;
-use crate::util::network::find_available_port;
-use axum::extract::Multipart;
+use crate::util::{append_to_tar_gz, extract_tar_gz_from_memory};
+use anyhow::anyhow;
+use axum::extract::{Multipart, Path};
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
-use axum::Extension;
-use bollard::container::{CreateContainerOptions, StartContainerOptions};
-use bollard::models::{HostConfig, PortBinding, PortMap};
-use bollard::Docker;
+use axum::{Extension, Json};
 use chrono::Utc;
+use dosei_proto::deployment::DeployContainer;
 use serde_json::json;
 use sqlx::{Error, Pool, Postgres};
-use std::collections::HashMap;
 use std::sync::Arc;
 use tempfile::tempdir;
 use tracing::error;
@@ -26,11 +33,12 @@ use uuid::Uuid;
 pub async fn api_deploy(
   pool: Extension<Arc<Pool<Postgres>>>,
   config: Extension<&'static Config>,
+  cluster_tls: Extension<Arc<ClusterTls>>,
   headers: axum::http::HeaderMap,
   mut multipart: Multipart,
 ) -> Result<Response, StatusCode> {
   let session = validate_session(Arc::clone(&pool), &config, headers).await?;
-  let user = get_user(session.owner_id, Arc::clone(&pool))
+  get_user(session.owner_id, Arc::clone(&pool))
     .await
     .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
@@ -43,7 +51,9 @@ pub async fn api_deploy(
     status: DeploymentStatus::Building,
     build_logs: json!({}),
     exposed_port: None,
+    exposed_host: None,
     internal_port: None,
+    node_id: None,
     updated_at: Utc::now(),
     created_at: Utc::now(),
   };
@@ -71,16 +81,34 @@ pub async fn api_deploy(
   }
   let image_tag = format!("{}/{}", Uuid::new_v4(), Uuid::new_v4());
 
-  let build_logs = build_image_raw(&image_tag, &combined_data)
+  let temp_dir = tempdir().expect("Failed to create a temp dir");
+  let temp_path = temp_dir.path();
+  extract_tar_gz_from_memory(&combined_data, temp_path)
     .await
     .map_err(|e| {
       error!("{}", e);
       StatusCode::INTERNAL_SERVER_ERROR
     })?;
+  let generated_dockerfile = ensure_dockerfile(temp_path).await.map_err(|e| {
+    error!("{}", e);
+    StatusCode::UNPROCESSABLE_ENTITY
+  })?;
+  if generated_dockerfile {
+    let dockerfile = tokio::fs::read(temp_path.join("Dockerfile"))
+      .await
+      .map_err(|e| {
+        error!("{}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+      })?;
+    combined_data = append_to_tar_gz(&combined_data, "Dockerfile", &dockerfile)
+      .await
+      .map_err(|e| {
+        error!("{}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+      })?;
+  }
 
-  let temp_dir = tempdir().expect("Failed to create a temp dir");
-  let temp_path = temp_dir.path();
-  extract_tar_gz_from_memory(&combined_data, temp_path)
+  let build_logs = build_image_raw(&image_tag, &combined_data)
     .await
     .map_err(|e| {
       error!("{}", e);
@@ -92,83 +120,192 @@ pub async fn api_deploy(
   })?;
 
   // Does this project exists? if not create
-  let project_id = match sqlx::query!(
-    "SELECT id FROM project WHERE owner_id = $1::uuid AND name = $2::text",
+  let (project_id, replicas) = match sqlx::query!(
+    "SELECT id, replicas FROM project WHERE owner_id = $1::uuid AND name = $2::text",
     session.owner_id,
     app.name
   )
   .fetch_one(&**pool)
   .await
   {
-    Ok(result) => Ok(result.id),
+    Ok(result) => Ok((result.id, result.replicas)),
     Err(error) => match &error {
       Error::RowNotFound => {
-        match create_project(Arc::clone(&pool), app.name, session.owner_id, None).await {
-          Ok(result) => Ok(result.id),
-          Err(err) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+        match create_project(Arc::clone(&pool), app.name.clone(), session.owner_id, None).await {
+          Ok(result) => Ok((result.id, result.replicas)),
+          Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
         }
       }
       _ => Err(StatusCode::INTERNAL_SERVER_ERROR),
     },
   }?;
 
-  let available_host_port = find_available_port().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-  // Create the exposed port key
-  let exposed_port = format!("{}/tcp", &app.port);
-
-  // Initialize exposed ports map
-  let empty = HashMap::new();
-  let mut exposed_ports = HashMap::new();
-  exposed_ports.insert(exposed_port.as_str(), empty);
-
-  // Initialize port bindings
-  let port_binding = vec![PortBinding {
-    host_ip: Some("127.0.0.1".to_string()),
-    host_port: Some(available_host_port.to_string()),
-  }];
-  let mut port_map = PortMap::new();
-  port_map.insert(format!("{}/tcp", &app.port), Some(port_binding));
-
-  let host_config = HostConfig {
-    port_bindings: Some(port_map),
-    ..Default::default()
+  let candidates = node_candidates(&config).await;
+  let placements = place_replicas(candidates, &app.node_labels, replicas.max(1) as usize);
+  if placements.is_empty() {
+    error!("No node matches the labels of {}", app.name);
+    return Err(StatusCode::SERVICE_UNAVAILABLE);
+  }
+  // Replicas pull the image from the container registry, it is pushed once for all of them.
+  let replica_request = if placements.iter().any(|node| node.address.is_some()) {
+    push_deployment_image(
+      &config,
+      &image_tag,
+      session.owner_id,
+      project_id,
+      deployment.id,
+      &app,
+    )
+    .await
+    .map_err(|err| {
+      error!(
+        "Failed to push the image of deployment {}: {}",
+        deployment.id, err
+      )
+    })
+    .ok()
+  } else {
+    None
   };
-
-  let config = bollard::container::Config {
-    image: Some(image_tag.as_str()),
-    cmd: Some(app.run.split_whitespace().collect()),
-    exposed_ports: Some(exposed_ports),
-    host_config: Some(host_config),
-    tty: Some(true),
-    ..Default::default()
+  let mut instances = Vec::with_capacity(placements.len());
+  for node in placements {
+    // Proxies on any node reach instances there: replicas on the IP the cluster observed them
+    // on, as they only know the host they bind to.
+    let exposed_host = match &node.address {
+      None => config.exposed_host(),
+      Some(address) => address.host.clone(),
+    };
+    let container = match &node.address {
+      None => run_deployment_container(&config, &image_tag, &app.run, app.port).await,
+      Some(address) => match &replica_request {
+        Some(request) => deploy_on_node(&cluster_tls, address, request).await,
+        None => Err(anyhow!("The image is not in the container registry")),
+      },
+    };
+    match container {
+      Ok(container) => instances.push(DeploymentInstance::new(
+        deployment.id,
+        node.id,
+        exposed_host,
+        container,
+      )),
+      // The deployment goes on with the replicas that started, health checks tell the rest.
+      Err(e) => error!(
+        "Failed to start a replica of deployment {} on node {}: {}",
+        deployment.id, node.id, e
+      ),
+    }
+  }
+  let Some(first_instance) = instances.first() else {
+    error!("Failed to start deployment {}", deployment.id);
+    return Err(StatusCode::INTERNAL_SERVER_ERROR);
   };
-
-  let docker = Docker::connect_with_socket_defaults().unwrap();
-  let container = docker
-    .create_container(None::<CreateContainerOptions<String>>, config)
-    .await
-    .unwrap();
-
-  if let Err(e) = docker
-    .start_container(&container.id, None::<StartContainerOptions<String>>)
+  for instance in &instances {
+    sqlx::query!(
+      "
+      INSERT INTO deployment_instance (id, deployment_id, node_id, container_id, exposed_host, exposed_port, healthy, created_at)
+      VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+      ",
+      instance.id,
+      instance.deployment_id,
+      instance.node_id,
+      instance.container_id,
+      instance.exposed_host,
+      instance.exposed_port,
+      instance.healthy,
+      instance.created_at
+    )
+    .execute(&**pool)
     .await
-  {
-    error!("Error starting container: {:?}", e)
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
   }
+  // The first instance is kept on the deployment, for clients unaware of replicas.
   sqlx::query_as!(
     Deployment,
-    "UPDATE deployment SET status = $1, updated_at = $2, build_logs = $3, exposed_port = $4, internal_port = $5, project_id = $6 WHERE id = $7::uuid",
+    "UPDATE deployment SET status = $1, updated_at = $2, build_logs = $3, exposed_port = $4, exposed_host = $5, internal_port = $6, project_id = $7, node_id = $8 WHERE id = $9::uuid",
     DeploymentStatus::Ready as DeploymentStatus,
     Utc::now(),
     json!(build_logs),
-    Some(available_host_port as i16),
+    Some(first_instance.exposed_port),
+    first_instance.exposed_host.clone(),
     Some(app.port as i16),
     project_id,
+    first_instance.node_id,
     deployment.id,
   )
   .execute(&**pool)
   .await
   .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-  Ok(StatusCode::CREATED.into_response())
+  let urls = assign_preview_domains(
+    &config,
+    &pool,
+    session.owner_id,
+    project_id,
+    &app.name,
+    deployment.id,
+  )
+  .await
+  .unwrap_or_else(|err| {
+    error!(
+      "Failed to assign preview domains to {}: {}",
+      deployment.id, err
+    );
+    vec![]
+  });
+  if let Err(err) = notify_routing_change(&pool, project_id).await {
+    error!("Failed to notify routing change: {}", err);
+  }
+  Ok(
+    (
+      StatusCode::CREATED,
+      Json(json!({"id": deployment.id, "urls": urls})),
+    )
+      .into_response(),
+  )
+}
+
+/// Starts the containers of a deployment stopped for being idle, for Dosei Proxy instances
+/// holding a request to it.
+pub async fn api_wake_deployment(
+  pool: Extension<Arc<Pool<Postgres>>>,
+  config: Extension<&'static Config>,
+  cluster_tls: Extension<Arc<ClusterTls>>,
+  headers: axum::http::HeaderMap,
+  Path(deployment_id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+  validate_proxy_token(&config, &headers)?;
+  match wake_deployment(&config, &pool, &cluster_tls, deployment_id).await {
+    Ok(Some(started)) => Ok(Json(json!({"started": started}))),
+    Ok(None) => Err(StatusCode::NOT_FOUND),
+    Err(err) => {
+      error!("Failed to wake deployment {}: {}", deployment_id, err);
+      Err(StatusCode::SERVICE_UNAVAILABLE)
+    }
+  }
+}
+
+/// Pushes the deployment image to the container registry, returning the request starting it on
+/// a replica.
+async fn push_deployment_image(
+  config: &'static Config,
+  image_tag: &str,
+  owner_id: Uuid,
+  project_id: Uuid,
+  deployment_id: Uuid,
+  app: &DoseiApp,
+) -> anyhow::Result<DeployContainer> {
+  let image_name = format!(
+    "{}/{}/{}",
+    &config.container_registry_url, owner_id, project_id
+  );
+  let tag = deployment_id.to_string();
+  docker::tag_image(image_tag, &image_name, &tag).await?;
+  docker::push_image(&image_name, &tag, docker_credentials().await?).await?;
+  Ok(DeployContainer {
+    deployment_id: deployment_id.to_string(),
+    image: image_name,
+    tag,
+    command: app.run.clone(),
+    port: u32::from(app.port),
+  })
 }
@@ -1,3 +1,4 @@
+use crate::server::deployment::DeploymentContainer;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -13,11 +14,51 @@ pub struct Deployment {
   pub status: DeploymentStatus,
   pub build_logs: Value,
   pub exposed_port: Option<i16>,
+  pub exposed_host: Option<String>,
   pub internal_port: Option<i16>,
+  pub node_id: Option<Uuid>,
   pub updated_at: DateTime<Utc>,
   pub created_at: DateTime<Utc>,
 }
 
+/// A container of a deployment, one per replica.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DeploymentInstance {
+  pub id: Uuid,
+  pub deployment_id: Uuid,
+  pub node_id: Option<Uuid>,
+  pub container_id: Option<String>,
+  pub exposed_host: Option<String>,
+  pub exposed_port: i16,
+  pub healthy: bool,
+  pub checked_at: Option<DateTime<Utc>>,
+  /// Set while the container is stopped for being idle, until a request wakes it.
+  pub stopped_at: Option<DateTime<Utc>>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl DeploymentInstance {
+  pub fn new(
+    deployment_id: Uuid,
+    node_id: Uuid,
+    exposed_host: String,
+    container: DeploymentContainer,
+  ) -> DeploymentInstance {
+    DeploymentInstance {
+      id: Uuid::new_v4(),
+      deployment_id,
+      node_id: Some(node_id),
+      container_id: Some(container.id),
+      exposed_host: Some(exposed_host),
+      exposed_port: container.exposed_port as i16,
+      healthy: true,
+      checked_at: None,
+      stopped_at: None,
+      created_at: Utc::now(),
+    }
+  }
+}
+
 #[derive(sqlx::Type, Serialize, Deserialize, Debug)]
 #[sqlx(type_name = "deployment_status", rename_all = "lowercase")]
 pub enum DeploymentStatus {
@@ -1 +1,284 @@
-mod schema;
+pub(crate) mod route;
+pub(crate) mod schema;
+
+use crate::config::Config;
+use crate::server::certificate::new_certificate_order;
+use crate::server::cluster::leader::is_leader;
+use crate::server::domain::schema::{Domain, ServiceType};
+use crate::server::proxy::routing::notify_routing_change;
+use chrono::{Duration as ChronoDuration, Utc};
+use sqlx::{Pool, Postgres};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+use trust_dns_resolver::config::{ResolverConfig, ResolverOpts};
+use trust_dns_resolver::TokioAsyncResolver;
+use uuid::Uuid;
+
+/// Length of the deployment id suffix of preview subdomains.
+const SHORT_ID_LENGTH: usize = 8;
+const VERIFICATION_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// Domains not verified within this many days are no longer checked, and their claim is removed.
+const VERIFICATION_WINDOW_DAYS: i64 = 7;
+
+/// Verifies the domain if its TXT record is published, then routes it and orders its certificate.
+pub async fn verify_domain(
+  config: &'static Config,
+  pool: Arc<Pool<Postgres>>,
+  domain: &Domain,
+) -> anyhow::Result<bool> {
+  if !has_verification_record(domain).await {
+    return Ok(false);
+  }
+  // Verifying takes the name over from the claims of other owners.
+  let mut transaction = pool.begin().await?;
+  sqlx::query!(
+    "DELETE FROM domain WHERE name = $1 AND id != $2 AND verified_at IS NULL",
+    domain.name,
+    domain.id
+  )
+  .execute(&mut *transaction)
+  .await?;
+  match sqlx::query!(
+    "UPDATE domain SET verified_at = $2, updated_at = $2 WHERE id = $1",
+    domain.id,
+    Utc::now()
+  )
+  .execute(&mut *transaction)
+  .await
+  {
+    // Removed meanwhile by the verification of another owner.
+    Ok(result) if result.rows_affected() == 0 => return Ok(false),
+    Ok(_) => {}
+    Err(sqlx::Error::Database(err)) if err.is_unique_violation() => {
+      warn!("{} was verified by another owner first", domain.name);
+      return Ok(false);
+    }
+    Err(err) => return Err(err.into()),
+  }
+  transaction.commit().await?;
+  info!("Verified domain {}", domain.name);
+  if let Some(project_id) = domain.project_id {
+    notify_routing_change(&pool, project_id).await?;
+  }
+  let has_certificate = sqlx::query_scalar!(
+    r#"
+    SELECT EXISTS(
+      SELECT 1 FROM certificate WHERE domain_name = $1 OR $1 = ANY(alt_names)
+    ) AS "exists!"
+    "#,
+    domain.name
+  )
+  .fetch_one(&*pool)
+  .await?;
+  if !has_certificate {
+    new_certificate_order(config, pool, domain.owner_id, &[domain.name.clone()]).await?;
+  }
+  Ok(true)
+}
+
+async fn has_verification_record(domain: &Domain) -> bool {
+  let record = domain.verification_record();
+  let resolver = TokioAsyncResolver::tokio(ResolverConfig::default(), ResolverOpts::default());
+  match resolver.txt_lookup(record.name.as_str()).await {
+    // Long TXT values may be split in several strings.
+    Ok(response) => response.iter().any(|txt| {
+      let value: Vec<u8> = txt.txt_data().concat();
+      value == record.value.as_bytes()
+    }),
+    Err(_) => false,
+  }
+}
+
+/// Periodically checks the domains waiting for their verification record to be published.
+pub fn start_domain_verification(config: &'static Config, pool: Arc<Pool<Postgres>>) {
+  tokio::spawn(async move {
+    loop {
+      sleep(VERIFICATION_CHECK_INTERVAL).await;
+      if !is_leader() {
+        continue;
+      }
+      if let Err(err) = verify_domains(config, Arc::clone(&pool)).await {
+        error!("Failed to verify domains: {}", err);
+      }
+    }
+  });
+}
+
+async fn verify_domains(config: &'static Config, pool: Arc<Pool<Postgres>>) -> anyhow::Result<()> {
+  let expired = sqlx::query!(
+    "DELETE FROM domain WHERE verified_at IS NULL AND created_at <= $1",
+    Utc::now() - ChronoDuration::days(VERIFICATION_WINDOW_DAYS)
+  )
+  .execute(&*pool)
+  .await?;
+  if expired.rows_affected() > 0 {
+    info!(
+      "Removed {} domains not verified in time",
+      expired.rows_affected()
+    );
+  }
+  let domains = sqlx::query_as!(
+    Domain,
+    r#"
+    SELECT id, name, service_type AS "service_type: ServiceType", project_id, storage_id, deployment_id, owner_id, verification_token, verified_at, rate_limit_per_second, rate_limit_burst, allowed_ips, denied_ips, upstream_http2, updated_at, created_at
+    FROM domain
+    WHERE verified_at IS NULL AND created_at > $1
+    "#,
+    Utc::now() - ChronoDuration::days(VERIFICATION_WINDOW_DAYS)
+  )
+  .fetch_all(&*pool)
+  .await?;
+  for domain in domains {
+    if let Err(err) = verify_domain(config, Arc::clone(&pool), &domain).await {
+      error!("Failed to verify domain {}: {}", domain.name, err);
+    }
+  }
+  Ok(())
+}
+
+/// Routes `<project>-<short-id>.<base>` to the deployment and `<project>.<base>` to the latest
+/// deployment of its project, returning the URLs of both.
+pub async fn assign_preview_domains(
+  config: &'static Config,
+  pool: &Pool<Postgres>,
+  owner_id: Uuid,
+  project_id: Uuid,
+  project_name: &str,
+  deployment_id: Uuid,
+) -> anyhow::Result<Vec<String>> {
+  let Some(base_domain) = &config.base_domain else {
+    return Ok(vec![]);
+  };
+  let (deployment_domain_name, project_domain_name) =
+    preview_domain_names(base_domain, project_name, deployment_id);
+  let mut deployment_domain = Domain::new(&deployment_domain_name, project_id, owner_id)?;
+  deployment_domain.deployment_id = Some(deployment_id.to_string());
+  let project_domain = Domain::new(&project_domain_name, project_id, owner_id)?;
+
+  let mut urls = vec![];
+  for domain in [deployment_domain, project_domain] {
+    if insert_preview_domain(pool, &domain, project_id).await? {
+      urls.push(preview_url(pool, base_domain, &domain.name).await?);
+    } else {
+      warn!("{} already routes to another project", domain.name);
+    }
+  }
+  Ok(urls)
+}
+
+/// Inserts the domain as verified, since the base domain belongs to the cluster. Returns whether
+/// the domain routes to the project, which is not the case when another project took its name.
+async fn insert_preview_domain(
+  pool: &Pool<Postgres>,
+  domain: &Domain,
+  project_id: Uuid,
+) -> anyhow::Result<bool> {
+  // Claims of the name can't be verified, the base domain belongs to the cluster.
+  sqlx::query!(
+    "DELETE FROM domain WHERE name = $1 AND verified_at IS NULL",
+    domain.name
+  )
+  .execute(pool)
+  .await?;
+  sqlx::query!(
+    "
+    INSERT INTO domain (id, name, service_type, project_id, deployment_id, owner_id, verification_token, verified_at, updated_at, created_at)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $8, $8)
+    ON CONFLICT (name) WHERE verified_at IS NOT NULL DO NOTHING
+    ",
+    domain.id,
+    domain.name,
+    ServiceType::Project as ServiceType,
+    domain.project_id,
+    domain.deployment_id,
+    domain.owner_id,
+    domain.verification_token,
+    Utc::now()
+  )
+  .execute(pool)
+  .await?;
+  let routed_project_id = sqlx::query_scalar!(
+    "SELECT project_id FROM domain WHERE name = $1 AND verified_at IS NOT NULL",
+    domain.name
+  )
+  .fetch_one(pool)
+  .await?;
+  Ok(routed_project_id == Some(project_id))
+}
+
+/// Served over HTTPS once a certificate covers the subdomain, usually a wildcard one.
+async fn preview_url(
+  pool: &Pool<Postgres>,
+  base_domain: &str,
+  domain_name: &str,
+) -> anyhow::Result<String> {
+  let has_certificate = sqlx::query_scalar!(
+    r#"
+    SELECT EXISTS(
+      SELECT 1 FROM certificate
+      WHERE domain_name = ANY($1) OR alt_names && $1
+    ) AS "exists!"
+    "#,
+    &[domain_name.to_string(), format!("*.{}", base_domain)]
+  )
+  .fetch_one(pool)
+  .await?;
+  let scheme = if has_certificate { "https" } else { "http" };
+  Ok(format!("{}://{}", scheme, domain_name))
+}
+
+/// The preview subdomain of a deployment, and the one of the active deployment of its project.
+fn preview_domain_names(
+  base_domain: &str,
+  project_name: &str,
+  deployment_id: Uuid,
+) -> (String, String) {
+  let short_id: String = deployment_id
+    .simple()
+    .to_string()
+    .chars()
+    .take(SHORT_ID_LENGTH)
+    .collect();
+  // Leaves room for the short id within the 63 characters of a label.
+  let label = project_label(project_name, 63 - SHORT_ID_LENGTH - 1);
+  (
+    format!("{}-{}.{}", label, short_id, base_domain),
+    format!("{}.{}", label, base_domain),
+  )
+}
+
+/// The project name as a DNS label, e.g. `My_App` becomes `my-app`.
+fn project_label(project_name: &str, max_length: usize) -> String {
+  let label: String = project_name
+    .to_lowercase()
+    .chars()
+    .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+    .collect();
+  let label: String = label.trim_matches('-').chars().take(max_length).collect();
+  match label.trim_end_matches('-') {
+    "" => "app".to_string(),
+    label => label.to_string(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::server::domain::{preview_domain_names, project_label};
+  use uuid::Uuid;
+
+  #[test]
+  fn test_preview_domain_names() {
+    let deployment_id = Uuid::parse_str("3f2b8c1e-0000-4000-8000-000000000000").unwrap();
+    assert_eq!(
+      preview_domain_names("apps.example.com", "My_App", deployment_id),
+      (
+        "my-app-3f2b8c1e.apps.example.com".to_string(),
+        "my-app.apps.example.com".to_string()
+      )
+    );
+    assert_eq!(project_label("--", 54), "app");
+    assert_eq!(project_label(&"a".repeat(100), 54).len(), 54);
+  }
+}
@@ -0,0 +1,338 @@
+use crate::config::Config;
+use crate::server::certificate::notify_certificate_change;
+use crate::server::domain::schema::{Domain, DomainResponse, DomainRules, ServiceType};
+use crate::server::domain::verify_domain;
+use crate::server::proxy::routing::notify_routing_change;
+use crate::server::session::validate_session;
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::{Pool, Postgres};
+use std::sync::Arc;
+use tracing::error;
+use uuid::Uuid;
+
+pub async fn api_new_domain(
+  pool: Extension<Arc<Pool<Postgres>>>,
+  config: Extension<&'static Config>,
+  headers: axum::http::HeaderMap,
+  Json(body): Json<DomainBody>,
+) -> Result<Response, Response> {
+  let session = validate_session(Arc::clone(&pool), &config, headers)
+    .await
+    .map_err(|e| e.into_response())?;
+  let bad_request =
+    |message: String| (StatusCode::BAD_REQUEST, Json(json!({"message": message}))).into_response();
+  if body.project_id.is_none() && body.project_name.is_none() {
+    return Err(bad_request(
+      "project_id or project_name is required".to_string(),
+    ));
+  }
+  let project_id = match sqlx::query_scalar!(
+    "SELECT id FROM project WHERE owner_id = $1 AND (id = $2 OR name = $3)",
+    session.owner_id,
+    body.project_id,
+    body.project_name
+  )
+  .fetch_optional(&**pool)
+  .await
+  {
+    Ok(Some(project_id)) => project_id,
+    Ok(None) => return Err(StatusCode::NOT_FOUND.into_response()),
+    Err(err) => {
+      error!("Error in retrieving project: {:?}", err);
+      return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+    }
+  };
+  let domain = Domain::new(&body.name, project_id, session.owner_id)
+    .map_err(|e| bad_request(e.to_string()))?;
+  let conflict = || {
+    (
+      StatusCode::CONFLICT,
+      Json(json!({"message": format!("{} is already in use", body.name)})),
+    )
+      .into_response()
+  };
+  // Unverified domains of other owners are only claims, the first to verify takes the name.
+  match sqlx::query_scalar!(
+    r#"SELECT EXISTS(SELECT 1 FROM domain WHERE name = $1 AND verified_at IS NOT NULL) AS "exists!""#,
+    domain.name
+  )
+  .fetch_one(&**pool)
+  .await
+  {
+    Ok(false) => {}
+    Ok(true) => return Err(conflict()),
+    Err(err) => {
+      error!("Error in retrieving domain: {:?}", err);
+      return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+    }
+  }
+
+  let domain = match sqlx::query_as!(
+    Domain,
+    r#"
+    INSERT INTO domain (id, name, service_type, project_id, owner_id, verification_token, updated_at, created_at)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+    RETURNING id, name, service_type AS "service_type: ServiceType", project_id, storage_id, deployment_id, owner_id, verification_token, verified_at, rate_limit_per_second, rate_limit_burst, allowed_ips, denied_ips, upstream_http2, updated_at, created_at
+    "#,
+    domain.id,
+    domain.name,
+    domain.service_type as ServiceType,
+    domain.project_id,
+    domain.owner_id,
+    domain.verification_token,
+    domain.updated_at,
+    domain.created_at
+  )
+  .fetch_one(&**pool)
+  .await
+  {
+    Ok(domain) => domain,
+    Err(sqlx::Error::Database(err)) if err.is_unique_violation() => return Err(conflict()),
+    Err(err) => {
+      error!("Error in creating domain: {:?}", err);
+      return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+    }
+  };
+  // The record may already be published, otherwise it is checked again in the background.
+  let domain = match verify_domain(&config, Arc::clone(&pool), &domain).await {
+    Ok(true) => get_domain(&pool, domain.id, session.owner_id)
+      .await
+      .ok()
+      .flatten()
+      .unwrap_or(domain),
+    Ok(false) => domain,
+    Err(err) => {
+      error!("Error in verifying domain {}: {:?}", domain.name, err);
+      domain
+    }
+  };
+  Ok((StatusCode::CREATED, Json(domain.response())).into_response())
+}
+
+#[derive(Deserialize)]
+pub struct DomainBody {
+  name: String,
+  project_id: Option<Uuid>,
+  project_name: Option<String>,
+}
+
+pub async fn api_get_domains(
+  pool: Extension<Arc<Pool<Postgres>>>,
+  config: Extension<&'static Config>,
+  headers: axum::http::HeaderMap,
+) -> Result<Json<Vec<DomainResponse>>, StatusCode> {
+  let session = validate_session(Arc::clone(&pool), &config, headers).await?;
+  match sqlx::query_as!(
+    Domain,
+    r#"
+    SELECT id, name, service_type AS "service_type: ServiceType", project_id, storage_id, deployment_id, owner_id, verification_token, verified_at, rate_limit_per_second, rate_limit_burst, allowed_ips, denied_ips, upstream_http2, updated_at, created_at
+    FROM domain
+    WHERE owner_id = $1
+    ORDER BY name
+    "#,
+    session.owner_id
+  )
+  .fetch_all(&**pool)
+  .await
+  {
+    Ok(recs) => Ok(Json(recs.into_iter().map(Domain::response).collect())),
+    Err(err) => {
+      error!("Error in retrieving domains: {:?}", err);
+      Err(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+  }
+}
+
+pub async fn api_get_domain(
+  pool: Extension<Arc<Pool<Postgres>>>,
+  config: Extension<&'static Config>,
+  headers: axum::http::HeaderMap,
+  Path(domain_id): Path<Uuid>,
+) -> Result<Json<DomainResponse>, StatusCode> {
+  let session = validate_session(Arc::clone(&pool), &config, headers).await?;
+  match get_domain(&pool, domain_id, session.owner_id).await {
+    Ok(Some(domain)) => Ok(Json(domain.response())),
+    Ok(None) => Err(StatusCode::NOT_FOUND),
+    Err(err) => {
+      error!("Error in retrieving domain: {:?}", err);
+      Err(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+  }
+}
+
+async fn get_domain(
+  pool: &Pool<Postgres>,
+  domain_id: Uuid,
+  owner_id: Uuid,
+) -> Result<Option<Domain>, sqlx::Error> {
+  sqlx::query_as!(
+    Domain,
+    r#"
+    SELECT id, name, service_type AS "service_type: ServiceType", project_id, storage_id, deployment_id, owner_id, verification_token, verified_at, rate_limit_per_second, rate_limit_burst, allowed_ips, denied_ips, upstream_http2, updated_at, created_at
+    FROM domain
+    WHERE id = $1 AND owner_id = $2
+    "#,
+    domain_id,
+    owner_id
+  )
+  .fetch_optional(pool)
+  .await
+}
+
+/// Updates the settings of the domain given in the body, leaving the others as they are.
+pub async fn api_update_domain(
+  pool: Extension<Arc<Pool<Postgres>>>,
+  config: Extension<&'static Config>,
+  headers: axum::http::HeaderMap,
+  Path(domain_id): Path<Uuid>,
+  Json(body): Json<DomainPatch>,
+) -> Result<Json<DomainResponse>, StatusCode> {
+  let session = validate_session(Arc::clone(&pool), &config, headers).await?;
+  let domain = match sqlx::query_as!(
+    Domain,
+    r#"
+    UPDATE domain
+    SET upstream_http2 = COALESCE($3, upstream_http2), updated_at = $4
+    WHERE id = $1 AND owner_id = $2
+    RETURNING id, name, service_type AS "service_type: ServiceType", project_id, storage_id, deployment_id, owner_id, verification_token, verified_at, rate_limit_per_second, rate_limit_burst, allowed_ips, denied_ips, upstream_http2, updated_at, created_at
+    "#,
+    domain_id,
+    session.owner_id,
+    body.upstream_http2,
+    Utc::now()
+  )
+  .fetch_optional(&**pool)
+  .await
+  {
+    Ok(Some(domain)) => domain,
+    Ok(None) => return Err(StatusCode::NOT_FOUND),
+    Err(err) => {
+      error!("Error in updating domain: {:?}", err);
+      return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+  };
+  if let Some(project_id) = domain.project_id {
+    if let Err(err) = notify_routing_change(&pool, project_id).await {
+      error!("Failed to notify routing change: {}", err);
+    }
+  }
+  Ok(Json(domain.response()))
+}
+
+#[derive(Deserialize)]
+pub struct DomainPatch {
+  /// Forward requests to the deployment over HTTP/2 without TLS (h2c), e.g. for gRPC.
+  upstream_http2: Option<bool>,
+}
+
+/// Replaces the access rules of the domain, applied by the proxy once it reloads the route.
+pub async fn api_set_domain_rules(
+  pool: Extension<Arc<Pool<Postgres>>>,
+  config: Extension<&'static Config>,
+  headers: axum::http::HeaderMap,
+  Path(domain_id): Path<Uuid>,
+  Json(body): Json<DomainRules>,
+) -> Result<Json<DomainResponse>, Response> {
+  let session = validate_session(Arc::clone(&pool), &config, headers)
+    .await
+    .map_err(|e| e.into_response())?;
+  let rules = body.normalize().map_err(|e| {
+    (
+      StatusCode::BAD_REQUEST,
+      Json(json!({"message": e.to_string()})),
+    )
+      .into_response()
+  })?;
+  let (rate_limit_per_second, rate_limit_burst) = match &rules.rate_limit {
+    Some(rate_limit) => (Some(rate_limit.requests_per_second), Some(rate_limit.burst)),
+    None => (None, None),
+  };
+  let domain = match sqlx::query_as!(
+    Domain,
+    r#"
+    UPDATE domain
+    SET rate_limit_per_second = $3, rate_limit_burst = $4, allowed_ips = $5, denied_ips = $6, updated_at = $7
+    WHERE id = $1 AND owner_id = $2
+    RETURNING id, name, service_type AS "service_type: ServiceType", project_id, storage_id, deployment_id, owner_id, verification_token, verified_at, rate_limit_per_second, rate_limit_burst, allowed_ips, denied_ips, upstream_http2, updated_at, created_at
+    "#,
+    domain_id,
+    session.owner_id,
+    rate_limit_per_second,
+    rate_limit_burst,
+    &rules.allowed_ips,
+    &rules.denied_ips,
+    Utc::now()
+  )
+  .fetch_optional(&**pool)
+  .await
+  {
+    Ok(Some(domain)) => domain,
+    Ok(None) => return Err(StatusCode::NOT_FOUND.into_response()),
+    Err(err) => {
+      error!("Error in updating domain rules: {:?}", err);
+      return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+    }
+  };
+  if let Some(project_id) = domain.project_id {
+    if let Err(err) = notify_routing_change(&pool, project_id).await {
+      error!("Failed to notify routing change: {}", err);
+    }
+  }
+  Ok(Json(domain.response()))
+}
+
+/// Removes the domain and the certificate issued for it alone.
+pub async fn api_delete_domain(
+  pool: Extension<Arc<Pool<Postgres>>>,
+  config: Extension<&'static Config>,
+  headers: axum::http::HeaderMap,
+  Path(domain_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+  let session = validate_session(Arc::clone(&pool), &config, headers).await?;
+  let domain = match sqlx::query!(
+    "DELETE FROM domain WHERE id = $1 AND owner_id = $2 RETURNING name, project_id",
+    domain_id,
+    session.owner_id
+  )
+  .fetch_optional(&**pool)
+  .await
+  {
+    Ok(Some(domain)) => domain,
+    Ok(None) => return Err(StatusCode::NOT_FOUND),
+    Err(err) => {
+      error!("Error in deleting domain: {:?}", err);
+      return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+  };
+  if let Some(project_id) = domain.project_id {
+    if let Err(err) = notify_routing_change(&pool, project_id).await {
+      error!("Failed to notify routing change: {}", err);
+    }
+  }
+  match sqlx::query!(
+    "DELETE FROM certificate WHERE domain_name = $1 AND alt_names = '{}' AND owner_id = $2",
+    domain.name,
+    session.owner_id
+  )
+  .execute(&**pool)
+  .await
+  {
+    Ok(res) if res.rows_affected() > 0 => {
+      if let Err(err) = notify_certificate_change(&pool).await {
+        error!("Failed to notify certificate change: {}", err);
+      }
+    }
+    Ok(_) => {}
+    Err(err) => error!(
+      "Error in deleting certificate of {}: {:?}",
+      domain.name, err
+    ),
+  }
+  Ok(StatusCode::OK)
+}
@@ -1,5 +1,9 @@
 use chrono::{DateTime, Utc};
+use ipnet::IpNet;
+use rand::distributions::Alphanumeric;
+use rand::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
 use uuid::Uuid;
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -11,23 +15,237 @@ pub struct Domain {
   pub storage_id: Option<Uuid>,
   pub deployment_id: Option<String>,
   pub owner_id: Uuid,
+  pub verification_token: String,
+  pub verified_at: Option<DateTime<Utc>>,
+  pub rate_limit_per_second: Option<i32>,
+  pub rate_limit_burst: Option<i32>,
+  pub allowed_ips: Vec<String>,
+  pub denied_ips: Vec<String>,
+  pub upstream_http2: bool,
   pub updated_at: DateTime<Utc>,
   pub created_at: DateTime<Utc>,
 }
 
 impl Domain {
-  pub fn new(mut domain: Domain) -> Domain {
-    domain.name = domain.name.to_lowercase();
-    domain
+  /// A domain routing to a project, to be verified before it routes anywhere.
+  pub fn new(name: &str, project_id: Uuid, owner_id: Uuid) -> anyhow::Result<Domain> {
+    let name = name.trim().trim_end_matches('.').to_lowercase();
+    if !is_valid_domain_name(&name) {
+      return Err(anyhow::Error::msg(format!(
+        "{} is not a valid domain name",
+        name
+      )));
+    }
+    let now = Utc::now();
+    Ok(Domain {
+      id: Uuid::new_v4(),
+      name,
+      service_type: ServiceType::Project,
+      project_id: Some(project_id),
+      storage_id: None,
+      deployment_id: None,
+      owner_id,
+      verification_token: thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect(),
+      verified_at: None,
+      rate_limit_per_second: None,
+      rate_limit_burst: None,
+      allowed_ips: vec![],
+      denied_ips: vec![],
+      upstream_http2: false,
+      updated_at: now,
+      created_at: now,
+    })
   }
+
+  /// The TXT record proving the domain belongs to its owner.
+  pub fn verification_record(&self) -> VerificationRecord {
+    VerificationRecord {
+      name: format!("_dosei.{}", self.name),
+      value: format!("dosei-verification={}", self.verification_token),
+    }
+  }
+
+  pub fn rules(&self) -> DomainRules {
+    DomainRules {
+      rate_limit: self.rate_limit_per_second.zip(self.rate_limit_burst).map(
+        |(requests_per_second, burst)| RateLimit {
+          requests_per_second,
+          burst,
+        },
+      ),
+      allowed_ips: self.allowed_ips.clone(),
+      denied_ips: self.denied_ips.clone(),
+    }
+  }
+
+  pub fn response(self) -> DomainResponse {
+    DomainResponse {
+      rules: self.rules(),
+      verification_record: self
+        .verified_at
+        .is_none()
+        .then(|| self.verification_record()),
+      id: self.id,
+      name: self.name,
+      project_id: self.project_id,
+      deployment_id: self.deployment_id,
+      verified: self.verified_at.is_some(),
+      upstream_http2: self.upstream_http2,
+      verified_at: self.verified_at,
+      updated_at: self.updated_at,
+      created_at: self.created_at,
+    }
+  }
+}
+
+fn is_valid_domain_name(name: &str) -> bool {
+  let labels: Vec<&str> = name.split('.').collect();
+  name.len() <= 253
+    && labels.len() >= 2
+    && labels.iter().all(|label| {
+      !label.is_empty()
+        && label.len() <= 63
+        && !label.starts_with('-')
+        && !label.ends_with('-')
+        && label
+          .chars()
+          .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+    })
 }
 
 #[derive(sqlx::Type, Serialize, Deserialize, Debug)]
 #[sqlx(type_name = "service_type", rename_all = "lowercase")]
 pub enum ServiceType {
-  Queued,
-  Building,
-  Error,
-  Canceled,
-  Ready,
+  Project,
+  Storage,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct VerificationRecord {
+  pub name: String,
+  pub value: String,
+}
+
+/// Access rules the proxy applies to requests for a domain, before forwarding them.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct DomainRules {
+  pub rate_limit: Option<RateLimit>,
+  /// CIDR blocks clients must belong to, any client when empty.
+  #[serde(default)]
+  pub allowed_ips: Vec<String>,
+  /// CIDR blocks rejected even when allowed.
+  #[serde(default)]
+  pub denied_ips: Vec<String>,
+}
+
+impl DomainRules {
+  /// Checks the rules, turning single addresses into CIDR blocks, e.g. `10.0.0.1` into
+  /// `10.0.0.1/32`.
+  pub fn normalize(mut self) -> anyhow::Result<DomainRules> {
+    if let Some(rate_limit) = &self.rate_limit {
+      if rate_limit.requests_per_second < 1 || rate_limit.burst < 1 {
+        return Err(anyhow::Error::msg(
+          "requests_per_second and burst must be at least 1",
+        ));
+      }
+    }
+    for ips in [&mut self.allowed_ips, &mut self.denied_ips] {
+      for ip in ips.iter_mut() {
+        let network = match ip.parse::<IpNet>() {
+          Ok(network) => network,
+          Err(_) => ip
+            .parse::<IpAddr>()
+            .map(IpNet::from)
+            .map_err(|_| anyhow::Error::msg(format!("{} is not a CIDR block", ip)))?,
+        };
+        *ip = network.trunc().to_string();
+      }
+    }
+    Ok(self)
+  }
+}
+
+/// Token bucket per client IP, refilled at `requests_per_second` up to `burst` requests.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct RateLimit {
+  pub requests_per_second: i32,
+  pub burst: i32,
+}
+
+/// A domain as shown to its owner, with the record left to publish while it is unverified.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DomainResponse {
+  pub id: Uuid,
+  pub name: String,
+  pub project_id: Option<Uuid>,
+  pub deployment_id: Option<String>,
+  pub verified: bool,
+  pub verified_at: Option<DateTime<Utc>>,
+  pub verification_record: Option<VerificationRecord>,
+  pub rules: DomainRules,
+  pub upstream_http2: bool,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::server::domain::schema::{Domain, DomainRules, RateLimit};
+  use uuid::Uuid;
+
+  #[test]
+  fn test_new_domain() {
+    let domain = Domain::new(" App.Example.com. ", Uuid::default(), Uuid::default()).unwrap();
+    assert_eq!(domain.name, "app.example.com");
+    assert_eq!(domain.verification_record().name, "_dosei.app.example.com");
+    assert!(domain
+      .verification_record()
+      .value
+      .starts_with("dosei-verification="));
+    assert!(domain.response().verification_record.is_some());
+
+    for name in [
+      "localhost",
+      "*.example.com",
+      "-app.example.com",
+      "app..com",
+      "",
+    ] {
+      assert!(Domain::new(name, Uuid::default(), Uuid::default()).is_err());
+    }
+  }
+
+  #[test]
+  fn test_normalize_domain_rules() {
+    let rules = DomainRules {
+      rate_limit: Some(RateLimit {
+        requests_per_second: 10,
+        burst: 20,
+      }),
+      allowed_ips: vec!["10.1.2.3/8".to_string(), "192.168.0.1".to_string()],
+      denied_ips: vec!["2001:db8::1".to_string()],
+    }
+    .normalize()
+    .unwrap();
+    assert_eq!(rules.allowed_ips, vec!["10.0.0.0/8", "192.168.0.1/32"]);
+    assert_eq!(rules.denied_ips, vec!["2001:db8::1/128"]);
+
+    let rules = DomainRules {
+      allowed_ips: vec!["example.com".to_string()],
+      ..DomainRules::default()
+    };
+    assert!(rules.normalize().is_err());
+    let rules = DomainRules {
+      rate_limit: Some(RateLimit {
+        requests_per_second: 0,
+        burst: 1,
+      }),
+      ..DomainRules::default()
+    };
+    assert!(rules.normalize().is_err());
+  }
 }
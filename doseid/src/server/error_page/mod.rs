@@ -0,0 +1,15 @@
+pub(crate) mod route;
+pub(crate) mod schema;
+
+use sqlx::{Pool, Postgres};
+
+/// Postgres channel notified whenever an owner changes one of their error pages.
+const ERROR_PAGE_CHANNEL: &str = "dosei_error_pages";
+
+/// Tells proxies to drop the error pages they cached.
+pub async fn notify_error_page_change(pool: &Pool<Postgres>) -> anyhow::Result<()> {
+  sqlx::query!("SELECT pg_notify($1, '')", ERROR_PAGE_CHANNEL)
+    .execute(pool)
+    .await?;
+  Ok(())
+}
@@ -0,0 +1,119 @@
+use crate::config::Config;
+use crate::server::error_page::notify_error_page_change;
+use crate::server::error_page::schema::ErrorPage;
+use crate::server::session::validate_session;
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::{Pool, Postgres};
+use std::sync::Arc;
+use tracing::error;
+
+pub async fn api_get_error_pages(
+  pool: Extension<Arc<Pool<Postgres>>>,
+  config: Extension<&'static Config>,
+  headers: axum::http::HeaderMap,
+) -> Result<Json<Vec<ErrorPage>>, StatusCode> {
+  let session = validate_session(Arc::clone(&pool), &config, headers).await?;
+  match sqlx::query_as!(
+    ErrorPage,
+    "SELECT * FROM error_page WHERE owner_id = $1 ORDER BY status_code",
+    session.owner_id
+  )
+  .fetch_all(&**pool)
+  .await
+  {
+    Ok(recs) => Ok(Json(recs)),
+    Err(err) => {
+      error!("Error in retrieving error pages: {:?}", err);
+      Err(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+  }
+}
+
+/// Sets the page the proxy responds with for a status, on every domain of the owner.
+pub async fn api_set_error_page(
+  pool: Extension<Arc<Pool<Postgres>>>,
+  config: Extension<&'static Config>,
+  headers: axum::http::HeaderMap,
+  Path(status_code): Path<i16>,
+  Json(body): Json<ErrorPageBody>,
+) -> Result<Json<ErrorPage>, Response> {
+  let session = validate_session(Arc::clone(&pool), &config, headers)
+    .await
+    .map_err(|e| e.into_response())?;
+  let error_page = ErrorPage::new(session.owner_id, status_code, body.content).map_err(|e| {
+    (
+      StatusCode::BAD_REQUEST,
+      Json(json!({"message": e.to_string()})),
+    )
+      .into_response()
+  })?;
+  let error_page = match sqlx::query_as!(
+    ErrorPage,
+    r#"
+    INSERT INTO error_page (owner_id, status_code, content, updated_at, created_at)
+    VALUES ($1, $2, $3, $4, $5)
+    ON CONFLICT (owner_id, status_code)
+    DO UPDATE SET content = EXCLUDED.content, updated_at = EXCLUDED.updated_at
+    RETURNING *
+    "#,
+    error_page.owner_id,
+    error_page.status_code,
+    error_page.content,
+    error_page.updated_at,
+    error_page.created_at
+  )
+  .fetch_one(&**pool)
+  .await
+  {
+    Ok(error_page) => error_page,
+    Err(err) => {
+      error!("Error in saving error page: {:?}", err);
+      return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+    }
+  };
+  if let Err(err) = notify_error_page_change(&pool).await {
+    error!("Failed to notify error page change: {}", err);
+  }
+  Ok(Json(error_page))
+}
+
+#[derive(Deserialize)]
+pub struct ErrorPageBody {
+  /// HTML served as is.
+  content: String,
+}
+
+/// Goes back to the default page of the proxy for a status.
+pub async fn api_delete_error_page(
+  pool: Extension<Arc<Pool<Postgres>>>,
+  config: Extension<&'static Config>,
+  headers: axum::http::HeaderMap,
+  Path(status_code): Path<i16>,
+) -> Result<StatusCode, StatusCode> {
+  let session = validate_session(Arc::clone(&pool), &config, headers).await?;
+  match sqlx::query!(
+    "DELETE FROM error_page WHERE owner_id = $1 AND status_code = $2",
+    session.owner_id,
+    status_code
+  )
+  .execute(&**pool)
+  .await
+  {
+    Ok(res) if res.rows_affected() == 0 => Err(StatusCode::NOT_FOUND),
+    Ok(_) => {
+      if let Err(err) = notify_error_page_change(&pool).await {
+        error!("Failed to notify error page change: {}", err);
+      }
+      Ok(StatusCode::OK)
+    }
+    Err(err) => {
+      error!("Error in deleting error page: {:?}", err);
+      Err(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+  }
+}
@@ -0,0 +1,62 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Statuses the proxy responds with on its own, instead of an upstream.
+pub const ERROR_PAGE_STATUS_CODES: [i16; 6] = [403, 404, 429, 502, 503, 504];
+
+/// Largest page accepted, as every proxy keeps the pages it serves in memory.
+const MAX_CONTENT_LENGTH: usize = 64 * 1024;
+
+/// HTML the proxy responds with, for requests to the domains of an owner.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ErrorPage {
+  pub owner_id: Uuid,
+  pub status_code: i16,
+  pub content: String,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl ErrorPage {
+  pub fn new(owner_id: Uuid, status_code: i16, content: String) -> anyhow::Result<ErrorPage> {
+    if !ERROR_PAGE_STATUS_CODES.contains(&status_code) {
+      return Err(anyhow::Error::msg(format!(
+        "Error pages can only be set for statuses {:?}",
+        ERROR_PAGE_STATUS_CODES
+      )));
+    }
+    if content.trim().is_empty() {
+      return Err(anyhow::Error::msg("content can't be empty"));
+    }
+    if content.len() > MAX_CONTENT_LENGTH {
+      return Err(anyhow::Error::msg(format!(
+        "content can't be larger than {} bytes",
+        MAX_CONTENT_LENGTH
+      )));
+    }
+    let now = Utc::now();
+    Ok(ErrorPage {
+      owner_id,
+      status_code,
+      content,
+      updated_at: now,
+      created_at: now,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::server::error_page::schema::ErrorPage;
+  use uuid::Uuid;
+
+  #[test]
+  fn test_new_error_page() {
+    let owner_id = Uuid::new_v4();
+    assert!(ErrorPage::new(owner_id, 502, "<h1>Be right back</h1>".to_string()).is_ok());
+    assert!(ErrorPage::new(owner_id, 500, "<h1>Oops</h1>".to_string()).is_err());
+    assert!(ErrorPage::new(owner_id, 404, " ".to_string()).is_err());
+    assert!(ErrorPage::new(owner_id, 404, "a".repeat(64 * 1024 + 1)).is_err());
+  }
+}
@@ -0,0 +1,28 @@
+pub(crate) mod route;
+pub(crate) mod schema;
+
+use crate::server::event::schema::EventType;
+use sqlx::{Pool, Postgres};
+use tracing::error;
+use uuid::Uuid;
+
+/// Records something that happened in the background, so its owner can find out about it.
+pub async fn record_event(
+  pool: &Pool<Postgres>,
+  owner_id: Uuid,
+  event_type: EventType,
+  message: &str,
+) {
+  if let Err(err) = sqlx::query!(
+    "INSERT INTO event (id, event_type, message, owner_id) VALUES ($1, $2, $3, $4)",
+    Uuid::new_v4(),
+    event_type as EventType,
+    message,
+    owner_id
+  )
+  .execute(pool)
+  .await
+  {
+    error!("Failed to record {:?} event: {}", event_type, err);
+  }
+}
@@ -0,0 +1,36 @@
+use crate::config::Config;
+use crate::server::event::schema::{Event, EventType};
+use crate::server::session::validate_session;
+use axum::http::StatusCode;
+use axum::{Extension, Json};
+use sqlx::{Pool, Postgres};
+use std::sync::Arc;
+use tracing::error;
+
+pub async fn api_get_events(
+  pool: Extension<Arc<Pool<Postgres>>>,
+  config: Extension<&'static Config>,
+  headers: axum::http::HeaderMap,
+) -> Result<Json<Vec<Event>>, StatusCode> {
+  let session = validate_session(Arc::clone(&pool), &config, headers).await?;
+  match sqlx::query_as!(
+    Event,
+    r#"
+    SELECT id, event_type AS "event_type: EventType", message, owner_id, created_at
+    FROM event
+    WHERE owner_id = $1
+    ORDER BY created_at DESC
+    LIMIT 100
+    "#,
+    session.owner_id
+  )
+  .fetch_all(&**pool)
+  .await
+  {
+    Ok(recs) => Ok(Json(recs)),
+    Err(err) => {
+      error!("Error in retrieving events: {:?}", err);
+      Err(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+  }
+}
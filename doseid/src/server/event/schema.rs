@@ -0,0 +1,20 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Event {
+  pub id: Uuid,
+  pub event_type: EventType,
+  pub message: String,
+  pub owner_id: Uuid,
+  pub created_at: DateTime<Utc>,
+}
+
+#[derive(sqlx::Type, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[sqlx(type_name = "event_type", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum EventType {
+  CertificateIssued,
+  CertificateFailed,
+}
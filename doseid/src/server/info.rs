@@ -1,4 +1,5 @@
 use crate::config::{Address, Config, VERSION};
+use crate::server::cluster::leader::is_leader;
 use crate::server::cluster::CLUSTER_INFO;
 use axum::http::StatusCode;
 use axum::{Extension, Json};
@@ -12,7 +13,7 @@ pub async fn api_info(config: Extension<&'static Config>) -> Result<Json<Info>,
   Ok(Json(Info {
     server: Server {
       id: config.node_info.id,
-      mode: if config.is_primary() && cluster_info.lock().await.replicas.is_empty() {
+      mode: if is_leader() && cluster_info.lock().await.replicas.is_empty() {
         Mode::STANDALONE
       } else {
         Mode::CLUSTER
@@ -4,11 +4,14 @@ mod cluster;
 mod cron;
 mod deployment;
 mod domain;
+mod error_page;
+mod event;
 mod info;
 pub(crate) mod integration;
 mod logs;
 mod ping;
 mod project;
+mod proxy;
 mod secret;
 mod session;
 mod token;
@@ -22,7 +25,7 @@ use std::sync::Arc;
 use crate::config::Config;
 use crate::docker;
 use crate::server::app::shutdown_app;
-use axum::{routing, Extension, Router};
+use axum::{middleware, routing, Extension, Router};
 use bollard::Docker;
 use tokio::net::TcpListener;
 use tokio::signal;
@@ -44,8 +47,16 @@ pub async fn start_server(config: &'static Config) -> anyhow::Result<()> {
   let shared_pool = Arc::new(pool);
   info!("Successfully connected to Postgres");
 
-  cluster::start_cluster(config)?;
+  let cluster_tls = cluster::start_cluster(config, Arc::clone(&shared_pool))
+    .await
+    .context("Failed to start cluster")?;
   cron::start_job_manager(config, Arc::clone(&shared_pool));
+  certificate::start_certificate_renewal(config, Arc::clone(&shared_pool));
+  certificate::start_certificate_orders(config, Arc::clone(&shared_pool));
+  domain::start_domain_verification(config, Arc::clone(&shared_pool));
+  deployment::health::start_health_checks(Arc::clone(&shared_pool));
+  deployment::idle::start_idle_shutdown(config, Arc::clone(&shared_pool));
+  proxy::start_proxy(config, Arc::clone(&shared_pool), Arc::clone(&cluster_tls));
   docker::event::start_docker_event_listener();
   let app = Router::new()
     .route("/tokens", routing::get(token::route::api_get_tokens))
@@ -66,17 +77,59 @@ pub async fn start_server(config: &'static Config) -> anyhow::Result<()> {
     )
     .route(
       "/certificate",
-      routing::post(certificate::route::api_new_certificate),
+      routing::post(certificate::route::api_new_certificate)
+        .layer(middleware::from_fn(cluster::leader::forward_to_leader)),
+    )
+    .route(
+      "/certificate-orders/:order_id",
+      routing::get(certificate::route::api_get_certificate_order),
+    )
+    .route(
+      "/certificates",
+      routing::get(certificate::route::api_get_certificates),
+    )
+    .route(
+      "/certificates/:certificate_id",
+      routing::get(certificate::route::api_get_certificate)
+        .delete(certificate::route::api_delete_certificate),
     )
     .route(
       "/.well-known/acme-challenge/:token",
-      routing::get(certificate::route::api_http01_challenge),
+      routing::get(certificate::route::api_http01_challenge)
+        .layer(middleware::from_fn(cluster::leader::forward_to_leader)),
     )
+    .route(
+      "/domains",
+      routing::post(domain::route::api_new_domain)
+        .layer(middleware::from_fn(cluster::leader::forward_to_leader)),
+    )
+    .route("/domains", routing::get(domain::route::api_get_domains))
+    .route(
+      "/domains/:domain_id",
+      routing::get(domain::route::api_get_domain)
+        .patch(domain::route::api_update_domain)
+        .delete(domain::route::api_delete_domain),
+    )
+    .route(
+      "/domains/:domain_id/rules",
+      routing::put(domain::route::api_set_domain_rules),
+    )
+    .route(
+      "/error-pages",
+      routing::get(error_page::route::api_get_error_pages),
+    )
+    .route(
+      "/error-pages/:status_code",
+      routing::put(error_page::route::api_set_error_page)
+        .delete(error_page::route::api_delete_error_page),
+    )
+    .route("/events", routing::get(event::route::api_get_events))
     .route("/cron-jobs", routing::post(cron::route::api_create_job))
     .route("/cron-jobs", routing::get(cron::route::api_get_cron_jobs))
     .route(
       "/unstable/integration/github/events",
-      routing::post(integration::github::route::api_integration_github_events),
+      routing::post(integration::github::route::api_integration_github_events)
+        .layer(middleware::from_fn(cluster::leader::forward_to_leader)),
     )
     .route(
       "/auth/github",
@@ -86,15 +139,36 @@ pub async fn start_server(config: &'static Config) -> anyhow::Result<()> {
       "/auth/github/cli",
       routing::get(session::route::api_auth_github_cli),
     )
-    .route("/deploy", routing::post(deployment::route::api_deploy))
+    .route(
+      "/deploy",
+      routing::post(deployment::route::api_deploy)
+        .layer(middleware::from_fn(cluster::leader::forward_to_leader)),
+    )
+    .route(
+      "/deployments/:deployment_id/wake",
+      routing::post(deployment::route::api_wake_deployment)
+        .layer(middleware::from_fn(cluster::leader::forward_to_leader)),
+    )
     .route("/auth/logout", routing::delete(session::route::api_logout))
     .route("/projects", routing::get(project::route::api_list_projects))
+    .route(
+      "/projects/:project_id",
+      routing::patch(project::route::api_update_project),
+    )
     .route(
       "/projects/:owner_name/:project_name/deployments",
       routing::get(project::route::api_list_project_deployments),
     )
     .route("/projects/clone", routing::post(project::api_new_project))
     .route("/user", routing::get(user::route::api_get_user))
+    .route(
+      "/cluster/join-tokens",
+      routing::post(cluster::route::api_new_join_token),
+    )
+    .route(
+      "/cluster/nodes",
+      routing::get(cluster::route::api_get_nodes),
+    )
     .route("/info", routing::get(info::api_info))
     .route("/ping", routing::get(ping::api_ping))
     .route(
@@ -107,6 +181,7 @@ pub async fn start_server(config: &'static Config) -> anyhow::Result<()> {
     // )
     .layer(CorsLayer::permissive())
     .layer(Extension(Arc::clone(&shared_pool)))
+    .layer(Extension(cluster_tls))
     .layer(Extension(config));
   let address = config.address.to_string();
   let listener = TcpListener::bind(&address)
@@ -3,7 +3,7 @@ mod schema;
 
 use crate::config::Config;
 use crate::server::integration::github::CreateRepoError;
-use crate::server::project::schema::{GitSource, Project};
+use crate::server::project::schema::{GitSource, LoadBalancing, Project};
 use crate::server::session::validate_session;
 use crate::server::user::get_user;
 use axum::http::StatusCode;
@@ -30,21 +30,26 @@ pub async fn create_project(
     owner_id,
     git_source: GitSource::Github,
     git_source_metadata: github_repo_response.unwrap_or_else(|| json!({})),
+    replicas: 1,
+    load_balancing: LoadBalancing::RoundRobin,
+    idle_timeout_seconds: None,
     updated_at: Utc::now(),
     created_at: Utc::now(),
   };
   match sqlx::query_as!(
       Project,
       r#"
-      INSERT INTO project (id, name, owner_id, git_source, git_source_metadata, updated_at, created_at)
-      VALUES ($1, $2, $3, $4, $5, $6, $7)
-      RETURNING id, name, owner_id, git_source AS "git_source!: GitSource", git_source_metadata, updated_at, created_at
+      INSERT INTO project (id, name, owner_id, git_source, git_source_metadata, replicas, load_balancing, updated_at, created_at)
+      VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+      RETURNING id, name, owner_id, git_source AS "git_source!: GitSource", git_source_metadata, replicas, load_balancing AS "load_balancing: LoadBalancing", idle_timeout_seconds, updated_at, created_at
       "#,
       project.id,
       project.name,
       project.owner_id,
       project.git_source as GitSource,
       project.git_source_metadata,
+      project.replicas,
+      project.load_balancing as LoadBalancing,
       project.updated_at,
       project.created_at,
     ).fetch_one(&*pool).await {
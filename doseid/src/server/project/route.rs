@@ -2,13 +2,25 @@ use crate::config::Config;
 use crate::server::deployment::schema::Deployment;
 use crate::server::deployment::schema::DeploymentStatus;
 use crate::server::project::schema::Project;
-use crate::server::project::GitSource;
+use crate::server::project::{GitSource, LoadBalancing};
+use crate::server::proxy::routing::notify_routing_change;
 use crate::server::session::validate_session;
 use axum::extract::Path;
 use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
 use axum::{Extension, Json};
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::json;
 use sqlx::{Pool, Postgres};
 use std::sync::Arc;
+use tracing::error;
+use uuid::Uuid;
+
+/// Most containers a deployment can be scaled to.
+const MAX_REPLICAS: i16 = 16;
+/// Shortest idle timeout, as proxies report requests about once a minute.
+const MIN_IDLE_TIMEOUT_SECONDS: i32 = 300;
 
 pub async fn api_list_projects(
   pool: Extension<Arc<Pool<Postgres>>>,
@@ -18,7 +30,7 @@ pub async fn api_list_projects(
   let session = validate_session(Arc::clone(&pool), &config, headers).await?;
   match sqlx::query_as!(
     Project,
-    r#"SELECT id, name, owner_id, git_source AS "git_source!: GitSource", git_source_metadata, updated_at, created_at FROM project WHERE owner_id = $1::uuid"#,
+    r#"SELECT id, name, owner_id, git_source AS "git_source!: GitSource", git_source_metadata, replicas, load_balancing AS "load_balancing: LoadBalancing", idle_timeout_seconds, updated_at, created_at FROM project WHERE owner_id = $1::uuid"#,
     session.owner_id
   )
   .fetch_all(&**pool)
@@ -39,7 +51,7 @@ pub async fn api_list_project_deployments(
   match sqlx::query_as!(
     Deployment,
     r#"
-    SELECT d.id, d.commit_id, d.commit_metadata, d.project_id, d.owner_id, d.status AS "status!: DeploymentStatus", d.build_logs, d.exposed_port, d.internal_port, d.updated_at, d.created_at
+    SELECT d.id, d.commit_id, d.commit_metadata, d.project_id, d.owner_id, d.status AS "status!: DeploymentStatus", d.build_logs, d.exposed_port, d.exposed_host, d.internal_port, d.node_id, d.updated_at, d.created_at
     FROM deployment d
     INNER JOIN project p ON p.id = d.project_id
     WHERE p.name = $1 AND d.owner_id = $2::uuid
@@ -54,3 +66,80 @@ pub async fn api_list_project_deployments(
     Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
   }
 }
+
+/// Updates the settings of the project given in the body. Replicas apply from the next
+/// deployment on, load balancing as soon as proxies reload the route, and an idle timeout of 0
+/// keeps containers running.
+pub async fn api_update_project(
+  pool: Extension<Arc<Pool<Postgres>>>,
+  config: Extension<&'static Config>,
+  headers: axum::http::HeaderMap,
+  Path(project_id): Path<Uuid>,
+  Json(body): Json<ProjectPatch>,
+) -> Result<Json<Project>, Response> {
+  let session = validate_session(Arc::clone(&pool), &config, headers)
+    .await
+    .map_err(|e| e.into_response())?;
+  if let Some(replicas) = body.replicas {
+    if !(1..=MAX_REPLICAS).contains(&replicas) {
+      return Err(
+        (
+          StatusCode::BAD_REQUEST,
+          Json(json!({"message": format!("replicas must be between 1 and {}", MAX_REPLICAS)})),
+        )
+          .into_response(),
+      );
+    }
+  }
+  if let Some(idle_timeout_seconds) = body.idle_timeout_seconds {
+    if idle_timeout_seconds != 0 && idle_timeout_seconds < MIN_IDLE_TIMEOUT_SECONDS {
+      return Err(
+        (
+          StatusCode::BAD_REQUEST,
+          Json(json!({"message": format!("idle_timeout_seconds must be 0 or at least {}", MIN_IDLE_TIMEOUT_SECONDS)})),
+        )
+          .into_response(),
+      );
+    }
+  }
+  let project = match sqlx::query_as!(
+    Project,
+    r#"
+    UPDATE project
+    SET
+      replicas = COALESCE($3, replicas),
+      load_balancing = COALESCE($4, load_balancing),
+      idle_timeout_seconds = CASE WHEN $5::int IS NULL THEN idle_timeout_seconds ELSE NULLIF($5, 0) END,
+      updated_at = $6
+    WHERE id = $1 AND owner_id = $2
+    RETURNING id, name, owner_id, git_source AS "git_source!: GitSource", git_source_metadata, replicas, load_balancing AS "load_balancing: LoadBalancing", idle_timeout_seconds, updated_at, created_at
+    "#,
+    project_id,
+    session.owner_id,
+    body.replicas,
+    body.load_balancing as Option<LoadBalancing>,
+    body.idle_timeout_seconds,
+    Utc::now()
+  )
+  .fetch_optional(&**pool)
+  .await
+  {
+    Ok(Some(project)) => project,
+    Ok(None) => return Err(StatusCode::NOT_FOUND.into_response()),
+    Err(err) => {
+      error!("Error in updating project: {:?}", err);
+      return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+    }
+  };
+  if let Err(err) = notify_routing_change(&pool, project.id).await {
+    error!("Failed to notify routing change: {}", err);
+  }
+  Ok(Json(project))
+}
+
+#[derive(Deserialize)]
+pub struct ProjectPatch {
+  replicas: Option<i16>,
+  load_balancing: Option<LoadBalancing>,
+  idle_timeout_seconds: Option<i32>,
+}
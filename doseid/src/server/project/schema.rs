@@ -10,6 +10,11 @@ pub struct Project {
   pub owner_id: Uuid,
   pub git_source: GitSource,
   pub git_source_metadata: Value,
+  /// Containers started for each deployment.
+  pub replicas: i16,
+  pub load_balancing: LoadBalancing,
+  /// Seconds without requests after which its containers are stopped, until the next request.
+  pub idle_timeout_seconds: Option<i32>,
   pub updated_at: DateTime<Utc>,
   pub created_at: DateTime<Utc>,
 }
@@ -21,3 +26,12 @@ pub enum GitSource {
   Gitlab,
   Bitbucket,
 }
+
+/// How the proxy spreads requests across the containers of a deployment.
+#[derive(sqlx::Type, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[sqlx(type_name = "load_balancing", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum LoadBalancing {
+  RoundRobin,
+  LeastConnections,
+}
@@ -0,0 +1,29 @@
+use crate::config::Config;
+use crate::server::cluster::tls::ClusterTls;
+use crate::server::deployment::idle::wake_deployment;
+use anyhow::anyhow;
+use dosei_proxy_core::cold_start;
+use sqlx::{Pool, Postgres};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Longest a request is held while the containers of its deployment start.
+const COLD_START_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Starts the containers of a deployment stopped for being idle, and waits until one of them
+/// accepts connections.
+pub async fn wake(
+  config: &'static Config,
+  pool: &Pool<Postgres>,
+  tls: &ClusterTls,
+  deployment_id: Uuid,
+  upstreams: &[String],
+) -> anyhow::Result<()> {
+  cold_start::wake(deployment_id, upstreams, COLD_START_TIMEOUT, || async {
+    match wake_deployment(config, pool, tls, deployment_id).await? {
+      Some(_) => Ok(()),
+      None => Err(anyhow!("Deployment {} is not ready", deployment_id)),
+    }
+  })
+  .await
+}
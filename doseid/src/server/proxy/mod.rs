@@ -0,0 +1,164 @@
+pub(crate) mod cold_start;
+pub(crate) mod routing;
+
+use crate::config::{Address, Config};
+use crate::server::cluster::tls::ClusterTls;
+use crate::server::proxy::cold_start::wake;
+use crate::server::proxy::routing::{get_route, start_routing_listener};
+use axum::body::Body;
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::header::HOST;
+use axum::http::uri::Uri;
+use axum::http::{HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::any;
+use axum::{Extension, Router};
+use dosei_proxy_core::balancer::BALANCER;
+use dosei_proxy_core::client_ip::{client_ip, set_forwarded_for};
+use dosei_proxy_core::cold_start::record_activity;
+use dosei_proxy_core::host::normalize_host;
+use dosei_proxy_core::rules;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::rt::TokioExecutor;
+use sqlx::{Pool, Postgres};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::net::TcpListener;
+use tracing::{error, info};
+
+pub type Client = hyper_util::client::legacy::Client<HttpConnector, Body>;
+
+/// Starts the reverse proxy routing requests by their `Host` to the active deployment of the
+/// matching domain.
+pub fn start_proxy(
+  config: &'static Config,
+  pool: Arc<Pool<Postgres>>,
+  cluster_tls: Arc<ClusterTls>,
+) {
+  let Some(proxy_config) = config.proxy.as_ref() else {
+    return;
+  };
+  start_routing_listener(Arc::clone(&pool));
+  rules::start_rate_limiter_eviction();
+  let client: Client = hyper_util::client::legacy::Client::<(), ()>::builder(TokioExecutor::new())
+    .build(HttpConnector::new());
+  let app = Router::new()
+    .route("/", any(handler))
+    .route("/*path", any(handler))
+    .with_state(client)
+    .layer(Extension(pool))
+    .layer(Extension(cluster_tls))
+    .layer(Extension(config));
+  let address = proxy_config.address.to_string();
+  tokio::spawn(async move {
+    let listener = match TcpListener::bind(&address).await {
+      Ok(listener) => listener,
+      Err(err) => {
+        error!("Failed to start proxy on {}: {}", address, err);
+        return;
+      }
+    };
+    info!("Proxy running on http://{}", address);
+    if let Err(err) = axum::serve(
+      listener,
+      app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    {
+      error!("Proxy stopped: {}", err);
+    }
+  });
+}
+
+async fn handler(
+  pool: Extension<Arc<Pool<Postgres>>>,
+  config: Extension<&'static Config>,
+  cluster_tls: Extension<Arc<ClusterTls>>,
+  State(client): State<Client>,
+  ConnectInfo(peer_address): ConnectInfo<SocketAddr>,
+  mut req: Request,
+) -> Result<Response, StatusCode> {
+  let host = match req.headers().get(HOST).and_then(|host| host.to_str().ok()) {
+    Some(host) => normalize_host(host),
+    None => return Err(StatusCode::BAD_REQUEST),
+  };
+  let route = match get_route(&pool, &host).await {
+    Ok(Some(route)) => route,
+    Ok(None) => return Err(StatusCode::NOT_FOUND),
+    Err(err) => {
+      error!("Failed to resolve route for {}: {}", host, err);
+      return Err(StatusCode::BAD_GATEWAY);
+    }
+  };
+
+  let trusted_proxies = config
+    .proxy
+    .as_ref()
+    .map_or(&[][..], |proxy| &proxy.trusted_proxies);
+  let client_address = client_ip(peer_address.ip(), req.headers(), trusted_proxies);
+  if let Some(rejection) = route.rules.check(&host, client_address) {
+    let mut response = rejection.status().into_response();
+    rejection.apply(&mut response);
+    return Ok(response);
+  }
+
+  let upstreams: Vec<String> = route.upstreams.iter().map(Address::to_string).collect();
+  let Some(lease) = BALANCER.pick(
+    &route.deployment_id.to_string(),
+    &upstreams,
+    route.load_balancing,
+    Instant::now(),
+  ) else {
+    return Err(StatusCode::SERVICE_UNAVAILABLE);
+  };
+  if route.stopped {
+    if let Err(err) = wake(
+      &config,
+      &pool,
+      &cluster_tls,
+      route.deployment_id,
+      &upstreams,
+    )
+    .await
+    {
+      error!("Failed to wake deployment {}: {}", route.deployment_id, err);
+      return Err(StatusCode::SERVICE_UNAVAILABLE);
+    }
+  }
+  record_activity(Arc::clone(&pool), route.deployment_id);
+
+  let path_query = req
+    .uri()
+    .path_and_query()
+    .map(|v| v.as_str())
+    .unwrap_or("/");
+  let upstream = lease.upstream().to_string();
+  let uri = format!("http://{}{}", upstream, path_query);
+  *req.uri_mut() = Uri::try_from(uri).map_err(|_| StatusCode::BAD_REQUEST)?;
+  let headers = req.headers_mut();
+  set_forwarded_for(headers, peer_address.ip(), trusted_proxies);
+  if let Ok(value) = HeaderValue::from_str(&host) {
+    headers.insert("x-forwarded-host", value);
+  }
+  headers.insert("x-forwarded-proto", HeaderValue::from_static("http"));
+
+  match client.request(req).await {
+    Ok(response) => {
+      let failed = matches!(
+        response.status(),
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+      );
+      BALANCER.report(&upstream, !failed, Instant::now());
+      Ok(lease.attach(response.into_response()))
+    }
+    Err(err) => {
+      error!(
+        "Failed to forward {} to deployment {}: {}",
+        host, route.deployment_id, err
+      );
+      BALANCER.report(&upstream, false, Instant::now());
+      Err(StatusCode::BAD_GATEWAY)
+    }
+  }
+}
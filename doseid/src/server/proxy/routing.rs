@@ -0,0 +1,282 @@
+use crate::config::Address;
+use dosei_proxy_core::balancer::LoadBalancing;
+use dosei_proxy_core::rules::DomainRules;
+use once_cell::sync::Lazy;
+use sqlx::postgres::PgListener;
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tokio::time::sleep;
+use tracing::{error, warn};
+use uuid::Uuid;
+
+/// Postgres channel notified whenever the deployment a domain routes to may have changed.
+const ROUTING_CHANNEL: &str = "dosei_routing";
+/// Upper bound on how long a route is trusted, in case a notification was missed.
+const ROUTE_LIFESPAN: Duration = Duration::from_secs(300);
+/// Most hosts cached at once, so requests for made up hosts can't grow the table without bound.
+const ROUTE_CAPACITY: usize = 10_000;
+
+pub static ROUTING_TABLE: Lazy<Arc<Mutex<RoutingTable>>> = Lazy::new(|| {
+  Arc::new(Mutex::new(RoutingTable::new(
+    ROUTE_LIFESPAN,
+    ROUTE_CAPACITY,
+  )))
+});
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Route {
+  pub project_id: Uuid,
+  pub deployment_id: Uuid,
+  /// The running instances of the deployment, healthy ones only unless none is. Every instance
+  /// when they were all stopped for being idle.
+  pub upstreams: Vec<Address>,
+  /// Whether every instance was stopped for being idle, and has to be woken.
+  pub stopped: bool,
+  pub load_balancing: LoadBalancing,
+  pub rules: DomainRules,
+}
+
+/// Routes by host, including hosts known not to route anywhere.
+pub struct RoutingTable {
+  lifespan: Duration,
+  capacity: usize,
+  routes: HashMap<String, (Option<Route>, Instant)>,
+}
+
+impl RoutingTable {
+  pub fn new(lifespan: Duration, capacity: usize) -> RoutingTable {
+    RoutingTable {
+      lifespan,
+      capacity,
+      routes: HashMap::new(),
+    }
+  }
+
+  /// Returns `None` on a cache miss, `Some(None)` for hosts known to have no route.
+  pub fn get(&self, host: &str) -> Option<Option<Route>> {
+    self
+      .routes
+      .get(host)
+      .filter(|(_, cached_at)| cached_at.elapsed() < self.lifespan)
+      .map(|(route, _)| route.clone())
+  }
+
+  /// Caches a route, sweeping expired ones first when the table is full, and then evicting the
+  /// oldest if it still is.
+  pub fn insert(&mut self, host: String, route: Option<Route>) {
+    if self.routes.len() >= self.capacity && !self.routes.contains_key(&host) {
+      let lifespan = self.lifespan;
+      self
+        .routes
+        .retain(|_, (_, cached_at)| cached_at.elapsed() < lifespan);
+      if self.routes.len() >= self.capacity {
+        let oldest = self
+          .routes
+          .iter()
+          .min_by_key(|(_, (_, cached_at))| *cached_at)
+          .map(|(host, _)| host.clone());
+        if let Some(oldest) = oldest {
+          self.routes.remove(&oldest);
+        }
+      }
+    }
+    self.routes.insert(host, (route, Instant::now()));
+  }
+
+  /// Forgets the routes of a project, and every unknown host since one may now belong to it.
+  pub fn invalidate_project(&mut self, project_id: Uuid) {
+    self.routes.retain(|_, (route, _)| {
+      route
+        .as_ref()
+        .is_some_and(|route| route.project_id != project_id)
+    });
+  }
+
+  pub fn clear(&mut self) {
+    self.routes.clear();
+  }
+}
+
+pub async fn get_route(pool: &Pool<Postgres>, host: &str) -> anyhow::Result<Option<Route>> {
+  if let Some(route) = ROUTING_TABLE.lock().await.get(host) {
+    return Ok(route);
+  }
+  let route = resolve_route(pool, host).await?;
+  ROUTING_TABLE
+    .lock()
+    .await
+    .insert(host.to_string(), route.clone());
+  Ok(route)
+}
+
+/// Resolves a host to the instances of the latest ready deployment of its verified domain, or
+/// of the deployment the domain is pinned to.
+async fn resolve_route(pool: &Pool<Postgres>, host: &str) -> anyhow::Result<Option<Route>> {
+  let records = sqlx::query!(
+    "
+    SELECT
+      d.id, d.project_id, i.exposed_host, i.exposed_port, i.healthy, i.stopped_at IS NOT NULL AS \"stopped!\",
+      p.load_balancing::text, dm.rate_limit_per_second, dm.rate_limit_burst, dm.allowed_ips,
+      dm.denied_ips
+    FROM domain dm
+    INNER JOIN LATERAL (
+      SELECT id, project_id
+      FROM deployment
+      WHERE (
+        (dm.deployment_id IS NOT NULL AND id::text = dm.deployment_id)
+        OR (dm.deployment_id IS NULL AND project_id = dm.project_id)
+      ) AND status = 'ready'
+      ORDER BY created_at DESC
+      LIMIT 1
+    ) d ON true
+    INNER JOIN project p ON p.id = d.project_id
+    INNER JOIN deployment_instance i ON i.deployment_id = d.id
+    WHERE dm.name = $1 AND dm.verified_at IS NOT NULL
+    ORDER BY i.created_at
+    ",
+    host
+  )
+  .fetch_all(pool)
+  .await?;
+  let Some(first) = records.first() else {
+    return Ok(None);
+  };
+  let (project_id, deployment_id) = (first.project_id, first.id);
+  let rules = DomainRules::from_columns(
+    first.rate_limit_per_second,
+    first.rate_limit_burst,
+    first.allowed_ips.clone(),
+    first.denied_ips.clone(),
+  );
+  let load_balancing = LoadBalancing::from_column(first.load_balancing.as_deref());
+  let stopped = records.iter().all(|record| record.stopped);
+  let any_healthy = records
+    .iter()
+    .any(|record| record.healthy && !record.stopped);
+  let upstreams = records
+    .into_iter()
+    .filter(|record| stopped || (!record.stopped && (record.healthy || !any_healthy)))
+    .map(|record| Address {
+      host: upstream_host(record.exposed_host.as_deref()),
+      port: record.exposed_port as u16,
+    })
+    .collect();
+  Ok(Some(Route {
+    project_id,
+    deployment_id,
+    upstreams,
+    stopped,
+    load_balancing,
+    rules,
+  }))
+}
+
+pub fn upstream_host(exposed_host: Option<&str>) -> String {
+  match exposed_host {
+    None | Some("0.0.0.0") => "127.0.0.1".to_string(),
+    Some(host) => host.to_string(),
+  }
+}
+
+/// Tells every node that the routes of a project changed, e.g. after a redeploy.
+pub async fn notify_routing_change(pool: &Pool<Postgres>, project_id: Uuid) -> anyhow::Result<()> {
+  sqlx::query!(
+    "SELECT pg_notify($1, $2)",
+    ROUTING_CHANNEL,
+    project_id.to_string()
+  )
+  .execute(pool)
+  .await?;
+  Ok(())
+}
+
+/// Keeps the routing table of this node in sync with changes made on any node.
+pub fn start_routing_listener(pool: Arc<Pool<Postgres>>) {
+  tokio::spawn(async move {
+    loop {
+      if let Err(err) = listen_routing_changes(&pool).await {
+        error!("Routing listener failed: {}", err);
+      }
+      ROUTING_TABLE.lock().await.clear();
+      sleep(Duration::from_secs(1)).await;
+    }
+  });
+}
+
+async fn listen_routing_changes(pool: &Pool<Postgres>) -> anyhow::Result<()> {
+  let mut listener = PgListener::connect_with(pool).await?;
+  listener.listen(ROUTING_CHANNEL).await?;
+  loop {
+    // `None` means the connection was lost and notifications may have been missed.
+    let Some(notification) = listener.try_recv().await? else {
+      warn!("Routing listener reconnected, clearing routing table");
+      ROUTING_TABLE.lock().await.clear();
+      continue;
+    };
+    let mut routing_table = ROUTING_TABLE.lock().await;
+    match Uuid::parse_str(notification.payload()) {
+      Ok(project_id) => routing_table.invalidate_project(project_id),
+      Err(_) => routing_table.clear(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::config::Address;
+  use crate::server::proxy::routing::{Route, RoutingTable};
+  use dosei_proxy_core::balancer::LoadBalancing;
+  use dosei_proxy_core::rules::DomainRules;
+  use std::time::Duration;
+  use uuid::Uuid;
+
+  fn route(project_id: Uuid) -> Route {
+    Route {
+      project_id,
+      deployment_id: Uuid::new_v4(),
+      upstreams: vec![Address {
+        host: "127.0.0.1".to_string(),
+        port: 10000,
+      }],
+      stopped: false,
+      load_balancing: LoadBalancing::RoundRobin,
+      rules: DomainRules::default(),
+    }
+  }
+
+  #[test]
+  fn test_routing_table_invalidate_project() {
+    let (project, other_project) = (Uuid::new_v4(), Uuid::new_v4());
+    let mut routing_table = RoutingTable::new(Duration::from_secs(60), 10);
+    routing_table.insert("a.example.com".to_string(), Some(route(project)));
+    routing_table.insert("b.example.com".to_string(), Some(route(other_project)));
+    routing_table.insert("unknown.example.com".to_string(), None);
+    assert_eq!(routing_table.get("unknown.example.com"), Some(None));
+
+    routing_table.invalidate_project(project);
+    assert_eq!(routing_table.get("a.example.com"), None);
+    assert_eq!(routing_table.get("unknown.example.com"), None);
+    assert!(routing_table.get("b.example.com").unwrap().is_some());
+  }
+
+  #[test]
+  fn test_routing_table_expiration() {
+    let mut routing_table = RoutingTable::new(Duration::ZERO, 10);
+    routing_table.insert("a.example.com".to_string(), Some(route(Uuid::new_v4())));
+    assert_eq!(routing_table.get("a.example.com"), None);
+  }
+
+  #[test]
+  fn test_routing_table_capacity() {
+    let mut routing_table = RoutingTable::new(Duration::from_secs(60), 2);
+    routing_table.insert("a.example.com".to_string(), None);
+    routing_table.insert("b.example.com".to_string(), None);
+    routing_table.insert("c.example.com".to_string(), None);
+    assert_eq!(routing_table.routes.len(), 2);
+    assert_eq!(routing_table.get("a.example.com"), None);
+    assert_eq!(routing_table.get("c.example.com"), Some(None));
+  }
+}
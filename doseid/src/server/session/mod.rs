@@ -1,11 +1,12 @@
 pub(crate) mod route;
-mod schema;
+pub(crate) mod schema;
 
 use crate::config::Config;
 use crate::server::session::schema::SessionToken;
 use crate::server::token::schema::Token;
 use axum::http::{header, StatusCode};
 use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use sha2::{Digest, Sha256};
 use sqlx::{Pool, Postgres};
 use std::collections::HashSet;
 use std::sync::Arc;
@@ -51,3 +52,21 @@ pub async fn validate_session(
     owner_id: token.owner_id,
   })
 }
+
+/// Validates the bearer token of Dosei Proxy instances, set through `PROXY_TOKEN` on both.
+pub fn validate_proxy_token(
+  config: &'static Config,
+  headers: &axum::http::HeaderMap,
+) -> Result<(), StatusCode> {
+  let proxy_token = config.proxy_token.as_ref().ok_or(StatusCode::FORBIDDEN)?;
+  let bearer_token = headers
+    .get(header::AUTHORIZATION)
+    .and_then(|authorization| authorization.to_str().ok())
+    .and_then(|authorization| authorization.strip_prefix(BEARER))
+    .ok_or(StatusCode::UNAUTHORIZED)?;
+  // Compares digests, so the time taken tells nothing about the token.
+  if Sha256::digest(bearer_token.as_bytes()) != Sha256::digest(proxy_token.as_bytes()) {
+    return Err(StatusCode::UNAUTHORIZED);
+  }
+  Ok(())
+}
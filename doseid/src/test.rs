@@ -1,4 +1,49 @@
-use crate::config::Config;
+use crate::config::{AcmeConfig, Address, ClusterConfig, Config, NodeInfo, Telemetry};
+use dosei_proto::ping::NodeType;
 use once_cell::sync::Lazy;
+use std::time::Duration;
+use uuid::Uuid;
 
 pub(crate) static CONFIG: Lazy<Config> = Lazy::new(|| Config::new().unwrap());
+
+/// A config built without reading arguments, environment variables or files, for route tests.
+pub(crate) fn config() -> Config {
+  Config {
+    address: Address {
+      host: "127.0.0.1".to_string(),
+      port: 8844,
+    },
+    advertise_host: "127.0.0.1".to_string(),
+    node_info: NodeInfo {
+      id: Uuid::new_v4(),
+      node_type: NodeType::Primary,
+      address: Address {
+        host: "127.0.0.1".to_string(),
+        port: 18844,
+      },
+    },
+    primary_address: None,
+    join_token: None,
+    database_url: String::new(),
+    jwt_secret: "test".to_string(),
+    container_registry_url: String::new(),
+    telemetry: Telemetry { client: None },
+    github_integration: None,
+    console: false,
+    cluster: ClusterConfig {
+      suspect_timeout: Duration::from_secs(5),
+      dead_timeout: Duration::from_secs(30),
+      eviction_timeout: Duration::from_secs(300),
+      labels: Default::default(),
+      admins: vec![],
+    },
+    proxy: None,
+    acme: AcmeConfig {
+      directory_url: "https://localhost:14000/dir".to_string(),
+      external_account: None,
+      rfc2136: None,
+    },
+    base_domain: None,
+    proxy_token: None,
+  }
+}
@@ -16,6 +16,7 @@ pub(crate) async fn write_tar_gz(output_path: &str, folder_path: &Path) -> anyho
     let tar_gz = File::create(output_path)?;
     let enc = GzEncoder::new(tar_gz, Compression::default());
     let mut tar = Builder::new(enc);
+    tar.follow_symlinks(false);
 
     tar.append_dir_all(".", folder_path)?;
 
@@ -25,6 +26,38 @@ pub(crate) async fn write_tar_gz(output_path: &str, folder_path: &Path) -> anyho
   .await?
 }
 
+/// Copies the entries of an archive as they are, adding a file to it. Symlinks stay links, so
+/// they can't pull files of the host into the archive.
+pub(crate) async fn append_to_tar_gz(
+  tar_gz: &[u8],
+  path: &str,
+  contents: &[u8],
+) -> anyhow::Result<Vec<u8>> {
+  let tar_gz = tar_gz.to_owned();
+  let path = path.to_owned();
+  let contents = contents.to_owned();
+  task::spawn_blocking(move || {
+    let mut archive = Archive::new(GzDecoder::new(Cursor::new(tar_gz)));
+    let mut tar = Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    for entry in archive.entries()? {
+      let mut entry = entry?;
+      let entry_path = entry.path()?.into_owned();
+      if entry_path.strip_prefix(".").unwrap_or(&entry_path) == Path::new(&path) {
+        continue;
+      }
+      let header = entry.header().clone();
+      tar.append(&header, &mut entry)?;
+    }
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    tar.append_data(&mut header, path, contents.as_slice())?;
+    Ok(tar.into_inner()?.finish()?)
+  })
+  .await?
+}
+
 pub(crate) async fn extract_tar_gz_from_memory(
   combined_data: &[u8],
   target_folder: &Path,
@@ -50,3 +83,43 @@ pub(crate) async fn read_tar_gz_content(output_path: &str) -> Vec<u8> {
   file.read_to_end(&mut contents).await.unwrap();
   contents
 }
+
+#[cfg(test)]
+mod tests {
+  use crate::util::{append_to_tar_gz, write_tar_gz};
+  use flate2::read::GzDecoder;
+  use std::io::Cursor;
+  use tar::{Archive, EntryType};
+  use tempfile::tempdir;
+
+  #[tokio::test]
+  async fn test_append_to_tar_gz_keeps_symlinks() {
+    let temp_dir = tempdir().unwrap();
+    let folder = temp_dir.path().join("app");
+    std::fs::create_dir(&folder).unwrap();
+    std::fs::write(folder.join("main.py"), "print('Hello')").unwrap();
+    std::os::unix::fs::symlink("/etc/hostname", folder.join("secret")).unwrap();
+    let output_path = temp_dir.path().join("app.tar.gz");
+    write_tar_gz(output_path.to_str().unwrap(), &folder)
+      .await
+      .unwrap();
+    let tar_gz = std::fs::read(&output_path).unwrap();
+
+    let tar_gz = append_to_tar_gz(&tar_gz, "Dockerfile", b"FROM python:3.11-slim\n")
+      .await
+      .unwrap();
+    let mut archive = Archive::new(GzDecoder::new(Cursor::new(tar_gz)));
+    let entries = archive
+      .entries()
+      .unwrap()
+      .map(|entry| {
+        let entry = entry.unwrap();
+        let path = entry.path().unwrap().to_string_lossy().to_string();
+        (path, entry.header().entry_type())
+      })
+      .collect::<Vec<_>>();
+    assert!(entries.contains(&("secret".to_string(), EntryType::Symlink)));
+    assert!(entries.contains(&("main.py".to_string(), EntryType::Regular)));
+    assert!(entries.contains(&("Dockerfile".to_string(), EntryType::Regular)));
+  }
+}
@@ -0,0 +1,247 @@
+//! Length-delimited framing for the cluster protocol.
+//!
+//! Every frame on the wire is laid out as (big endian):
+//!
+//! | length: u32 | version: u8 | kind: u8 | message_id: u8 | correlation_id: u64 | payload |
+//!
+//! `length` covers everything after itself. `message_id` is the [`ProtoChannel::PROTO_ID`] of the
+//! protobuf message carried in `payload`, and replies echo the `correlation_id` of the request
+//! they answer.
+
+use crate::ProtoChannel;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use prost::Message;
+use std::io;
+use tokio_util::codec::{Decoder, Encoder};
+
+pub const PROTOCOL_VERSION: u8 = 1;
+pub const MAX_FRAME_LENGTH: usize = 8 * 1024 * 1024;
+
+const LENGTH_FIELD_LEN: usize = 4;
+const HEADER_LEN: usize = 11;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+  Request,
+  Reply,
+}
+
+impl FrameKind {
+  fn as_u8(&self) -> u8 {
+    match self {
+      FrameKind::Request => 0,
+      FrameKind::Reply => 1,
+    }
+  }
+
+  fn from_u8(value: u8) -> Result<FrameKind, FrameError> {
+    match value {
+      0 => Ok(FrameKind::Request),
+      1 => Ok(FrameKind::Reply),
+      kind => Err(FrameError::UnknownKind(kind)),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+  pub version: u8,
+  pub kind: FrameKind,
+  pub message_id: u8,
+  pub correlation_id: u64,
+  pub payload: Bytes,
+}
+
+impl Frame {
+  pub fn request<T: ProtoChannel + Message>(correlation_id: u64, message: &T) -> Frame {
+    Frame::new(FrameKind::Request, correlation_id, message)
+  }
+
+  /// Builds the reply to this frame, carrying over its correlation id.
+  pub fn reply<T: ProtoChannel + Message>(&self, message: &T) -> Frame {
+    Frame::new(FrameKind::Reply, self.correlation_id, message)
+  }
+
+  pub fn decode_message<T: ProtoChannel + Message + Default>(&self) -> Result<T, FrameError> {
+    if self.message_id != T::PROTO_ID {
+      return Err(FrameError::UnexpectedMessage {
+        expected: T::PROTO_ID,
+        actual: self.message_id,
+      });
+    }
+    Ok(T::decode(self.payload.clone())?)
+  }
+
+  fn new<T: ProtoChannel + Message>(kind: FrameKind, correlation_id: u64, message: &T) -> Frame {
+    Frame {
+      version: PROTOCOL_VERSION,
+      kind,
+      message_id: T::PROTO_ID,
+      correlation_id,
+      payload: Bytes::from(message.encode_to_vec()),
+    }
+  }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FrameError {
+  #[error("I/O error: {0}")]
+  Io(#[from] io::Error),
+
+  #[error("Frame of {0} bytes exceeds the maximum frame length")]
+  TooLarge(usize),
+
+  #[error("Frame of {0} bytes is shorter than the frame header")]
+  TooShort(usize),
+
+  #[error("Unsupported protocol version: {0}")]
+  UnsupportedVersion(u8),
+
+  #[error("Unknown frame kind: {0}")]
+  UnknownKind(u8),
+
+  #[error("Expected message id {expected:#04x}, got {actual:#04x}")]
+  UnexpectedMessage { expected: u8, actual: u8 },
+
+  #[error("Failed to decode message: {0}")]
+  Decode(#[from] prost::DecodeError),
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ClusterCodec;
+
+impl Decoder for ClusterCodec {
+  type Item = Frame;
+  type Error = FrameError;
+
+  fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Frame>, FrameError> {
+    if src.len() < LENGTH_FIELD_LEN {
+      return Ok(None);
+    }
+    let mut length_bytes = [0u8; LENGTH_FIELD_LEN];
+    length_bytes.copy_from_slice(&src[..LENGTH_FIELD_LEN]);
+    let length = u32::from_be_bytes(length_bytes) as usize;
+    if length > MAX_FRAME_LENGTH {
+      return Err(FrameError::TooLarge(length));
+    }
+    if length < HEADER_LEN {
+      return Err(FrameError::TooShort(length));
+    }
+    if src.len() < LENGTH_FIELD_LEN + length {
+      src.reserve(LENGTH_FIELD_LEN + length - src.len());
+      return Ok(None);
+    }
+
+    src.advance(LENGTH_FIELD_LEN);
+    let mut frame = src.split_to(length);
+    let version = frame.get_u8();
+    if version != PROTOCOL_VERSION {
+      return Err(FrameError::UnsupportedVersion(version));
+    }
+    let kind = FrameKind::from_u8(frame.get_u8())?;
+    let message_id = frame.get_u8();
+    let correlation_id = frame.get_u64();
+    Ok(Some(Frame {
+      version,
+      kind,
+      message_id,
+      correlation_id,
+      payload: frame.freeze(),
+    }))
+  }
+}
+
+impl Encoder<Frame> for ClusterCodec {
+  type Error = FrameError;
+
+  fn encode(&mut self, frame: Frame, dst: &mut BytesMut) -> Result<(), FrameError> {
+    let length = HEADER_LEN + frame.payload.len();
+    if length > MAX_FRAME_LENGTH {
+      return Err(FrameError::TooLarge(length));
+    }
+    dst.reserve(LENGTH_FIELD_LEN + length);
+    dst.put_u32(length as u32);
+    dst.put_u8(frame.version);
+    dst.put_u8(frame.kind.as_u8());
+    dst.put_u8(frame.message_id);
+    dst.put_u64(frame.correlation_id);
+    dst.extend_from_slice(&frame.payload);
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{ack, ping};
+
+  fn encode(frame: Frame) -> BytesMut {
+    let mut buf = BytesMut::new();
+    ClusterCodec.encode(frame, &mut buf).unwrap();
+    buf
+  }
+
+  #[test]
+  fn test_request_reply_round_trip() {
+    let ping = ping::Ping {
+      id: "node".to_string(),
+      node_type: ping::NodeType::Replica as i32,
+      address: "127.0.0.1:8844".to_string(),
+      version: "0.0.0".to_string(),
+      resources: None,
+      labels: Default::default(),
+      node_address: "127.0.0.1:18844".to_string(),
+    };
+    let mut buf = encode(Frame::request(42, &ping));
+
+    let request = ClusterCodec.decode(&mut buf).unwrap().unwrap();
+    assert!(buf.is_empty());
+    assert_eq!(request.kind, FrameKind::Request);
+    assert_eq!(request.correlation_id, 42);
+    assert_eq!(request.decode_message::<ping::Ping>().unwrap(), ping);
+
+    let reply = request.reply(&ack::Ack::ok());
+    assert_eq!(reply.kind, FrameKind::Reply);
+    assert_eq!(reply.correlation_id, 42);
+    assert!(reply.decode_message::<ack::Ack>().unwrap().ok);
+  }
+
+  #[test]
+  fn test_partial_frame() {
+    let mut full = encode(Frame::request(1, &ack::Ack::error("boom")));
+    let mut partial = full.split_to(full.len() - 1);
+    assert!(ClusterCodec.decode(&mut partial).unwrap().is_none());
+    partial.unsplit(full);
+    assert!(ClusterCodec.decode(&mut partial).unwrap().is_some());
+  }
+
+  #[test]
+  fn test_unexpected_message() {
+    let frame = Frame::request(1, &ack::Ack::ok());
+    assert!(matches!(
+      frame.decode_message::<ping::Ping>(),
+      Err(FrameError::UnexpectedMessage { .. })
+    ));
+  }
+
+  #[test]
+  fn test_unsupported_version() {
+    let mut frame = Frame::request(1, &ack::Ack::ok());
+    frame.version = PROTOCOL_VERSION + 1;
+    let mut buf = encode(frame);
+    assert!(matches!(
+      ClusterCodec.decode(&mut buf),
+      Err(FrameError::UnsupportedVersion(_))
+    ));
+  }
+
+  #[test]
+  fn test_frame_too_large() {
+    let mut buf = BytesMut::new();
+    buf.put_u32(MAX_FRAME_LENGTH as u32 + 1);
+    assert!(matches!(
+      ClusterCodec.decode(&mut buf),
+      Err(FrameError::TooLarge(_))
+    ));
+  }
+}
@@ -1,3 +1,5 @@
+pub mod frame;
+
 pub trait ProtoChannel {
   const PROTO_ID: u8;
 }
@@ -25,3 +27,43 @@ pub mod cron_job {
 impl ProtoChannel for cron_job::CronJob {
   const PROTO_ID: u8 = 0x02;
 }
+
+pub mod ack {
+  include!(concat!(env!("OUT_DIR"), "/dosei.ack.rs"));
+}
+
+impl ProtoChannel for ack::Ack {
+  const PROTO_ID: u8 = 0x03;
+}
+
+pub mod deployment {
+  include!(concat!(env!("OUT_DIR"), "/dosei.deployment.rs"));
+}
+
+impl ProtoChannel for deployment::DeployContainer {
+  const PROTO_ID: u8 = 0x04;
+}
+
+impl ProtoChannel for deployment::ContainerDeployed {
+  const PROTO_ID: u8 = 0x05;
+}
+
+impl ProtoChannel for deployment::StartContainer {
+  const PROTO_ID: u8 = 0x06;
+}
+
+impl ack::Ack {
+  pub fn ok() -> ack::Ack {
+    ack::Ack {
+      ok: true,
+      message: String::new(),
+    }
+  }
+
+  pub fn error(message: impl ToString) -> ack::Ack {
+    ack::Ack {
+      ok: false,
+      message: message.to_string(),
+    }
+  }
+}
@@ -0,0 +1,100 @@
+use crate::config::Config;
+use crate::metrics::{record_request, UNKNOWN_DOMAIN};
+use axum::body::HttpBody;
+use axum::extract::{ConnectInfo, Request};
+use axum::http::HeaderValue;
+use axum::middleware::Next;
+use axum::response::Response;
+use axum::Extension;
+use dosei_proxy_core::client_ip::client_ip;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Instant;
+use tracing::info;
+use uuid::Uuid;
+
+/// Header identifying a request in the access log, forwarded to upstreams and echoed back.
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// The upstream a request was forwarded to, left on the response by the proxy handler.
+#[derive(Debug, Clone)]
+pub struct ForwardedTo(pub String);
+
+/// Marks the response to a request for a known domain that was not forwarded, e.g. rate limited.
+#[derive(Debug, Clone)]
+pub struct RoutedDomain;
+
+/// Where a request comes from, left on it for the proxy handler.
+#[derive(Debug, Clone, Copy)]
+pub struct Client {
+  /// The client, as told by trusted proxies in front, if any.
+  pub ip: IpAddr,
+  /// The address of the connection, the last trusted proxy if any.
+  pub peer: IpAddr,
+}
+
+/// Logs every request once its response headers are ready, and records its metrics.
+pub async fn access_log(
+  config: Extension<&'static Config>,
+  ConnectInfo(peer): ConnectInfo<SocketAddr>,
+  mut req: Request,
+  next: Next,
+) -> Response {
+  let client = client_ip(peer.ip(), req.headers(), &config.trusted_proxies);
+  req.extensions_mut().insert(Client {
+    ip: client,
+    peer: peer.ip(),
+  });
+  // Keeps the id of a request another proxy already tagged, so logs can be correlated.
+  let request_id = req
+    .headers()
+    .get(REQUEST_ID_HEADER)
+    .and_then(|value| value.to_str().ok())
+    .filter(|value| !value.is_empty() && value.len() <= 128)
+    .map(String::from)
+    .unwrap_or_else(|| Uuid::new_v4().to_string());
+  let request_id_value = HeaderValue::from_str(&request_id).ok();
+  if let Some(value) = &request_id_value {
+    req.headers_mut().insert(REQUEST_ID_HEADER, value.clone());
+  }
+  let host = req
+    .headers()
+    .get("host")
+    .and_then(|host| host.to_str().ok())
+    .map(|host| host.split(':').next().unwrap_or(host).to_lowercase())
+    .unwrap_or_default();
+  let method = req.method().clone();
+  let path = req.uri().path().to_string();
+
+  let started_at = Instant::now();
+  let mut response = next.run(req).await;
+  let latency = started_at.elapsed();
+
+  let status = response.status();
+  let bytes = response.body().size_hint().exact();
+  let upstream = response
+    .extensions()
+    .get::<ForwardedTo>()
+    .map(|forwarded_to| forwarded_to.0.clone());
+  if let Some(value) = request_id_value {
+    response.headers_mut().insert(REQUEST_ID_HEADER, value);
+  }
+  info!(
+    target: "access",
+    request_id = %request_id,
+    client = %client,
+    host = %host,
+    method = %method,
+    path = %path,
+    status = status.as_u16(),
+    latency_ms = latency.as_millis() as u64,
+    bytes = %bytes.map_or("-".to_string(), |bytes| bytes.to_string()),
+    upstream = %upstream.as_deref().unwrap_or("-"),
+  );
+  let domain = if upstream.is_some() || response.extensions().get::<RoutedDomain>().is_some() {
+    host.as_str()
+  } else {
+    UNKNOWN_DOMAIN
+  };
+  record_request(domain, status, latency, bytes);
+  response
+}
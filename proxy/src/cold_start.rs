@@ -0,0 +1,37 @@
+use crate::forward::Clients;
+use anyhow::anyhow;
+use axum::body::Body;
+use axum::extract::Request;
+use axum::http::header::AUTHORIZATION;
+use dosei_proxy_core::cold_start;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Asks doseid to start the containers of a deployment stopped for being idle, holding the
+/// request until one of them accepts connections.
+pub async fn wake(
+  clients: &Clients,
+  doseid_url: &str,
+  proxy_token: Option<&str>,
+  deployment_id: Uuid,
+  upstreams: &[String],
+  cold_start_timeout: Duration,
+) -> anyhow::Result<()> {
+  cold_start::wake(deployment_id, upstreams, cold_start_timeout, || async {
+    let uri = format!(
+      "{}/deployments/{}/wake",
+      doseid_url.trim_end_matches('/'),
+      deployment_id
+    );
+    let mut request = Request::post(uri);
+    if let Some(proxy_token) = proxy_token {
+      request = request.header(AUTHORIZATION, format!("Bearer {}", proxy_token));
+    }
+    let response = clients.http1.request(request.body(Body::empty())?).await?;
+    if !response.status().is_success() {
+      return Err(anyhow!("doseid responded {}", response.status()));
+    }
+    Ok(())
+  })
+  .await
+}
@@ -1,7 +1,10 @@
-use anyhow::Context;
-use clap::Parser;
+use anyhow::{anyhow, Context};
+use clap::{Parser, ValueEnum};
+use dosei_proxy_core::client_ip::parse_trusted_proxy;
 use dotenv::dotenv;
+use ipnet::IpNet;
 use std::fmt::Formatter;
+use std::time::Duration;
 use std::{env, fmt};
 
 #[derive(Parser, Debug)]
@@ -13,12 +16,102 @@ struct Args {
   port: u16,
   #[arg(short, long)]
   connect: Option<String>,
+  #[arg(long, value_enum, default_value_t = Upstream::Local, help = "How to reach upstreams.")]
+  upstream: Upstream,
+  #[arg(
+    long,
+    default_value = "default",
+    help = "Kubernetes namespace of the services."
+  )]
+  kubernetes_namespace: String,
+  #[arg(
+    long,
+    help = "Terminate TLS on this port, redirecting plain HTTP to it."
+  )]
+  tls_port: Option<u16>,
+  #[arg(
+    long,
+    default_value = "http://127.0.0.1:8844",
+    help = "doseid API answering ACME challenges."
+  )]
+  doseid_url: String,
+  #[arg(
+    long,
+    default_value = "127.0.0.1",
+    help = "Host serving /health and /metrics, apart from routed domains."
+  )]
+  internal_host: String,
+  #[arg(
+    long,
+    default_value = "9090",
+    help = "Port serving /health and /metrics, apart from routed domains."
+  )]
+  internal_port: u16,
+  #[arg(long, value_enum, default_value_t = LogFormat::Text, help = "Format of logs, including access logs.")]
+  log_format: LogFormat,
+  #[arg(
+    long,
+    default_value = "5",
+    help = "Seconds to wait for a connection to an upstream."
+  )]
+  connect_timeout: u64,
+  #[arg(
+    long,
+    default_value = "60",
+    help = "Seconds to wait for the response headers of an upstream."
+  )]
+  read_timeout: u64,
+  #[arg(
+    long,
+    default_value = "2",
+    help = "Times a failed request without a body is retried, if it is safe to."
+  )]
+  retries: u32,
+  #[arg(
+    long,
+    default_value = "60",
+    help = "Seconds to hold a request while its idle deployment starts."
+  )]
+  cold_start_timeout: u64,
+  #[arg(
+    long,
+    value_delimiter = ',',
+    help = "Addresses or CIDR blocks of proxies in front, whose X-Forwarded-For is trusted."
+  )]
+  trusted_proxies: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum LogFormat {
+  Text,
+  /// One JSON object per line, access log fields included at the top level.
+  Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Upstream {
+  /// Forward to `<project_id>.<namespace>.svc.cluster.local`.
+  Kubernetes,
+  /// Forward to the host and port doseid exposed the active deployment on.
+  Local,
 }
 
 #[derive(Debug, Clone)]
 pub struct Config {
   pub address: Address,
-  pub mongo_url: String,
+  pub database_url: String,
+  pub upstream: Upstream,
+  pub kubernetes_namespace: String,
+  pub tls_address: Option<Address>,
+  pub internal_address: Address,
+  pub doseid_url: String,
+  pub connect_timeout: Duration,
+  pub read_timeout: Duration,
+  pub retries: u32,
+  pub cold_start_timeout: Duration,
+  pub trusted_proxies: Vec<IpNet>,
+  /// Shared with doseid, authenticates the requests waking idle deployments.
+  pub proxy_token: Option<String>,
 }
 
 impl Config {
@@ -31,16 +124,47 @@ impl Config {
 
     let subscriber = tracing_subscriber::fmt()
       .with_line_number(true)
-      .with_target(true)
-      .finish();
-    tracing::subscriber::set_global_default(subscriber)?;
+      .with_target(true);
+    match args.log_format {
+      LogFormat::Text => tracing::subscriber::set_global_default(subscriber.finish())?,
+      LogFormat::Json => {
+        tracing::subscriber::set_global_default(subscriber.json().flatten_event(true).finish())?
+      }
+    }
+
+    let trusted_proxies = args
+      .trusted_proxies
+      .iter()
+      .map(|proxy| {
+        parse_trusted_proxy(proxy).ok_or_else(|| anyhow!("Invalid trusted proxy {}", proxy))
+      })
+      .collect::<anyhow::Result<Vec<_>>>()?;
 
     Ok(Config {
       address: Address {
         host: args.host.clone(),
         port: args.port,
       },
-      mongo_url: env::var("MONGODB_URL").context("MONGODB_URL is required.")?,
+      database_url: env::var("DATABASE_URL").context("DATABASE_URL is required.")?,
+      upstream: args.upstream,
+      kubernetes_namespace: args.kubernetes_namespace,
+      tls_address: args.tls_port.map(|port| Address {
+        host: args.host.clone(),
+        port,
+      }),
+      internal_address: Address {
+        host: args.internal_host,
+        port: args.internal_port,
+      },
+      doseid_url: args.doseid_url,
+      connect_timeout: Duration::from_secs(args.connect_timeout),
+      read_timeout: Duration::from_secs(args.read_timeout),
+      retries: args.retries,
+      cold_start_timeout: Duration::from_secs(args.cold_start_timeout),
+      trusted_proxies,
+      proxy_token: env::var("PROXY_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty()),
     })
   }
 }
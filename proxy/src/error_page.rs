@@ -0,0 +1,152 @@
+use axum::http::header::{ACCEPT, CONTENT_TYPE};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use cached::{Cached, TimedCache};
+use once_cell::sync::Lazy;
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+use tokio::sync::Mutex;
+use tracing::error;
+use uuid::Uuid;
+
+/// Postgres channel doseid notifies when an owner changes one of their error pages.
+pub const ERROR_PAGE_CHANNEL: &str = "dosei_error_pages";
+
+/// Pages set by owners, by status code, for as long as the cache lifespan or a change.
+pub static ERROR_PAGES_CACHE: Lazy<Mutex<TimedCache<Uuid, HashMap<u16, String>>>> =
+  Lazy::new(|| Mutex::new(TimedCache::with_lifespan(300)));
+
+/// Responds with the page the owner of the domain set for the status, or the default one.
+/// Clients not asking for HTML, e.g. API clients, get the reason as plain text.
+pub async fn error_response(
+  pool: &Pool<Postgres>,
+  owner_id: Option<Uuid>,
+  status: StatusCode,
+  headers: &HeaderMap,
+) -> Response {
+  if !wants_html(headers) {
+    return (status, status.canonical_reason().unwrap_or_default()).into_response();
+  }
+  let page = match owner_id {
+    Some(owner_id) => owner_error_pages(pool, owner_id)
+      .await
+      .remove(&status.as_u16()),
+    None => None,
+  };
+  (
+    status,
+    [(
+      CONTENT_TYPE,
+      HeaderValue::from_static("text/html; charset=utf-8"),
+    )],
+    page.unwrap_or_else(|| default_page(status)),
+  )
+    .into_response()
+}
+
+async fn owner_error_pages(pool: &Pool<Postgres>, owner_id: Uuid) -> HashMap<u16, String> {
+  if let Some(pages) = ERROR_PAGES_CACHE.lock().await.cache_get(&owner_id) {
+    return pages.clone();
+  }
+  match sqlx::query!(
+    "SELECT status_code, content FROM error_page WHERE owner_id = $1",
+    owner_id
+  )
+  .fetch_all(pool)
+  .await
+  {
+    Ok(records) => {
+      let pages: HashMap<u16, String> = records
+        .into_iter()
+        .map(|record| (record.status_code as u16, record.content))
+        .collect();
+      ERROR_PAGES_CACHE
+        .lock()
+        .await
+        .cache_set(owner_id, pages.clone());
+      pages
+    }
+    Err(err) => {
+      error!("Failed to get error pages of {}: {}", owner_id, err);
+      HashMap::new()
+    }
+  }
+}
+
+fn wants_html(headers: &HeaderMap) -> bool {
+  match headers.get(ACCEPT).and_then(|accept| accept.to_str().ok()) {
+    None => true,
+    Some(accept) => accept
+      .split(',')
+      .map(|media_range| media_range.split(';').next().unwrap_or_default().trim())
+      .any(|media_type| matches!(media_type, "text/html" | "text/*" | "*/*")),
+  }
+}
+
+fn default_page(status: StatusCode) -> String {
+  let message = match status {
+    StatusCode::FORBIDDEN => "You don't have access to this page.",
+    StatusCode::NOT_FOUND => "There is nothing deployed here.",
+    StatusCode::TOO_MANY_REQUESTS => "Too many requests, try again in a moment.",
+    StatusCode::BAD_GATEWAY => "The application is not responding.",
+    StatusCode::SERVICE_UNAVAILABLE => "The application is not available right now.",
+    StatusCode::GATEWAY_TIMEOUT => "The application took too long to respond.",
+    _ => "Something went wrong.",
+  };
+  format!(
+    r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <meta name="viewport" content="width=device-width, initial-scale=1">
+  <title>{code} {reason}</title>
+  <style>
+    body {{ margin: 0; min-height: 100vh; display: flex; align-items: center; justify-content: center; font-family: system-ui, sans-serif; background: #0a0a0a; color: #ededed; }}
+    main {{ text-align: center; }}
+    h1 {{ margin: 0; font-size: 4rem; }}
+    p {{ color: #a1a1a1; }}
+    footer {{ position: fixed; bottom: 1.5rem; font-size: 0.875rem; color: #666; }}
+  </style>
+</head>
+<body>
+  <main>
+    <h1>{code}</h1>
+    <p>{message}</p>
+  </main>
+  <footer>Dosei</footer>
+</body>
+</html>
+"#,
+    code = status.as_u16(),
+    reason = status.canonical_reason().unwrap_or_default(),
+    message = message
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::error_page::{default_page, wants_html};
+  use axum::http::{HeaderMap, StatusCode};
+
+  #[test]
+  fn test_wants_html() {
+    let mut headers = HeaderMap::new();
+    assert!(wants_html(&headers));
+    headers.insert(
+      "accept",
+      "text/html,application/xhtml+xml;q=0.9".parse().unwrap(),
+    );
+    assert!(wants_html(&headers));
+    headers.insert("accept", "*/*".parse().unwrap());
+    assert!(wants_html(&headers));
+    headers.insert("accept", "application/json".parse().unwrap());
+    assert!(!wants_html(&headers));
+  }
+
+  #[test]
+  fn test_default_page() {
+    let page = default_page(StatusCode::GATEWAY_TIMEOUT);
+    assert!(page.contains("<title>504 Gateway Timeout</title>"));
+    assert!(page.contains("took too long"));
+  }
+}
@@ -0,0 +1,321 @@
+use axum::body::{Body, HttpBody};
+use axum::extract::Request;
+use axum::http::header::{CONNECTION, TE, UPGRADE};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Version};
+use axum::response::{IntoResponse, Response};
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client as LegacyClient;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use std::fmt;
+use std::fmt::Formatter;
+use std::time::Duration;
+use tokio::io::copy_bidirectional;
+use tokio::time::{sleep, timeout};
+use tracing::warn;
+
+pub type Client = LegacyClient<HttpConnector, Body>;
+
+/// Headers describing a single connection, which must not be forwarded as is.
+const HOP_BY_HOP_HEADERS: [&str; 9] = [
+  "connection",
+  "keep-alive",
+  "proxy-authenticate",
+  "proxy-authorization",
+  "proxy-connection",
+  "te",
+  "trailer",
+  "transfer-encoding",
+  "upgrade",
+];
+
+/// Pause before retrying a request, multiplied by the attempt.
+const RETRY_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Why a request could not be forwarded to its upstream.
+#[derive(Debug)]
+pub enum ForwardError {
+  /// The upstream did not send the response headers within the read timeout.
+  Timeout,
+  /// The upstream could not be reached, or closed the connection without responding.
+  Upstream(hyper_util::client::legacy::Error),
+}
+
+impl ForwardError {
+  /// The status responded with instead of the one of the upstream.
+  pub fn status(&self) -> StatusCode {
+    match self {
+      ForwardError::Timeout => StatusCode::GATEWAY_TIMEOUT,
+      ForwardError::Upstream(_) => StatusCode::BAD_GATEWAY,
+    }
+  }
+}
+
+impl fmt::Display for ForwardError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    match self {
+      ForwardError::Timeout => write!(f, "upstream timed out"),
+      ForwardError::Upstream(err) => write!(f, "{}", err),
+    }
+  }
+}
+
+/// Connection pools to upstreams, over HTTP/1.1 or HTTP/2 with prior knowledge (h2c).
+#[derive(Clone)]
+pub struct Clients {
+  pub http1: Client,
+  pub http2: Client,
+  read_timeout: Duration,
+  retries: u32,
+}
+
+impl Clients {
+  pub fn new(connect_timeout: Duration, read_timeout: Duration, retries: u32) -> Clients {
+    let mut connector = HttpConnector::new();
+    connector.set_connect_timeout(Some(connect_timeout));
+    Clients {
+      http1: LegacyClient::builder(TokioExecutor::new()).build(connector.clone()),
+      http2: LegacyClient::builder(TokioExecutor::new())
+        .http2_only(true)
+        .build(connector),
+      read_timeout,
+      retries,
+    }
+  }
+
+  /// Forwards a request whose URI already points at the upstream. Upgrade requests, e.g.
+  /// WebSockets, go over HTTP/1.1 and their connections are spliced once the upstream accepts.
+  pub async fn forward(&self, mut req: Request, http2: bool) -> Result<Response, ForwardError> {
+    if is_upgrade(req.headers()) {
+      let client_upgrade = hyper::upgrade::on(&mut req);
+      *req.version_mut() = Version::HTTP_11;
+      let mut response = timeout(self.read_timeout, self.http1.request(req))
+        .await
+        .map_err(|_| ForwardError::Timeout)?
+        .map_err(ForwardError::Upstream)?;
+      if response.status() == StatusCode::SWITCHING_PROTOCOLS {
+        let upstream_upgrade = hyper::upgrade::on(&mut response);
+        tokio::spawn(async move {
+          match tokio::try_join!(client_upgrade, upstream_upgrade) {
+            Ok((client, upstream)) => {
+              let (mut client, mut upstream) = (TokioIo::new(client), TokioIo::new(upstream));
+              if let Err(err) = copy_bidirectional(&mut client, &mut upstream).await {
+                warn!("Upgraded connection closed: {}", err);
+              }
+            }
+            Err(err) => warn!("Failed to upgrade connection: {}", err),
+          }
+        });
+      }
+      return Ok(response.into_response());
+    }
+    remove_hop_by_hop_headers(req.headers_mut());
+    let client = if http2 {
+      *req.version_mut() = Version::HTTP_2;
+      &self.http2
+    } else {
+      *req.version_mut() = Version::HTTP_11;
+      &self.http1
+    };
+    let (parts, body) = req.into_parts();
+    // Bodies are streamed to the upstream, so only requests without one can be sent again.
+    let replayable = body.size_hint().exact() == Some(0);
+    let mut body = Some(body);
+    let mut attempt = 0;
+    loop {
+      let mut req = Request::new(body.take().unwrap_or_else(Body::empty));
+      *req.method_mut() = parts.method.clone();
+      *req.uri_mut() = parts.uri.clone();
+      *req.version_mut() = parts.version;
+      *req.headers_mut() = parts.headers.clone();
+      match timeout(self.read_timeout, client.request(req)).await {
+        Ok(Ok(mut response)) => {
+          remove_hop_by_hop_headers(response.headers_mut());
+          return Ok(response.into_response());
+        }
+        // A slow upstream is likely to be slow again, retrying would only keep the client waiting.
+        Err(_) => return Err(ForwardError::Timeout),
+        Ok(Err(err)) => {
+          // Requests that never reached the upstream are safe to send again, others only if
+          // sending them twice has the same effect as once.
+          let retryable = err.is_connect() || is_idempotent(&parts.method);
+          if !replayable || !retryable || attempt >= self.retries {
+            return Err(ForwardError::Upstream(err));
+          }
+          attempt += 1;
+          warn!(
+            "Retrying {} {} ({}/{}): {}",
+            parts.method, parts.uri, attempt, self.retries, err
+          );
+          sleep(RETRY_BACKOFF * attempt).await;
+        }
+      }
+    }
+  }
+}
+
+fn is_idempotent(method: &Method) -> bool {
+  matches!(
+    *method,
+    Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE | Method::PUT | Method::DELETE
+  )
+}
+
+/// Whether the client asks to switch protocols, e.g. `Connection: upgrade` and
+/// `Upgrade: websocket`.
+fn is_upgrade(headers: &HeaderMap) -> bool {
+  headers.contains_key(UPGRADE)
+    && headers
+      .get_all(CONNECTION)
+      .iter()
+      .filter_map(|value| value.to_str().ok())
+      .flat_map(|value| value.split(','))
+      .any(|token| token.trim().eq_ignore_ascii_case("upgrade"))
+}
+
+/// Removes hop-by-hop headers, including those the `Connection` header lists. HTTP/2 upstreams
+/// reject requests carrying any of them, except `TE: trailers` which gRPC needs and is kept.
+fn remove_hop_by_hop_headers(headers: &mut HeaderMap) {
+  let accepts_trailers = headers
+    .get_all(TE)
+    .iter()
+    .filter_map(|value| value.to_str().ok())
+    .flat_map(|value| value.split(','))
+    .any(|token| {
+      let coding = token.split(';').next().unwrap_or_default();
+      coding.trim().eq_ignore_ascii_case("trailers")
+    });
+  let listed: Vec<HeaderName> = headers
+    .get_all(CONNECTION)
+    .iter()
+    .filter_map(|value| value.to_str().ok())
+    .flat_map(|value| value.split(','))
+    .filter_map(|token| HeaderName::try_from(token.trim()).ok())
+    .collect();
+  for name in listed {
+    headers.remove(name);
+  }
+  for name in HOP_BY_HOP_HEADERS {
+    headers.remove(name);
+  }
+  if accepts_trailers {
+    headers.insert(TE, HeaderValue::from_static("trailers"));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::forward::{
+    is_idempotent, is_upgrade, remove_hop_by_hop_headers, Clients, ForwardError,
+  };
+  use axum::body::Body;
+  use axum::extract::Request;
+  use axum::http::{HeaderMap, Method, StatusCode};
+  use std::time::Duration;
+  use tokio::io::{AsyncReadExt, AsyncWriteExt};
+  use tokio::net::TcpListener;
+
+  fn clients(read_timeout: Duration) -> Clients {
+    Clients::new(Duration::from_secs(1), read_timeout, 2)
+  }
+
+  fn get(address: std::net::SocketAddr) -> Request {
+    Request::builder()
+      .uri(format!("http://{}/", address))
+      .body(Body::empty())
+      .unwrap()
+  }
+
+  #[test]
+  fn test_is_upgrade() {
+    let mut headers = HeaderMap::new();
+    headers.insert("connection", "keep-alive, Upgrade".parse().unwrap());
+    assert!(!is_upgrade(&headers));
+    headers.insert("upgrade", "websocket".parse().unwrap());
+    assert!(is_upgrade(&headers));
+    headers.insert("connection", "keep-alive".parse().unwrap());
+    assert!(!is_upgrade(&headers));
+  }
+
+  #[test]
+  fn test_remove_hop_by_hop_headers() {
+    let mut headers = HeaderMap::new();
+    headers.insert("connection", "keep-alive, x-session".parse().unwrap());
+    headers.insert("keep-alive", "timeout=5".parse().unwrap());
+    headers.insert("x-session", "1".parse().unwrap());
+    headers.insert("transfer-encoding", "chunked".parse().unwrap());
+    headers.insert("content-type", "text/plain".parse().unwrap());
+    remove_hop_by_hop_headers(&mut headers);
+    assert_eq!(headers.len(), 1);
+    assert!(headers.contains_key("content-type"));
+
+    let mut headers = HeaderMap::new();
+    headers.insert("connection", "te".parse().unwrap());
+    headers.insert("te", "gzip, trailers".parse().unwrap());
+    remove_hop_by_hop_headers(&mut headers);
+    assert_eq!(headers.get("te").unwrap(), "trailers");
+
+    let mut headers = HeaderMap::new();
+    headers.insert("te", "gzip".parse().unwrap());
+    remove_hop_by_hop_headers(&mut headers);
+    assert!(!headers.contains_key("te"));
+  }
+
+  #[test]
+  fn test_is_idempotent() {
+    assert!(is_idempotent(&Method::GET));
+    assert!(is_idempotent(&Method::DELETE));
+    assert!(!is_idempotent(&Method::POST));
+    assert!(!is_idempotent(&Method::PATCH));
+  }
+
+  #[tokio::test]
+  async fn test_forward_unreachable_upstream() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    drop(listener);
+    let err = clients(Duration::from_secs(1))
+      .forward(get(address), false)
+      .await
+      .unwrap_err();
+    assert!(matches!(&err, ForwardError::Upstream(err) if err.is_connect()));
+    assert_eq!(err.status(), StatusCode::BAD_GATEWAY);
+  }
+
+  #[tokio::test]
+  async fn test_forward_timeout() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let upstream = tokio::spawn(async move { listener.accept().await });
+    let err = clients(Duration::from_millis(100))
+      .forward(get(address), false)
+      .await
+      .unwrap_err();
+    assert!(matches!(err, ForwardError::Timeout));
+    assert_eq!(err.status(), StatusCode::GATEWAY_TIMEOUT);
+    upstream.abort();
+  }
+
+  #[tokio::test]
+  async fn test_forward_retries_idempotent_request() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+      // The first connection is closed before responding, the second one is answered.
+      let (mut stream, _) = listener.accept().await.unwrap();
+      let mut buffer = [0; 1024];
+      let _ = stream.read(&mut buffer).await;
+      drop(stream);
+      let (mut stream, _) = listener.accept().await.unwrap();
+      let _ = stream.read(&mut buffer).await;
+      stream
+        .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok")
+        .await
+        .unwrap();
+    });
+    let response = clients(Duration::from_secs(1))
+      .forward(get(address), false)
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+  }
+}
@@ -4,62 +4,139 @@
 //!
 //! Dosei Proxy
 //!
-//! Built to run on Dosei K8S Cluster with MongoDB.
+//! Routes domains from the doseid Postgres database, either to Kubernetes services on the
+//! Dosei cluster or to the ports doseid exposed deployments on.
 //! Currently WIP.
 //! TODO:
 //! - Implement Redis for Caching
-//! - Migrate to Postgres
-//! - Run on Dosei Engine
-//! - Move /health to only check for internal traffic
 //! - Implement events: onProxyPassEvent
 
+mod access_log;
+mod cold_start;
 mod config;
+mod error_page;
+mod forward;
+mod metrics;
+mod tls;
+mod upstream;
 
-use crate::config::Config;
+use crate::access_log::{access_log, Client, ForwardedTo, RoutedDomain};
+use crate::config::{Config, Upstream};
+use crate::error_page::{error_response, ERROR_PAGES_CACHE, ERROR_PAGE_CHANNEL};
+use crate::forward::Clients;
+use crate::upstream::{
+  Instance, KubernetesDnsResolver, LocalPortResolver, RouteTarget, UpstreamResolver,
+};
 use anyhow::Context;
+use axum::middleware::{self, Next};
 use axum::response::Redirect;
 use axum::routing::get;
 use axum::{
-  body::Body,
   extract::{Request, State},
   http::uri::Uri,
+  http::HeaderValue,
   response::{IntoResponse, Response},
   routing::any,
   Extension, Router,
 };
 use cached::{Cached, TimedCache};
+use dosei_proxy_core::balancer::{LoadBalancing, BALANCER};
+use dosei_proxy_core::client_ip::set_forwarded_for;
+use dosei_proxy_core::cold_start::record_activity;
+use dosei_proxy_core::host::normalize_host;
+use dosei_proxy_core::rules::{self, DomainRules};
 use hyper::StatusCode;
-use hyper_util::{client::legacy::connect::HttpConnector, rt::TokioExecutor};
-use mongodb::bson::{doc, Bson, Document};
-use mongodb::Database;
 use once_cell::sync::Lazy;
+use sqlx::postgres::PgListener;
+use sqlx::{Pool, Postgres};
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::net::TcpListener;
 use tokio::sync::Mutex;
-use tracing::info;
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+
+/// Postgres channel doseid notifies when the deployment a domain routes to may have changed.
+const ROUTING_CHANNEL: &str = "dosei_routing";
 
-type Client = hyper_util::client::legacy::Client<HttpConnector, Body>;
+/// Scheme the client used, forwarded to upstreams as `X-Forwarded-Proto`.
+#[derive(Clone, Copy)]
+struct ForwardedProto(&'static str);
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
   let config: &'static Config = Box::leak(Box::new(Config::new()?));
-  let client_options = mongodb::options::ClientOptions::parse(&config.mongo_url).await?;
-  let client = mongodb::Client::with_options(client_options)?;
-  client
-    .database("admin")
-    .run_command(doc! {"ping": 1}, None)
-    .await?;
-  info!("Successfully connected to MongoDB");
-  let shared_mongo_client = Arc::new(client);
-  let client: Client = hyper_util::client::legacy::Client::<(), ()>::builder(TokioExecutor::new())
-    .build(HttpConnector::new());
+  let pool = Pool::<Postgres>::connect(&config.database_url)
+    .await
+    .context("Failed to connect to Postgres")?;
+  info!("Successfully connected to Postgres");
+  let shared_pool = Arc::new(pool);
+  start_cache_invalidation(Arc::clone(&shared_pool));
+  rules::start_rate_limiter_eviction();
+  let upstream_resolver: Arc<dyn UpstreamResolver> = match config.upstream {
+    Upstream::Kubernetes => Arc::new(KubernetesDnsResolver {
+      namespace: config.kubernetes_namespace.clone(),
+    }),
+    Upstream::Local => Arc::new(LocalPortResolver),
+  };
+  let clients = Clients::new(config.connect_timeout, config.read_timeout, config.retries);
 
-  let app = Router::new()
+  // Served on their own address, so every path of routed domains reaches their apps.
+  let internal = Router::new()
     .route("/health", get(health))
+    .route("/metrics", get(metrics))
+    .layer(Extension(Arc::clone(&shared_pool)));
+  let internal_listener = TcpListener::bind(config.internal_address.to_string())
+    .await
+    .context("Failed to start internal server")?;
+  info!(
+    "Dosei Proxy serving /health and /metrics on http://{}",
+    config.internal_address
+  );
+  tokio::spawn(async move {
+    if let Err(err) = axum::serve(internal_listener, internal).await {
+      error!("Internal server stopped: {}", err);
+    }
+  });
+
+  let proxy = Router::new()
     .route("/", any(handler))
     .route("/*path", any(handler))
-    .with_state(client)
-    .layer(Extension(Arc::clone(&shared_mongo_client)));
+    .with_state(clients.clone());
+
+  let app = match &config.tls_address {
+    None => proxy,
+    Some(tls_address) => {
+      tls::start_certificate_reload(Arc::clone(&shared_pool));
+      let https_app = proxy
+        .clone()
+        .layer(middleware::from_fn(access_log))
+        .layer(Extension(ForwardedProto("https")))
+        .layer(Extension(Arc::clone(&upstream_resolver)))
+        .layer(Extension(Arc::clone(&shared_pool)))
+        .layer(Extension(config));
+      let tls_listener = TcpListener::bind(tls_address.to_string())
+        .await
+        .context("Failed to start TLS server")?;
+      info!("Dosei Proxy running on https://{}", tls_address);
+      tokio::spawn(async move {
+        if let Err(err) = tls::serve_tls(tls_listener, https_app).await {
+          error!("TLS server stopped: {}", err);
+        }
+      });
+      // Plain HTTP only serves ACME challenges and hosts without a certificate yet.
+      Router::new()
+        .route("/.well-known/acme-challenge/*token", any(acme_challenge))
+        .with_state(clients)
+        .merge(proxy.layer(middleware::from_fn(redirect_to_https)))
+    }
+  }
+  .layer(middleware::from_fn(access_log))
+  .layer(Extension(ForwardedProto("http")))
+  .layer(Extension(upstream_resolver))
+  .layer(Extension(Arc::clone(&shared_pool)))
+  .layer(Extension(config));
 
   let address = config.address.to_string();
   let listener = TcpListener::bind(&address)
@@ -69,36 +146,86 @@ async fn main() -> anyhow::Result<()> {
     "Dosei Proxy running on http://{} (Press CTRL+C to quit",
     address
   );
-  axum::serve(listener, app).await?;
+  axum::serve(
+    listener,
+    app.into_make_service_with_connect_info::<SocketAddr>(),
+  )
+  .await?;
   Ok(())
 }
 
-async fn health(_mongo_client: Extension<Arc<mongodb::Client>>) -> Result<Response, StatusCode> {
-  // TODO: Fix, not sure wtf but not working prod
-  // let db: Database = mongo_client.database("admin");
-  // match db.run_command(doc! {"ping": 1}, None).await {
-  //   Ok(document) => {
-  //     if let Ok(ok_value) = document.get_f64("ok") {
-  //       if (ok_value - 1.0).abs() < f64::EPSILON {
-  //         return Ok((StatusCode::OK, "OK").into_response());
-  //       }
-  //     }
-  //     Err(StatusCode::INTERNAL_SERVER_ERROR)
-  //   }
-  //   Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-  // }
-  Ok((StatusCode::OK, "OK").into_response())
+async fn health(pool: Extension<Arc<Pool<Postgres>>>) -> Result<Response, StatusCode> {
+  match sqlx::query!("SELECT 1 AS ok").fetch_one(&**pool).await {
+    Ok(_) => Ok((StatusCode::OK, "OK").into_response()),
+    Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+  }
+}
+
+async fn metrics() -> Result<String, StatusCode> {
+  metrics::render().map_err(|err| {
+    error!("Failed to render metrics: {}", err);
+    StatusCode::INTERNAL_SERVER_ERROR
+  })
 }
 
-async fn handler(
-  mongo_client: Extension<Arc<mongodb::Client>>,
-  State(client): State<Client>,
+async fn redirect_to_https(req: Request, next: Next) -> Response {
+  let host = req
+    .headers()
+    .get("host")
+    .and_then(|host| host.to_str().ok())
+    .map(|host| host.split(':').next().unwrap_or(host).to_string());
+  match host {
+    Some(host) if tls::CERTIFICATES.get(&host).is_some() => {
+      let path_query = req
+        .uri()
+        .path_and_query()
+        .map(|v| v.as_str())
+        .unwrap_or("/");
+      Redirect::permanent(&format!("https://{}{}", host, path_query)).into_response()
+    }
+    _ => next.run(req).await,
+  }
+}
+
+/// Passes HTTP-01 challenges through to doseid, which answers them for its ACME orders.
+async fn acme_challenge(
+  config: Extension<&'static Config>,
+  State(clients): State<Clients>,
   mut req: Request,
 ) -> Result<Response, StatusCode> {
-  let headers = req.headers();
-  let host = match headers.get("host") {
-    Some(host_header) => host_header.to_str().unwrap_or_default(),
-    None => return Ok(Redirect::temporary("https://dosei.ai").into_response()),
+  let path_query = req
+    .uri()
+    .path_and_query()
+    .map(|v| v.as_str())
+    .unwrap_or("/");
+  let uri = format!("{}{}", config.doseid_url.trim_end_matches('/'), path_query);
+  *req.uri_mut() = Uri::try_from(uri).map_err(|_| StatusCode::BAD_REQUEST)?;
+  Ok(
+    clients
+      .http1
+      .request(req)
+      .await
+      .map_err(|_| StatusCode::BAD_GATEWAY)?
+      .into_response(),
+  )
+}
+
+async fn handler(
+  config: Extension<&'static Config>,
+  pool: Extension<Arc<Pool<Postgres>>>,
+  upstream_resolver: Extension<Arc<dyn UpstreamResolver>>,
+  Extension(forwarded_proto): Extension<ForwardedProto>,
+  Extension(client): Extension<Client>,
+  State(clients): State<Clients>,
+  mut req: Request,
+) -> Response {
+  // HTTP/2 clients send the host as the `:authority` of the URI instead.
+  let host = match req.headers().get("host") {
+    Some(host_header) => normalize_host(host_header.to_str().unwrap_or_default()),
+    None => match req.uri().host() {
+      Some(host) => normalize_host(host),
+      None => return error_response(&pool, None, StatusCode::NOT_FOUND, req.headers()).await,
+    },
   };
   let path = req.uri().path();
   let path_query = req
@@ -106,55 +233,242 @@ async fn handler(
     .path_and_query()
     .map(|v| v.as_str())
     .unwrap_or(path);
-  match get_domain(mongo_client, host.to_string()).await {
-    None => Ok(Redirect::temporary("https://dosei.ai").into_response()),
-    Some(service_id) => {
-      let uri = format!(
-        "http://{}.default.svc.cluster.local{}",
-        service_id, path_query
-      );
-      info!("Forwarding: {} -> {}", host, uri);
-      *req.uri_mut() = Uri::try_from(uri).unwrap();
-      Ok(
-        client
-          .request(req)
-          .await
-          .map_err(|_| StatusCode::BAD_REQUEST)?
-          .into_response(),
+  let Some(target) = get_domain(&pool, host.clone()).await else {
+    return error_response(&pool, None, StatusCode::NOT_FOUND, req.headers()).await;
+  };
+  let upstreams = upstream_resolver.resolve(&target);
+  let balanced = target
+    .deployment_id
+    .or(target.project_id)
+    .map_or_else(|| host.clone(), |id| id.to_string());
+  let Some(lease) = BALANCER.pick(&balanced, &upstreams, target.load_balancing, Instant::now())
+  else {
+    // The domain exists, but nothing of its project is ready to serve it.
+    let mut response = error_response(
+      &pool,
+      Some(target.owner_id),
+      StatusCode::SERVICE_UNAVAILABLE,
+      req.headers(),
+    )
+    .await;
+    response.extensions_mut().insert(RoutedDomain);
+    return response;
+  };
+  if let Some(rejection) = target.rules.check(&host, client.ip) {
+    let mut response = error_response(
+      &pool,
+      Some(target.owner_id),
+      rejection.status(),
+      req.headers(),
+    )
+    .await;
+    rejection.apply(&mut response);
+    response.extensions_mut().insert(RoutedDomain);
+    return response;
+  }
+  if let Some(deployment_id) = target.deployment_id {
+    if target.is_stopped() {
+      if let Err(err) = cold_start::wake(
+        &clients,
+        &config.doseid_url,
+        config.proxy_token.as_deref(),
+        deployment_id,
+        &upstreams,
+        config.cold_start_timeout,
       )
+      .await
+      {
+        error!("Failed to wake deployment {}: {}", deployment_id, err);
+        let mut response = error_response(
+          &pool,
+          Some(target.owner_id),
+          StatusCode::SERVICE_UNAVAILABLE,
+          req.headers(),
+        )
+        .await;
+        response.extensions_mut().insert(RoutedDomain);
+        return response;
+      }
     }
+    record_activity(Arc::clone(&pool), deployment_id);
   }
+  let upstream = lease.upstream().to_string();
+  let uri = format!("http://{}{}", upstream, path_query);
+  *req.uri_mut() = match Uri::try_from(uri) {
+    Ok(uri) => uri,
+    Err(_) => {
+      // The path and query came from the client, and won't form a valid URI with the upstream.
+      let mut response = error_response(
+        &pool,
+        Some(target.owner_id),
+        StatusCode::BAD_REQUEST,
+        req.headers(),
+      )
+      .await;
+      response.extensions_mut().insert(RoutedDomain);
+      return response;
+    }
+  };
+  set_forwarded_for(req.headers_mut(), client.peer, &config.trusted_proxies);
+  req.headers_mut().insert(
+    "x-forwarded-proto",
+    HeaderValue::from_static(forwarded_proto.0),
+  );
+  if !req.headers().contains_key("host") {
+    if let Ok(host) = HeaderValue::from_str(&host) {
+      req.headers_mut().insert("host", host);
+    }
+  }
+  let headers = req.headers().clone();
+  let mut response = match clients.forward(req, target.http2).await {
+    Ok(response) => {
+      let failed = matches!(
+        response.status(),
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+      );
+      BALANCER.report(&upstream, !failed, Instant::now());
+      lease.attach(response)
+    }
+    Err(err) => {
+      warn!("Failed to forward {} to {}: {}", host, upstream, err);
+      BALANCER.report(&upstream, false, Instant::now());
+      error_response(&pool, Some(target.owner_id), err.status(), &headers).await
+    }
+  };
+  response.extensions_mut().insert(ForwardedTo(upstream));
+  response
 }
 
-async fn get_domain(mongo_client: Extension<Arc<mongodb::Client>>, host: String) -> Option<String> {
+async fn get_domain(pool: &Pool<Postgres>, host: String) -> Option<RouteTarget> {
   let domains_cache = Arc::clone(&DOMAINS_CACHE);
   {
     let mut cache = domains_cache.lock().await;
     if let Some(value) = cache.cache_get(&host) {
-      let service_id = value.clone();
-      return cache.cache_set(host, service_id);
+      return Some(value.clone());
     }
   }
 
-  let db: Database = mongo_client.database("fast");
-  let collection = db.collection::<Document>("domains");
+  // Domains pinned to a deployment route to it, others to the latest ready deployment.
+  let record = sqlx::query!(
+    r#"
+    SELECT
+      dm.owner_id, dm.project_id, d.id AS "deployment_id?", p.load_balancing::text,
+      ARRAY(
+        SELECT COALESCE(i.exposed_host, '') FROM deployment_instance i
+        WHERE i.deployment_id = d.id ORDER BY i.created_at, i.id
+      ) AS "instance_hosts!",
+      ARRAY(
+        SELECT i.exposed_port FROM deployment_instance i
+        WHERE i.deployment_id = d.id ORDER BY i.created_at, i.id
+      ) AS "instance_ports!",
+      ARRAY(
+        SELECT i.healthy FROM deployment_instance i
+        WHERE i.deployment_id = d.id ORDER BY i.created_at, i.id
+      ) AS "instance_healthy!",
+      ARRAY(
+        SELECT i.stopped_at IS NOT NULL FROM deployment_instance i
+        WHERE i.deployment_id = d.id ORDER BY i.created_at, i.id
+      ) AS "instance_stopped!",
+      dm.rate_limit_per_second, dm.rate_limit_burst, dm.allowed_ips, dm.denied_ips,
+      dm.upstream_http2
+    FROM domain dm
+    LEFT JOIN project p ON p.id = dm.project_id
+    LEFT JOIN LATERAL (
+      SELECT id
+      FROM deployment
+      WHERE (
+        (dm.deployment_id IS NOT NULL AND id::text = dm.deployment_id)
+        OR (dm.deployment_id IS NULL AND project_id = dm.project_id)
+      ) AND status = 'ready'
+      ORDER BY created_at DESC
+      LIMIT 1
+    ) d ON true
+    WHERE dm.name = $1 AND dm.verified_at IS NOT NULL
+    "#,
+    host.to_lowercase()
+  )
+  .fetch_optional(pool)
+  .await;
+  match record {
+    Ok(Some(record)) => {
+      let target = RouteTarget {
+        owner_id: record.owner_id,
+        project_id: record.project_id,
+        deployment_id: record.deployment_id,
+        instances: record
+          .instance_hosts
+          .into_iter()
+          .zip(record.instance_ports)
+          .zip(record.instance_healthy)
+          .zip(record.instance_stopped)
+          .map(
+            |(((exposed_host, exposed_port), healthy), stopped)| Instance {
+              exposed_host: Some(exposed_host).filter(|host| !host.is_empty()),
+              exposed_port: exposed_port as u16,
+              healthy,
+              stopped,
+            },
+          )
+          .collect(),
+        load_balancing: LoadBalancing::from_column(record.load_balancing.as_deref()),
+        http2: record.upstream_http2,
+        rules: DomainRules::from_columns(
+          record.rate_limit_per_second,
+          record.rate_limit_burst,
+          record.allowed_ips,
+          record.denied_ips,
+        ),
+      };
+      {
+        let mut cache = domains_cache.lock().await;
+        cache.cache_set(host, target.clone());
+      }
+      Some(target)
+    }
+    Ok(None) => None,
+    Err(err) => {
+      error!("Failed to get domain {}: {}", host, err);
+      None
+    }
+  }
+}
 
-  match collection.find_one(doc! {"name": &host }, None).await {
-    Ok(Some(document)) => match document.get("service_id") {
-      Some(Bson::String(service_id)) => {
-        {
-          let mut cache = domains_cache.lock().await;
-          cache.cache_set(host, service_id.clone());
-        }
-        Some(service_id.clone())
+/// Clears cached domains whenever doseid reports a routing change, e.g. after a redeploy, and
+/// cached error pages whenever an owner changes theirs.
+fn start_cache_invalidation(pool: Arc<Pool<Postgres>>) {
+  tokio::spawn(async move {
+    loop {
+      if let Err(err) = listen_cache_changes(&pool).await {
+        error!("Cache listener failed: {}", err);
       }
-      _ => None,
-    },
-    _ => None,
+      DOMAINS_CACHE.lock().await.cache_clear();
+      ERROR_PAGES_CACHE.lock().await.cache_clear();
+      sleep(Duration::from_secs(1)).await;
+    }
+  });
+}
+
+async fn listen_cache_changes(pool: &Pool<Postgres>) -> anyhow::Result<()> {
+  let mut listener = PgListener::connect_with(pool).await?;
+  listener
+    .listen_all([ROUTING_CHANNEL, ERROR_PAGE_CHANNEL])
+    .await?;
+  loop {
+    match listener.try_recv().await? {
+      Some(notification) if notification.channel() == ERROR_PAGE_CHANNEL => {
+        ERROR_PAGES_CACHE.lock().await.cache_clear();
+      }
+      Some(_) => DOMAINS_CACHE.lock().await.cache_clear(),
+      // A reconnect may have missed some changes.
+      None => {
+        DOMAINS_CACHE.lock().await.cache_clear();
+        ERROR_PAGES_CACHE.lock().await.cache_clear();
+      }
+    }
   }
 }
 
-static DOMAINS_CACHE: Lazy<Arc<Mutex<TimedCache<String, String>>>> = Lazy::new(|| {
+static DOMAINS_CACHE: Lazy<Arc<Mutex<TimedCache<String, RouteTarget>>>> = Lazy::new(|| {
   let cache = TimedCache::with_lifespan(120);
   Arc::new(Mutex::new(cache))
 });
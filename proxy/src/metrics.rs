@@ -0,0 +1,103 @@
+use axum::http::StatusCode;
+use once_cell::sync::Lazy;
+use prometheus::{
+  register_histogram_vec_with_registry, register_int_counter_vec_with_registry, Encoder,
+  HistogramVec, IntCounterVec, Registry, TextEncoder,
+};
+use std::time::Duration;
+
+/// Label of requests for hosts that route nowhere, which would otherwise grow the label set
+/// without bound.
+pub const UNKNOWN_DOMAIN: &str = "unknown";
+
+static REGISTRY: Lazy<Registry> = Lazy::new(Registry::new);
+
+static REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+  register_int_counter_vec_with_registry!(
+    "dosei_proxy_requests_total",
+    "Requests proxied, by domain and status class.",
+    &["domain", "status"],
+    REGISTRY
+  )
+  .unwrap()
+});
+
+static REQUEST_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+  register_histogram_vec_with_registry!(
+    "dosei_proxy_request_duration_seconds",
+    "Time until the response headers were sent, by domain and status class.",
+    &["domain", "status"],
+    REGISTRY
+  )
+  .unwrap()
+});
+
+static RESPONSE_BYTES: Lazy<IntCounterVec> = Lazy::new(|| {
+  register_int_counter_vec_with_registry!(
+    "dosei_proxy_response_bytes_total",
+    "Bytes of response bodies with a known length, by domain and status class.",
+    &["domain", "status"],
+    REGISTRY
+  )
+  .unwrap()
+});
+
+pub fn record_request(domain: &str, status: StatusCode, latency: Duration, bytes: Option<u64>) {
+  let labels = [domain, status_class(status)];
+  REQUESTS.with_label_values(&labels).inc();
+  REQUEST_DURATION
+    .with_label_values(&labels)
+    .observe(latency.as_secs_f64());
+  if let Some(bytes) = bytes {
+    RESPONSE_BYTES.with_label_values(&labels).inc_by(bytes);
+  }
+}
+
+/// Every metric in the Prometheus text format.
+pub fn render() -> anyhow::Result<String> {
+  let mut buffer = Vec::new();
+  TextEncoder::new().encode(&REGISTRY.gather(), &mut buffer)?;
+  Ok(String::from_utf8(buffer)?)
+}
+
+fn status_class(status: StatusCode) -> &'static str {
+  match status.as_u16() {
+    100..=199 => "1xx",
+    200..=299 => "2xx",
+    300..=399 => "3xx",
+    400..=499 => "4xx",
+    _ => "5xx",
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::metrics::{record_request, render, status_class};
+  use axum::http::StatusCode;
+  use std::time::Duration;
+
+  #[test]
+  fn test_status_class() {
+    assert_eq!(status_class(StatusCode::SWITCHING_PROTOCOLS), "1xx");
+    assert_eq!(status_class(StatusCode::OK), "2xx");
+    assert_eq!(status_class(StatusCode::PERMANENT_REDIRECT), "3xx");
+    assert_eq!(status_class(StatusCode::NOT_FOUND), "4xx");
+    assert_eq!(status_class(StatusCode::BAD_GATEWAY), "5xx");
+  }
+
+  #[test]
+  fn test_render() {
+    record_request(
+      "metrics.example.com",
+      StatusCode::OK,
+      Duration::from_millis(20),
+      Some(512),
+    );
+    let metrics = render().unwrap();
+    assert!(metrics
+      .contains(r#"dosei_proxy_requests_total{domain="metrics.example.com",status="2xx"} 1"#));
+    assert!(metrics.contains(
+      r#"dosei_proxy_response_bytes_total{domain="metrics.example.com",status="2xx"} 512"#
+    ));
+  }
+}
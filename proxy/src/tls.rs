@@ -0,0 +1,194 @@
+use anyhow::{anyhow, Context};
+use axum::extract::ConnectInfo;
+use axum::http::Request;
+use axum::Router;
+use hyper::body::Incoming;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::service::TowerToHyperService;
+use once_cell::sync::Lazy;
+use sqlx::postgres::PgListener;
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+use std::io::BufReader;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::time::sleep;
+use tokio_rustls::rustls::crypto::ring::sign::any_supported_type;
+use tokio_rustls::rustls::server::{ClientHello, ResolvesServerCert};
+use tokio_rustls::rustls::sign::CertifiedKey;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+use tower::ServiceExt;
+use tracing::{error, info, warn};
+
+/// Postgres channel doseid notifies whenever a certificate is issued, renewed or removed.
+const CERTIFICATE_CHANNEL: &str = "dosei_certificates";
+/// Certificates are also reloaded periodically, in case a notification was missed.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(600);
+
+pub static CERTIFICATES: Lazy<Arc<CertificateStore>> =
+  Lazy::new(|| Arc::new(CertificateStore::default()));
+
+/// Certificates by domain name, picked per connection from the SNI the client sent.
+#[derive(Debug, Default)]
+pub struct CertificateStore {
+  certificates: RwLock<HashMap<String, Arc<CertifiedKey>>>,
+}
+
+impl CertificateStore {
+  /// Finds the certificate of a host, falling back to a wildcard certificate of its parent.
+  pub fn get(&self, host: &str) -> Option<Arc<CertifiedKey>> {
+    let host = host.to_lowercase();
+    let certificates = self.certificates.read().unwrap();
+    if let Some(certificate) = certificates.get(&host) {
+      return Some(Arc::clone(certificate));
+    }
+    let (_, parent) = host.split_once('.')?;
+    certificates.get(&format!("*.{}", parent)).cloned()
+  }
+
+  fn replace(&self, certificates: HashMap<String, Arc<CertifiedKey>>) {
+    *self.certificates.write().unwrap() = certificates;
+  }
+}
+
+impl ResolvesServerCert for CertificateStore {
+  fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+    self.get(client_hello.server_name()?)
+  }
+}
+
+pub fn certified_key(certificate_pem: &str, private_key_pem: &str) -> anyhow::Result<CertifiedKey> {
+  let certificate_chain = rustls_pemfile::certs(&mut BufReader::new(certificate_pem.as_bytes()))
+    .collect::<Result<Vec<_>, _>>()?;
+  if certificate_chain.is_empty() {
+    return Err(anyhow!("No certificate found"));
+  }
+  let private_key = rustls_pemfile::private_key(&mut BufReader::new(private_key_pem.as_bytes()))?
+    .ok_or_else(|| anyhow!("No private key found"))?;
+  let signing_key = any_supported_type(&private_key).context("Unsupported private key")?;
+  Ok(CertifiedKey::new(certificate_chain, signing_key))
+}
+
+pub async fn load_certificates(pool: &Pool<Postgres>) -> anyhow::Result<()> {
+  let records =
+    sqlx::query!("SELECT domain_name, alt_names, certificate, private_key FROM certificate")
+      .fetch_all(pool)
+      .await?;
+  let mut certificates = HashMap::new();
+  for record in records {
+    match certified_key(&record.certificate, &record.private_key) {
+      Ok(certificate) => {
+        let certificate = Arc::new(certificate);
+        for domain_name in std::iter::once(&record.domain_name).chain(&record.alt_names) {
+          certificates.insert(domain_name.to_lowercase(), Arc::clone(&certificate));
+        }
+      }
+      Err(err) => warn!("Skipping certificate of {}: {}", record.domain_name, err),
+    }
+  }
+  info!("Loaded {} certificates", certificates.len());
+  CERTIFICATES.replace(certificates);
+  Ok(())
+}
+
+/// Reloads certificates whenever doseid issues a new one, and periodically as a fallback.
+pub fn start_certificate_reload(pool: Arc<Pool<Postgres>>) {
+  tokio::spawn(async move {
+    loop {
+      if let Err(err) = listen_certificate_changes(&pool).await {
+        error!("Certificate listener failed: {}", err);
+      }
+      sleep(Duration::from_secs(1)).await;
+    }
+  });
+}
+
+async fn listen_certificate_changes(pool: &Pool<Postgres>) -> anyhow::Result<()> {
+  let mut listener = PgListener::connect_with(pool).await?;
+  listener.listen(CERTIFICATE_CHANNEL).await?;
+  loop {
+    load_certificates(pool).await?;
+    // Either a change, a reconnect that may have missed some, or the reload interval.
+    let _ = tokio::time::timeout(RELOAD_INTERVAL, listener.try_recv()).await;
+  }
+}
+
+/// Serves the router over TLS, with certificates resolved from the store by SNI.
+pub async fn serve_tls(listener: TcpListener, app: Router) -> anyhow::Result<()> {
+  let mut server_config = ServerConfig::builder()
+    .with_no_client_auth()
+    .with_cert_resolver(Arc::clone(&CERTIFICATES) as Arc<dyn ResolvesServerCert>);
+  server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+  let acceptor = TlsAcceptor::from(Arc::new(server_config));
+  loop {
+    let (socket, peer_address) = match listener.accept().await {
+      Ok(connection) => connection,
+      Err(err) => {
+        error!("Failed to accept connection: {}", err);
+        continue;
+      }
+    };
+    let acceptor = acceptor.clone();
+    let app = app.clone().map_request(move |mut req: Request<Incoming>| {
+      req.extensions_mut().insert(ConnectInfo(peer_address));
+      req
+    });
+    let service = TowerToHyperService::new(app);
+    tokio::spawn(async move {
+      let stream = match acceptor.accept(socket).await {
+        Ok(stream) => stream,
+        Err(err) => {
+          warn!("TLS handshake with {} failed: {}", peer_address, err);
+          return;
+        }
+      };
+      if let Err(err) = auto::Builder::new(TokioExecutor::new())
+        .serve_connection_with_upgrades(TokioIo::new(stream), service)
+        .await
+      {
+        warn!("Connection with {} closed: {}", peer_address, err);
+      }
+    });
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::tls::{certified_key, CertificateStore};
+  use std::collections::HashMap;
+  use std::sync::Arc;
+
+  fn self_signed() -> (String, String) {
+    let certificate = rcgen::generate_simple_self_signed(vec!["example.com".to_string()]).unwrap();
+    (
+      certificate.serialize_pem().unwrap(),
+      certificate.serialize_private_key_pem(),
+    )
+  }
+
+  #[test]
+  fn test_certificate_store_lookup() {
+    let (certificate, private_key) = self_signed();
+    let certificate = Arc::new(certified_key(&certificate, &private_key).unwrap());
+    let mut certificates = HashMap::new();
+    certificates.insert("example.com".to_string(), Arc::clone(&certificate));
+    certificates.insert("*.apps.example.com".to_string(), Arc::clone(&certificate));
+    let store = CertificateStore::default();
+    store.replace(certificates);
+
+    assert!(store.get("Example.com").is_some());
+    assert!(store.get("web.apps.example.com").is_some());
+    assert!(store.get("www.example.com").is_none());
+    assert!(store.get("a.web.apps.example.com").is_none());
+  }
+
+  #[test]
+  fn test_certified_key_rejects_invalid_pem() {
+    let (certificate, private_key) = self_signed();
+    assert!(certified_key("", &private_key).is_err());
+    assert!(certified_key(&certificate, "").is_err());
+  }
+}
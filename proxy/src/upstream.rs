@@ -0,0 +1,162 @@
+use dosei_proxy_core::balancer::LoadBalancing;
+use dosei_proxy_core::rules::DomainRules;
+use uuid::Uuid;
+
+/// What a domain points at, as stored in the doseid `domain` and `deployment` tables.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RouteTarget {
+  pub owner_id: Uuid,
+  pub project_id: Option<Uuid>,
+  pub deployment_id: Option<Uuid>,
+  /// Containers of the deployment, one per replica.
+  pub instances: Vec<Instance>,
+  pub load_balancing: LoadBalancing,
+  /// Whether the upstream speaks HTTP/2 without TLS (h2c).
+  pub http2: bool,
+  pub rules: DomainRules,
+}
+
+/// A container of a deployment, as exposed by doseid.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instance {
+  pub exposed_host: Option<String>,
+  pub exposed_port: u16,
+  /// Whether it passed the last health checks of doseid.
+  pub healthy: bool,
+  /// Whether doseid stopped it for being idle.
+  pub stopped: bool,
+}
+
+impl RouteTarget {
+  /// Whether every instance was stopped for being idle, and has to be woken before forwarding.
+  pub fn is_stopped(&self) -> bool {
+    !self.instances.is_empty() && self.instances.iter().all(|instance| instance.stopped)
+  }
+}
+
+/// Turns a route target into the `host:port` upstreams requests can be forwarded to.
+pub trait UpstreamResolver: Send + Sync {
+  fn resolve(&self, target: &RouteTarget) -> Vec<String>;
+}
+
+/// Forwards to the Kubernetes service of the project, as deployed on the hosted cluster.
+pub struct KubernetesDnsResolver {
+  pub namespace: String,
+}
+
+impl UpstreamResolver for KubernetesDnsResolver {
+  fn resolve(&self, target: &RouteTarget) -> Vec<String> {
+    // The service balances across the pods of the project on its own.
+    target
+      .project_id
+      .map(|project_id| format!("{}.{}.svc.cluster.local", project_id, self.namespace))
+      .into_iter()
+      .collect()
+  }
+}
+
+/// Forwards to the ports the containers of the active deployment are exposed on, as run by
+/// doseid. Stopped and unhealthy containers are left out, unless none is running or healthy.
+pub struct LocalPortResolver;
+
+impl UpstreamResolver for LocalPortResolver {
+  fn resolve(&self, target: &RouteTarget) -> Vec<String> {
+    let stopped = target.is_stopped();
+    let any_healthy = target
+      .instances
+      .iter()
+      .any(|instance| instance.healthy && !instance.stopped);
+    target
+      .instances
+      .iter()
+      .filter(|instance| stopped || (!instance.stopped && (instance.healthy || !any_healthy)))
+      .map(|instance| {
+        let host = match instance.exposed_host.as_deref() {
+          None | Some("0.0.0.0") => "127.0.0.1",
+          Some(host) => host,
+        };
+        format!("{}:{}", host, instance.exposed_port)
+      })
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::upstream::{
+    Instance, KubernetesDnsResolver, LocalPortResolver, RouteTarget, UpstreamResolver,
+  };
+  use dosei_proxy_core::balancer::LoadBalancing;
+  use dosei_proxy_core::rules::DomainRules;
+  use uuid::Uuid;
+
+  fn instance(exposed_host: Option<&str>, exposed_port: u16, healthy: bool) -> Instance {
+    Instance {
+      exposed_host: exposed_host.map(String::from),
+      exposed_port,
+      healthy,
+      stopped: false,
+    }
+  }
+
+  fn target(instances: Vec<Instance>) -> RouteTarget {
+    RouteTarget {
+      owner_id: Uuid::nil(),
+      project_id: Some(Uuid::nil()),
+      deployment_id: Some(Uuid::nil()),
+      instances,
+      load_balancing: LoadBalancing::RoundRobin,
+      http2: false,
+      rules: DomainRules::default(),
+    }
+  }
+
+  #[test]
+  fn test_kubernetes_dns_resolver() {
+    let resolver = KubernetesDnsResolver {
+      namespace: "default".to_string(),
+    };
+    assert_eq!(
+      resolver.resolve(&target(vec![])),
+      vec!["00000000-0000-0000-0000-000000000000.default.svc.cluster.local".to_string()]
+    );
+  }
+
+  #[test]
+  fn test_local_port_resolver() {
+    let resolver = LocalPortResolver;
+    assert_eq!(
+      resolver.resolve(&target(vec![
+        instance(Some("10.0.0.2"), 10001, true),
+        instance(Some("0.0.0.0"), 10002, true),
+        instance(Some("10.0.0.3"), 10003, false),
+      ])),
+      vec!["10.0.0.2:10001".to_string(), "127.0.0.1:10002".to_string()]
+    );
+    // Better to try unhealthy instances than to fail every request.
+    assert_eq!(
+      resolver.resolve(&target(vec![instance(None, 10001, false)])),
+      vec!["127.0.0.1:10001".to_string()]
+    );
+    assert!(resolver.resolve(&target(vec![])).is_empty());
+  }
+
+  #[test]
+  fn test_local_port_resolver_stopped_instances() {
+    let resolver = LocalPortResolver;
+    let stopped = Instance {
+      stopped: true,
+      ..instance(None, 10002, true)
+    };
+    let running = target(vec![instance(None, 10001, true), stopped.clone()]);
+    assert!(!running.is_stopped());
+    assert_eq!(
+      resolver.resolve(&running),
+      vec!["127.0.0.1:10001".to_string()]
+    );
+
+    let idle = target(vec![stopped]);
+    assert!(idle.is_stopped());
+    assert_eq!(resolver.resolve(&idle), vec!["127.0.0.1:10002".to_string()]);
+  }
+}
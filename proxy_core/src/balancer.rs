@@ -0,0 +1,301 @@
+use axum::body::{Body, Bytes, HttpBody};
+use axum::response::Response;
+use hyper::body::{Frame, SizeHint};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Failed requests in a row before an upstream is ejected.
+const EJECTION_THRESHOLD: u32 = 3;
+/// How long an ejected upstream gets no requests, unless every other one is ejected too.
+const EJECTION_DURATION: Duration = Duration::from_secs(30);
+
+pub static BALANCER: Lazy<Balancer> = Lazy::new(Balancer::default);
+
+/// How requests are spread across the instances of a deployment, as set on its project.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum LoadBalancing {
+  #[default]
+  RoundRobin,
+  LeastConnections,
+}
+
+impl LoadBalancing {
+  pub fn from_column(value: Option<&str>) -> LoadBalancing {
+    match value {
+      Some("least_connections") => LoadBalancing::LeastConnections,
+      _ => LoadBalancing::RoundRobin,
+    }
+  }
+}
+
+#[derive(Debug, Default)]
+struct UpstreamState {
+  /// Requests forwarded whose response body is not done yet.
+  active: usize,
+  failures: u32,
+  ejected_until: Option<Instant>,
+}
+
+impl UpstreamState {
+  fn is_ejected(&self, now: Instant) -> bool {
+    self.ejected_until.is_some_and(|until| until > now)
+  }
+
+  fn is_idle(&self) -> bool {
+    self.active == 0 && self.failures == 0 && self.ejected_until.is_none()
+  }
+}
+
+#[derive(Debug, Default)]
+struct State {
+  upstreams: HashMap<String, UpstreamState>,
+  /// Position of the next pick, by the deployment being balanced.
+  next: HashMap<String, usize>,
+}
+
+/// Picks upstreams and ejects the ones failing requests, kept in memory by each proxy instance.
+#[derive(Debug, Default)]
+pub struct Balancer {
+  state: Mutex<State>,
+}
+
+impl Balancer {
+  /// Picks the upstream to forward a request to, among the ones of the deployment `key` refers
+  /// to. Ejected upstreams are skipped, unless every one of them is ejected.
+  pub fn pick(
+    &self,
+    key: &str,
+    upstreams: &[String],
+    load_balancing: LoadBalancing,
+    now: Instant,
+  ) -> Option<Lease<'_>> {
+    let mut state = self.state.lock().unwrap();
+    let available: Vec<&String> = upstreams
+      .iter()
+      .filter(|upstream| {
+        !state
+          .upstreams
+          .get(*upstream)
+          .is_some_and(|upstream| upstream.is_ejected(now))
+      })
+      .collect();
+    let candidates = if available.is_empty() {
+      upstreams.iter().collect()
+    } else {
+      available
+    };
+    if candidates.is_empty() {
+      return None;
+    }
+    let next = state.next.entry(key.to_string()).or_default();
+    let start = *next % candidates.len();
+    *next = next.wrapping_add(1);
+    // Starting from the round-robin position spreads ties between least connected upstreams.
+    let rotated = candidates[start..].iter().chain(&candidates[..start]);
+    let upstream = match load_balancing {
+      LoadBalancing::RoundRobin => candidates[start],
+      LoadBalancing::LeastConnections => rotated
+        .min_by_key(|upstream| {
+          state
+            .upstreams
+            .get(upstream.as_str())
+            .map_or(0, |upstream| upstream.active)
+        })
+        .unwrap(),
+    }
+    .clone();
+    state.upstreams.entry(upstream.clone()).or_default().active += 1;
+    Some(Lease {
+      balancer: self,
+      upstream,
+    })
+  }
+
+  /// Records the outcome of a request, ejecting the upstream once it failed too many in a row.
+  pub fn report(&self, upstream: &str, success: bool, now: Instant) {
+    let mut state = self.state.lock().unwrap();
+    let upstream_state = state.upstreams.entry(upstream.to_string()).or_default();
+    if success {
+      upstream_state.failures = 0;
+      upstream_state.ejected_until = None;
+    } else {
+      upstream_state.failures += 1;
+      if upstream_state.failures >= EJECTION_THRESHOLD {
+        warn!(
+          "Ejecting upstream {} for {}s after {} failed requests",
+          upstream,
+          EJECTION_DURATION.as_secs(),
+          upstream_state.failures
+        );
+        upstream_state.failures = 0;
+        upstream_state.ejected_until = Some(now + EJECTION_DURATION);
+      }
+    }
+    if upstream_state.is_idle() {
+      state.upstreams.remove(upstream);
+    }
+  }
+
+  fn release(&self, upstream: &str) {
+    let mut state = self.state.lock().unwrap();
+    if let Some(upstream_state) = state.upstreams.get_mut(upstream) {
+      upstream_state.active = upstream_state.active.saturating_sub(1);
+      if upstream_state.is_idle() {
+        state.upstreams.remove(upstream);
+      }
+    }
+  }
+}
+
+/// A request counted as active on its upstream until dropped.
+#[derive(Debug)]
+pub struct Lease<'a> {
+  balancer: &'a Balancer,
+  upstream: String,
+}
+
+impl Lease<'_> {
+  pub fn upstream(&self) -> &str {
+    &self.upstream
+  }
+}
+
+impl Lease<'static> {
+  /// Keeps the request active until the body of its response is done or dropped.
+  pub fn attach(self, response: Response) -> Response {
+    response.map(|body| Body::new(LeasedBody { body, _lease: self }))
+  }
+}
+
+impl Drop for Lease<'_> {
+  fn drop(&mut self) {
+    self.balancer.release(&self.upstream);
+  }
+}
+
+struct LeasedBody {
+  body: Body,
+  _lease: Lease<'static>,
+}
+
+impl HttpBody for LeasedBody {
+  type Data = Bytes;
+  type Error = axum::Error;
+
+  fn poll_frame(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+  ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+    Pin::new(&mut self.get_mut().body).poll_frame(cx)
+  }
+
+  fn is_end_stream(&self) -> bool {
+    self.body.is_end_stream()
+  }
+
+  fn size_hint(&self) -> SizeHint {
+    self.body.size_hint()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::balancer::{Balancer, LoadBalancing, EJECTION_DURATION, EJECTION_THRESHOLD};
+  use std::time::Instant;
+
+  fn upstreams() -> Vec<String> {
+    vec!["10.0.0.1:80".to_string(), "10.0.0.2:80".to_string()]
+  }
+
+  fn pick(balancer: &Balancer, load_balancing: LoadBalancing, now: Instant) -> String {
+    balancer
+      .pick("deployment", &upstreams(), load_balancing, now)
+      .unwrap()
+      .upstream()
+      .to_string()
+  }
+
+  #[test]
+  fn test_round_robin() {
+    let balancer = Balancer::default();
+    let now = Instant::now();
+    assert_eq!(
+      pick(&balancer, LoadBalancing::RoundRobin, now),
+      "10.0.0.1:80"
+    );
+    assert_eq!(
+      pick(&balancer, LoadBalancing::RoundRobin, now),
+      "10.0.0.2:80"
+    );
+    assert_eq!(
+      pick(&balancer, LoadBalancing::RoundRobin, now),
+      "10.0.0.1:80"
+    );
+    assert!(balancer
+      .pick("deployment", &[], LoadBalancing::RoundRobin, now)
+      .is_none());
+  }
+
+  #[test]
+  fn test_least_connections() {
+    let balancer = Balancer::default();
+    let now = Instant::now();
+    let first = balancer
+      .pick(
+        "deployment",
+        &upstreams(),
+        LoadBalancing::LeastConnections,
+        now,
+      )
+      .unwrap();
+    assert_eq!(first.upstream(), "10.0.0.1:80");
+    // The first upstream is still busy, whatever the round-robin position.
+    for _ in 0..3 {
+      assert_eq!(
+        pick(&balancer, LoadBalancing::LeastConnections, now),
+        "10.0.0.2:80"
+      );
+    }
+    drop(first);
+    assert!(balancer.state.lock().unwrap().upstreams.is_empty());
+  }
+
+  #[test]
+  fn test_passive_ejection() {
+    let balancer = Balancer::default();
+    let now = Instant::now();
+    for _ in 0..EJECTION_THRESHOLD {
+      balancer.report("10.0.0.1:80", false, now);
+    }
+    for _ in 0..3 {
+      assert_eq!(
+        pick(&balancer, LoadBalancing::RoundRobin, now),
+        "10.0.0.2:80"
+      );
+    }
+
+    // With every upstream ejected, requests still go somewhere.
+    for _ in 0..EJECTION_THRESHOLD {
+      balancer.report("10.0.0.2:80", false, now);
+    }
+    assert!(balancer
+      .pick("deployment", &upstreams(), LoadBalancing::RoundRobin, now)
+      .is_some());
+
+    let later = now + EJECTION_DURATION;
+    balancer.report("10.0.0.2:80", true, later);
+    assert_eq!(
+      pick(&balancer, LoadBalancing::RoundRobin, later),
+      "10.0.0.1:80"
+    );
+    assert_eq!(
+      pick(&balancer, LoadBalancing::RoundRobin, later),
+      "10.0.0.2:80"
+    );
+  }
+}
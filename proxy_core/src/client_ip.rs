@@ -0,0 +1,132 @@
+use http::{HeaderMap, HeaderValue};
+use ipnet::IpNet;
+use std::net::IpAddr;
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// Parses a trusted proxy, given as a CIDR block or a single address.
+pub fn parse_trusted_proxy(value: &str) -> Option<IpNet> {
+  let value = value.trim();
+  value
+    .parse::<IpNet>()
+    .ok()
+    .or_else(|| value.parse::<IpAddr>().ok().map(IpNet::from))
+}
+
+/// Resolves the client a request comes from.
+///
+/// `X-Forwarded-For` is only believed as far as it was appended to by trusted proxies: it is
+/// read from the right, for as long as the address it was received from is trusted, since
+/// anything further left may have been made up by the client.
+pub fn client_ip(peer: IpAddr, headers: &HeaderMap, trusted_proxies: &[IpNet]) -> IpAddr {
+  let mut client = canonical(peer);
+  for forwarded in forwarded_for(headers).iter().rev() {
+    if !is_trusted(client, trusted_proxies) {
+      break;
+    }
+    match forwarded.parse::<IpAddr>() {
+      Ok(ip) => client = canonical(ip),
+      Err(_) => break,
+    }
+  }
+  client
+}
+
+/// Sets the `X-Forwarded-For` sent upstream: the one of a trusted proxy with its address
+/// appended, or else only the address of the peer.
+pub fn set_forwarded_for(headers: &mut HeaderMap, peer: IpAddr, trusted_proxies: &[IpNet]) {
+  let peer = canonical(peer);
+  let mut forwarded = if is_trusted(peer, trusted_proxies) {
+    forwarded_for(headers)
+  } else {
+    Vec::new()
+  };
+  forwarded.push(peer.to_string());
+  headers.remove(X_FORWARDED_FOR);
+  if let Ok(value) = HeaderValue::from_str(&forwarded.join(", ")) {
+    headers.insert(X_FORWARDED_FOR, value);
+  }
+}
+
+/// IPv4 clients of dual-stack listeners show up as IPv4-mapped IPv6 addresses.
+pub fn canonical(ip: IpAddr) -> IpAddr {
+  match ip {
+    IpAddr::V6(ip) => ip
+      .to_ipv4_mapped()
+      .map(IpAddr::V4)
+      .unwrap_or(IpAddr::V6(ip)),
+    ip => ip,
+  }
+}
+
+fn is_trusted(ip: IpAddr, trusted_proxies: &[IpNet]) -> bool {
+  trusted_proxies.iter().any(|network| network.contains(&ip))
+}
+
+/// Every address of every `X-Forwarded-For` header, from the client to the last proxy.
+fn forwarded_for(headers: &HeaderMap) -> Vec<String> {
+  headers
+    .get_all(X_FORWARDED_FOR)
+    .iter()
+    .filter_map(|value| value.to_str().ok())
+    .flat_map(|value| value.split(','))
+    .map(|address| address.trim().to_string())
+    .filter(|address| !address.is_empty())
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::client_ip::{client_ip, parse_trusted_proxy, set_forwarded_for};
+  use http::HeaderMap;
+  use ipnet::IpNet;
+
+  fn headers(forwarded_for: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert("x-forwarded-for", forwarded_for.parse().unwrap());
+    headers
+  }
+
+  #[test]
+  fn test_client_ip() {
+    let trusted: Vec<IpNet> = vec![
+      parse_trusted_proxy("10.0.0.0/8").unwrap(),
+      parse_trusted_proxy("192.168.1.1").unwrap(),
+    ];
+    let spoofed = headers("1.1.1.1, 203.0.113.7, 10.0.0.2");
+
+    // Untrusted peers can't claim to forward for anyone.
+    assert_eq!(
+      client_ip("198.51.100.1".parse().unwrap(), &spoofed, &trusted),
+      "198.51.100.1".parse::<std::net::IpAddr>().unwrap()
+    );
+    // Trusted hops are skipped up to the first address a trusted proxy did not add.
+    assert_eq!(
+      client_ip("::ffff:192.168.1.1".parse().unwrap(), &spoofed, &trusted),
+      "203.0.113.7".parse::<std::net::IpAddr>().unwrap()
+    );
+    assert_eq!(
+      client_ip("10.0.0.1".parse().unwrap(), &headers("garbage"), &trusted),
+      "10.0.0.1".parse::<std::net::IpAddr>().unwrap()
+    );
+    assert_eq!(
+      client_ip("10.0.0.1".parse().unwrap(), &spoofed, &[]),
+      "10.0.0.1".parse::<std::net::IpAddr>().unwrap()
+    );
+  }
+
+  #[test]
+  fn test_set_forwarded_for() {
+    let trusted = vec![parse_trusted_proxy("10.0.0.0/8").unwrap()];
+
+    let mut forwarded = headers("203.0.113.7");
+    set_forwarded_for(&mut forwarded, "10.0.0.2".parse().unwrap(), &trusted);
+    assert_eq!(forwarded["x-forwarded-for"], "203.0.113.7, 10.0.0.2");
+
+    let mut spoofed = headers("203.0.113.7");
+    set_forwarded_for(&mut spoofed, "198.51.100.1".parse().unwrap(), &trusted);
+    assert_eq!(spoofed["x-forwarded-for"], "198.51.100.1");
+
+    assert!(parse_trusted_proxy("not a block").is_none());
+  }
+}
@@ -0,0 +1,213 @@
+use anyhow::anyhow;
+use once_cell::sync::Lazy;
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::{sleep, timeout};
+use tracing::{error, info};
+use uuid::Uuid;
+
+const PROBE_INTERVAL: Duration = Duration::from_millis(100);
+const PROBE_TIMEOUT: Duration = Duration::from_millis(1000);
+/// How often a deployment receiving requests is reported as active.
+const ACTIVITY_INTERVAL: Duration = Duration::from_secs(60);
+
+static REPORTED_ACTIVITY: Lazy<Mutex<HashMap<Uuid, Instant>>> =
+  Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Deployments being woken, so concurrent requests wait for a single wake.
+static WAKING: Lazy<Mutex<HashMap<Uuid, Arc<tokio::sync::Mutex<()>>>>> =
+  Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Reports a request to the deployment, at most once per interval, so doseid does not stop its
+/// containers for being idle.
+pub fn record_activity(pool: Arc<Pool<Postgres>>, deployment_id: Uuid) {
+  {
+    let now = Instant::now();
+    let mut reported = REPORTED_ACTIVITY.lock().unwrap();
+    if reported
+      .get(&deployment_id)
+      .is_some_and(|reported_at| now.duration_since(*reported_at) < ACTIVITY_INTERVAL)
+    {
+      return;
+    }
+    reported.retain(|_, reported_at| now.duration_since(*reported_at) < ACTIVITY_INTERVAL);
+    reported.insert(deployment_id, now);
+  }
+  tokio::spawn(async move {
+    if let Err(err) = sqlx::query!(
+      "UPDATE deployment SET last_request_at = now() WHERE id = $1",
+      deployment_id
+    )
+    .execute(&*pool)
+    .await
+    {
+      error!(
+        "Failed to report activity of deployment {}: {}",
+        deployment_id, err
+      );
+    }
+  });
+}
+
+/// Starts a deployment stopped for being idle through `start`, holding the request until one of
+/// its upstreams answers HTTP requests. Concurrent requests to the deployment start it only once.
+pub async fn wake<F, Fut>(
+  deployment_id: Uuid,
+  upstreams: &[String],
+  cold_start_timeout: Duration,
+  start: F,
+) -> anyhow::Result<()>
+where
+  F: FnOnce() -> Fut,
+  Fut: Future<Output = anyhow::Result<()>>,
+{
+  let waking = Waking::new(deployment_id);
+  let _guard = waking.lock.lock().await;
+  let started_at = Instant::now();
+  timeout(cold_start_timeout, async {
+    // A concurrent request may have woken it already.
+    if serves_requests(upstreams).await {
+      return Ok(());
+    }
+    start().await?;
+    while !serves_requests(upstreams).await {
+      sleep(PROBE_INTERVAL).await;
+    }
+    info!(
+      "Deployment {} woke up in {}ms",
+      deployment_id,
+      started_at.elapsed().as_millis()
+    );
+    Ok(())
+  })
+  .await
+  .map_err(|_| {
+    anyhow!(
+      "Timed out waiting for deployment {} to start",
+      deployment_id
+    )
+  })?
+}
+
+/// A request waiting on the wake of a deployment, which forgets the wake once the last request
+/// waiting on it is done, finished or not.
+struct Waking {
+  deployment_id: Uuid,
+  lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl Waking {
+  fn new(deployment_id: Uuid) -> Waking {
+    let lock = Arc::clone(WAKING.lock().unwrap().entry(deployment_id).or_default());
+    Waking {
+      deployment_id,
+      lock,
+    }
+  }
+}
+
+impl Drop for Waking {
+  fn drop(&mut self) {
+    let mut waking = WAKING.lock().unwrap();
+    // Held by the map and this request only, others clone it under the same lock.
+    if waking
+      .get(&self.deployment_id)
+      .is_some_and(|lock| Arc::ptr_eq(lock, &self.lock) && Arc::strong_count(lock) == 2)
+    {
+      waking.remove(&self.deployment_id);
+    }
+  }
+}
+
+/// Whether any upstream answers an HTTP request, whatever its status. Accepting connections is
+/// not enough, Docker accepts them on published ports before the app inside listens.
+async fn serves_requests(upstreams: &[String]) -> bool {
+  for upstream in upstreams {
+    if let Ok(true) = timeout(PROBE_TIMEOUT, probe(upstream)).await {
+      return true;
+    }
+  }
+  false
+}
+
+async fn probe(upstream: &str) -> bool {
+  let Ok(mut stream) = TcpStream::connect(upstream).await else {
+    return false;
+  };
+  let request = format!(
+    "HEAD / HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+    upstream
+  );
+  if stream.write_all(request.as_bytes()).await.is_err() {
+    return false;
+  }
+  let mut response = [0; 5];
+  stream.read_exact(&mut response).await.is_ok() && &response == b"HTTP/"
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::cold_start::{serves_requests, wake, WAKING};
+  use std::time::Duration;
+  use tokio::io::{AsyncReadExt, AsyncWriteExt};
+  use tokio::net::TcpListener;
+  use uuid::Uuid;
+
+  /// Listens like Docker's userland proxy when `respond` is false, accepting connections and
+  /// closing them, or like an app answering every request.
+  async fn upstream(respond: bool) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    tokio::spawn(async move {
+      while let Ok((mut stream, _)) = listener.accept().await {
+        let mut buffer = [0; 1024];
+        let _ = stream.read(&mut buffer).await;
+        if respond {
+          let _ = stream
+            .write_all(b"HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\n\r\n")
+            .await;
+        }
+      }
+    });
+    address
+  }
+
+  #[tokio::test]
+  async fn test_serves_requests() {
+    let closed = {
+      let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+      listener.local_addr().unwrap().to_string()
+    };
+    let accepting = upstream(false).await;
+    let serving = upstream(true).await;
+    assert!(!serves_requests(&[closed.clone()]).await);
+    assert!(!serves_requests(&[accepting.clone()]).await);
+    assert!(serves_requests(&[closed, accepting, serving]).await);
+  }
+
+  #[tokio::test]
+  async fn test_wake_forgets_finished_wakes() {
+    let upstreams = [upstream(true).await];
+    let deployment_id = Uuid::new_v4();
+
+    wake(
+      deployment_id,
+      &upstreams,
+      Duration::from_secs(5),
+      || async { Ok(()) },
+    )
+    .await
+    .unwrap();
+    assert!(wake(deployment_id, &[], Duration::from_secs(5), || async {
+      Err(anyhow::anyhow!("failed to start"))
+    })
+    .await
+    .is_err());
+    assert!(!WAKING.lock().unwrap().contains_key(&deployment_id));
+  }
+}
@@ -0,0 +1,20 @@
+/// Lowercases the host and strips its port, so `Example.com:80` matches `example.com`.
+pub fn normalize_host(host: &str) -> String {
+  let host = match host.rsplit_once(':') {
+    Some((name, port)) if port.chars().all(|c| c.is_ascii_digit()) => name,
+    _ => host,
+  };
+  host.to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::host::normalize_host;
+
+  #[test]
+  fn test_normalize_host() {
+    assert_eq!(normalize_host("Example.com"), "example.com");
+    assert_eq!(normalize_host("example.com:8080"), "example.com");
+    assert_eq!(normalize_host("127.0.0.1:80"), "127.0.0.1");
+  }
+}
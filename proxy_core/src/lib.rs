@@ -0,0 +1,8 @@
+//! Request handling shared by the proxy built into doseid and the standalone Dosei Proxy, so
+//! both treat the requests of a domain the same way.
+
+pub mod balancer;
+pub mod client_ip;
+pub mod cold_start;
+pub mod host;
+pub mod rules;
@@ -0,0 +1,248 @@
+use crate::client_ip::canonical;
+use http::{header, HeaderValue, Response, StatusCode};
+use ipnet::IpNet;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+use tracing::warn;
+
+/// How often buckets back to full capacity are dropped, as they are equivalent to no bucket.
+const EVICTION_INTERVAL: Duration = Duration::from_secs(60);
+
+pub static RATE_LIMITER: Lazy<RateLimiter> = Lazy::new(RateLimiter::default);
+
+/// Access rules of a domain, as stored next to it by doseid.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DomainRules {
+  pub rate_limit: Option<RateLimit>,
+  pub allowed_ips: Vec<IpNet>,
+  pub denied_ips: Vec<IpNet>,
+}
+
+/// Token bucket per client IP, refilled at `requests_per_second` up to `burst` requests.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+  pub requests_per_second: f64,
+  pub burst: f64,
+}
+
+impl DomainRules {
+  /// Builds the rules from the `domain` columns, skipping blocks doseid would not have stored.
+  pub fn from_columns(
+    rate_limit_per_second: Option<i32>,
+    rate_limit_burst: Option<i32>,
+    allowed_ips: Vec<String>,
+    denied_ips: Vec<String>,
+  ) -> DomainRules {
+    let parse = |ips: Vec<String>| {
+      ips
+        .into_iter()
+        .filter_map(|ip| match ip.parse::<IpNet>() {
+          Ok(network) => Some(network),
+          Err(_) => {
+            warn!("Ignoring invalid CIDR block {}", ip);
+            None
+          }
+        })
+        .collect()
+    };
+    DomainRules {
+      rate_limit: rate_limit_per_second
+        .zip(rate_limit_burst)
+        .filter(|(requests_per_second, burst)| *requests_per_second > 0 && *burst > 0)
+        .map(|(requests_per_second, burst)| RateLimit {
+          requests_per_second: f64::from(requests_per_second),
+          burst: f64::from(burst),
+        }),
+      allowed_ips: parse(allowed_ips),
+      denied_ips: parse(denied_ips),
+    }
+  }
+
+  /// Denied blocks take precedence, and an empty allow list allows every client.
+  pub fn allows(&self, ip: IpAddr) -> bool {
+    let ip = canonical(ip);
+    !self.denied_ips.iter().any(|network| network.contains(&ip))
+      && (self.allowed_ips.is_empty()
+        || self.allowed_ips.iter().any(|network| network.contains(&ip)))
+  }
+
+  /// Why the request is rejected, if the client is not allowed or over its rate limit.
+  pub fn check(&self, domain: &str, ip: IpAddr) -> Option<Rejection> {
+    if !self.allows(ip) {
+      return Some(Rejection::Forbidden);
+    }
+    let rate_limit = self.rate_limit?;
+    match RATE_LIMITER.acquire(domain, ip, rate_limit, Instant::now()) {
+      Ok(()) => None,
+      Err(retry_after) => Some(Rejection::RateLimited { retry_after }),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rejection {
+  Forbidden,
+  RateLimited { retry_after: Duration },
+}
+
+impl Rejection {
+  pub fn status(&self) -> StatusCode {
+    match self {
+      Rejection::Forbidden => StatusCode::FORBIDDEN,
+      Rejection::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+    }
+  }
+
+  /// Adds the headers telling the client when to come back, to the response rejecting it.
+  pub fn apply<B>(&self, response: &mut Response<B>) {
+    if let Rejection::RateLimited { retry_after } = self {
+      let seconds = (retry_after.as_secs_f64().ceil() as u64).max(1);
+      response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+    }
+  }
+}
+
+#[derive(Debug)]
+struct Bucket {
+  tokens: f64,
+  refilled_at: Instant,
+  rate_limit: RateLimit,
+}
+
+impl Bucket {
+  fn refill(&mut self, now: Instant) {
+    let elapsed = now
+      .saturating_duration_since(self.refilled_at)
+      .as_secs_f64();
+    self.tokens =
+      (self.tokens + elapsed * self.rate_limit.requests_per_second).min(self.rate_limit.burst);
+    self.refilled_at = now;
+  }
+}
+
+/// Token buckets by domain and client IP, kept in memory by each proxy instance.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+  buckets: Mutex<HashMap<(String, IpAddr), Bucket>>,
+}
+
+impl RateLimiter {
+  /// Takes a token from the bucket of the client, or tells how long until one is available.
+  pub fn acquire(
+    &self,
+    domain: &str,
+    ip: IpAddr,
+    rate_limit: RateLimit,
+    now: Instant,
+  ) -> Result<(), Duration> {
+    let mut buckets = self.buckets.lock().unwrap();
+    let bucket = buckets
+      .entry((domain.to_string(), ip))
+      .or_insert_with(|| Bucket {
+        tokens: rate_limit.burst,
+        refilled_at: now,
+        rate_limit,
+      });
+    // The limit may have changed since the bucket was created.
+    bucket.rate_limit = rate_limit;
+    bucket.refill(now);
+    if bucket.tokens >= 1.0 {
+      bucket.tokens -= 1.0;
+      Ok(())
+    } else {
+      Err(Duration::from_secs_f64(
+        (1.0 - bucket.tokens) / rate_limit.requests_per_second,
+      ))
+    }
+  }
+
+  fn evict_full(&self, now: Instant) {
+    self.buckets.lock().unwrap().retain(|_, bucket| {
+      bucket.refill(now);
+      bucket.tokens < bucket.rate_limit.burst
+    });
+  }
+}
+
+pub fn start_rate_limiter_eviction() {
+  tokio::spawn(async move {
+    loop {
+      sleep(EVICTION_INTERVAL).await;
+      RATE_LIMITER.evict_full(Instant::now());
+    }
+  });
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::rules::{DomainRules, RateLimit, RateLimiter, Rejection};
+  use std::net::IpAddr;
+  use std::time::{Duration, Instant};
+
+  #[test]
+  fn test_domain_rules_allows() {
+    let rules = DomainRules::from_columns(
+      None,
+      None,
+      vec!["10.0.0.0/8".to_string(), "not a block".to_string()],
+      vec!["10.0.0.13/32".to_string()],
+    );
+    assert_eq!(rules.allowed_ips.len(), 1);
+    assert!(rules.allows("10.1.2.3".parse().unwrap()));
+    assert!(rules.allows("::ffff:10.1.2.3".parse().unwrap()));
+    assert!(!rules.allows("10.0.0.13".parse().unwrap()));
+    assert!(!rules.allows("8.8.8.8".parse().unwrap()));
+    assert!(DomainRules::default().allows("8.8.8.8".parse().unwrap()));
+  }
+
+  #[test]
+  fn test_rate_limiter() {
+    let rate_limiter = RateLimiter::default();
+    let rate_limit = RateLimit {
+      requests_per_second: 2.0,
+      burst: 3.0,
+    };
+    let ip: IpAddr = "203.0.113.1".parse().unwrap();
+    let now = Instant::now();
+    for _ in 0..3 {
+      assert!(rate_limiter
+        .acquire("example.com", ip, rate_limit, now)
+        .is_ok());
+    }
+    assert_eq!(
+      rate_limiter.acquire("example.com", ip, rate_limit, now),
+      Err(Duration::from_millis(500))
+    );
+    // Buckets are per domain and client.
+    assert!(rate_limiter
+      .acquire("other.example.com", ip, rate_limit, now)
+      .is_ok());
+
+    let later = now + Duration::from_millis(500);
+    assert!(rate_limiter
+      .acquire("example.com", ip, rate_limit, later)
+      .is_ok());
+    assert!(rate_limiter
+      .acquire("example.com", ip, rate_limit, later)
+      .is_err());
+
+    rate_limiter.evict_full(now + Duration::from_secs(10));
+    assert!(rate_limiter.buckets.lock().unwrap().is_empty());
+  }
+
+  #[test]
+  fn test_rejection_apply() {
+    let mut response = http::Response::new(());
+    Rejection::RateLimited {
+      retry_after: Duration::from_millis(200),
+    }
+    .apply(&mut response);
+    assert_eq!(response.headers()["retry-after"], "1");
+  }
+}
@@ -0,0 +1,166 @@
+use crate::package_manager::PackageManager;
+use crate::python::_resolve_python_project;
+use crate::{Framework, _find_framework_init};
+use std::path::Path;
+
+/// Port the generated image listens on, unless `PORT` is set when running it.
+pub const DEFAULT_PORT: u16 = 8080;
+
+pub fn _generate_dockerfile(folder_path: &Path) -> Result<String, String> {
+  let project = _resolve_python_project(folder_path)?;
+  let python_version = project.python_version;
+  let command = entrypoint(folder_path)?;
+
+  let install = match project.package_manager {
+    PackageManager::Poetry => {
+      "\
+COPY pyproject.toml poetry.lock* poetry.toml* ./
+RUN pip install --no-cache-dir poetry \\
+  && poetry config virtualenvs.create false \\
+  && poetry install --no-interaction --no-ansi --no-root --only main"
+    }
+    PackageManager::Uv => {
+      "\
+COPY pyproject.toml uv.lock ./
+ENV UV_PROJECT_ENVIRONMENT=/usr/local
+RUN pip install --no-cache-dir uv \\
+  && uv sync --frozen --no-dev --no-install-project"
+    }
+    PackageManager::Pdm => {
+      "\
+COPY pyproject.toml pdm.lock ./
+RUN pip install --no-cache-dir pdm \\
+  && pdm export --prod --without-hashes -o /tmp/requirements.txt \\
+  && pip install --no-cache-dir -r /tmp/requirements.txt"
+    }
+    PackageManager::Pipenv => {
+      "\
+COPY Pipfile Pipfile.lock ./
+RUN pip install --no-cache-dir pipenv \\
+  && pipenv install --system --deploy"
+    }
+    PackageManager::Pip => {
+      "\
+COPY requirements.txt ./
+RUN pip install --no-cache-dir -r requirements.txt"
+    }
+    package_manager => {
+      return Err(format!(
+        "Generating a `Dockerfile` for {} projects is not supported",
+        package_manager
+      ))
+    }
+  };
+
+  Ok(format!(
+    "\
+FROM python:{python_version}-slim
+
+ENV PYTHONDONTWRITEBYTECODE=1 \\
+  PYTHONUNBUFFERED=1 \\
+  PORT={DEFAULT_PORT}
+
+WORKDIR /app
+
+{install}
+
+COPY . .
+
+EXPOSE {DEFAULT_PORT}
+
+CMD {command}
+"
+  ))
+}
+
+/// Infers how to start the app from the framework it is initialized with.
+fn entrypoint(folder_path: &Path) -> Result<String, String> {
+  if _find_framework_init(&Framework::Dosei, folder_path).is_ok() {
+    return Ok(r#"["python", "-c", "from dosei_sdk import main; main.run()"]"#.to_string());
+  }
+  if let Ok(app_instance) = _find_framework_init(&Framework::FastAPI, folder_path) {
+    return Ok(format!(
+      r#"["/bin/sh", "-c", "uvicorn {} --host 0.0.0.0 --port ${{PORT}}"]"#,
+      app_instance
+    ));
+  }
+  Err("No framework initialization found, add a `Dockerfile` instead".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::python::DEFAULT_PYTHON_VERSION;
+  use std::fs;
+  use tempfile::tempdir;
+
+  #[test]
+  fn test_generate_dockerfile_poetry_dosei() {
+    let temp_dir = tempdir().unwrap();
+    fs::write(
+      temp_dir.path().join("pyproject.toml"),
+      "[tool.poetry.dependencies]\npython = \"^3.12\"\n",
+    )
+    .unwrap();
+    fs::write(temp_dir.path().join("poetry.lock"), "").unwrap();
+    fs::write(
+      temp_dir.path().join("main.py"),
+      "from dosei_sdk import Dosei\ndosei = Dosei()",
+    )
+    .unwrap();
+
+    let dockerfile = _generate_dockerfile(temp_dir.path()).unwrap();
+    assert!(dockerfile.starts_with("FROM python:3.14-slim\n"));
+    assert!(dockerfile.contains("poetry install"));
+    assert!(dockerfile.contains("main.run()"));
+  }
+
+  #[test]
+  fn test_generate_dockerfile_uv() {
+    let temp_dir = tempdir().unwrap();
+    fs::write(
+      temp_dir.path().join("pyproject.toml"),
+      "[project]\nrequires-python = \">=3.10,<3.13\"\n",
+    )
+    .unwrap();
+    fs::write(temp_dir.path().join("uv.lock"), "").unwrap();
+    fs::write(
+      temp_dir.path().join("main.py"),
+      "from fastapi import FastAPI\napp = FastAPI()",
+    )
+    .unwrap();
+
+    let dockerfile = _generate_dockerfile(temp_dir.path()).unwrap();
+    assert!(dockerfile.starts_with("FROM python:3.12-slim\n"));
+    assert!(dockerfile.contains("uv sync --frozen"));
+  }
+
+  #[test]
+  fn test_generate_dockerfile_pip_fastapi() {
+    let temp_dir = tempdir().unwrap();
+    fs::write(
+      temp_dir.path().join("requirements.txt"),
+      "fastapi\nuvicorn\n",
+    )
+    .unwrap();
+    fs::create_dir(temp_dir.path().join("api")).unwrap();
+    fs::write(
+      temp_dir.path().join("api/server.py"),
+      "from fastapi import FastAPI\napp = FastAPI()",
+    )
+    .unwrap();
+
+    let dockerfile = _generate_dockerfile(temp_dir.path()).unwrap();
+    assert!(dockerfile.starts_with(&format!("FROM python:{}-slim\n", DEFAULT_PYTHON_VERSION)));
+    assert!(dockerfile.contains("pip install --no-cache-dir -r requirements.txt"));
+    assert!(dockerfile.contains("uvicorn api.server:app"));
+  }
+
+  #[test]
+  fn test_generate_dockerfile_without_framework() {
+    let temp_dir = tempdir().unwrap();
+    fs::write(temp_dir.path().join("requirements.txt"), "requests\n").unwrap();
+    fs::write(temp_dir.path().join("main.py"), "print('Hello')").unwrap();
+    assert!(_generate_dockerfile(temp_dir.path()).is_err());
+  }
+}
@@ -0,0 +1,216 @@
+use rustpython_parser::ast::{Expr, Mod, Stmt};
+use rustpython_parser::{parse, Mode};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+
+#[derive(Debug, PartialEq)]
+pub struct FrameworkInit {
+  /// Path of the file, relative to the project folder.
+  pub file: PathBuf,
+  /// Line the app is initialized on, starting at 1.
+  pub line: usize,
+  /// Module path of Python files, or the path of Node.js files.
+  pub module: String,
+  /// Variable the app is assigned to, empty for apps initialized by their config.
+  pub variable: String,
+}
+
+impl fmt::Display for FrameworkInit {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if self.variable.is_empty() {
+      write!(f, "{}", self.module)
+    } else {
+      write!(f, "{}:{}", self.module, self.variable)
+    }
+  }
+}
+
+/// Finds the module level variables assigned a call to one of `callables`, given as fully
+/// qualified names such as `fastapi.FastAPI`. Imports and their aliases are resolved, so
+/// `from fastapi import FastAPI as API` followed by `app = API()` is found as well.
+///
+/// Returns the variable and line of each, or `None` if the source is not valid Python.
+pub fn python_inits(source: &str, callables: &[&str]) -> Option<Vec<(String, usize)>> {
+  let Mod::Module(module) = parse(source, Mode::Module, "<module>").ok()? else {
+    return None;
+  };
+  let mut visitor = Visitor {
+    source,
+    callables,
+    imports: HashMap::new(),
+    inits: Vec::new(),
+  };
+  visitor.visit(&module.body);
+  Some(visitor.inits)
+}
+
+struct Visitor<'a> {
+  source: &'a str,
+  callables: &'a [&'a str],
+  /// Local names bound by imports, to the qualified name they refer to.
+  imports: HashMap<String, String>,
+  inits: Vec<(String, usize)>,
+}
+
+impl Visitor<'_> {
+  fn visit(&mut self, body: &[Stmt]) {
+    for stmt in body {
+      match stmt {
+        Stmt::Import(import) => {
+          for alias in &import.names {
+            let name = alias.name.as_str();
+            match &alias.asname {
+              Some(asname) => self.imports.insert(asname.to_string(), name.to_string()),
+              // `import a.b` binds `a`.
+              None => {
+                let package = name.split('.').next().unwrap_or(name);
+                self
+                  .imports
+                  .insert(package.to_string(), package.to_string())
+              }
+            };
+          }
+        }
+        Stmt::ImportFrom(import) => {
+          // Relative imports are of the project itself, never of a framework.
+          if import.level.map_or(0, |level| level.to_u32()) > 0 {
+            continue;
+          }
+          let Some(module) = &import.module else {
+            continue;
+          };
+          for alias in &import.names {
+            let local = alias.asname.as_ref().unwrap_or(&alias.name);
+            self
+              .imports
+              .insert(local.to_string(), format!("{}.{}", module, alias.name));
+          }
+        }
+        Stmt::Assign(assign) => {
+          if self.is_init(&assign.value) {
+            for target in &assign.targets {
+              if let Expr::Name(name) = target {
+                let line = self.line(stmt);
+                self.inits.push((name.id.to_string(), line));
+              }
+            }
+          }
+        }
+        Stmt::AnnAssign(assign) => {
+          if let (Expr::Name(name), Some(value)) = (assign.target.as_ref(), &assign.value) {
+            if self.is_init(value) {
+              let line = self.line(stmt);
+              self.inits.push((name.id.to_string(), line));
+            }
+          }
+        }
+        // Still module level, as in `if DEBUG:` or `try: ... except ImportError:`.
+        Stmt::If(stmt) => {
+          self.visit(&stmt.body);
+          self.visit(&stmt.orelse);
+        }
+        Stmt::Try(stmt) => {
+          self.visit(&stmt.body);
+          for handler in &stmt.handlers {
+            let rustpython_parser::ast::ExceptHandler::ExceptHandler(handler) = handler;
+            self.visit(&handler.body);
+          }
+          self.visit(&stmt.orelse);
+          self.visit(&stmt.finalbody);
+        }
+        Stmt::With(stmt) => self.visit(&stmt.body),
+        _ => {}
+      }
+    }
+  }
+
+  fn is_init(&self, value: &Expr) -> bool {
+    let Expr::Call(call) = value else {
+      return false;
+    };
+    let Some(name) = self.qualified_name(&call.func) else {
+      return false;
+    };
+    self.callables.iter().any(|callable| {
+      // Frameworks re-export their app from submodules, as `fastapi.applications.FastAPI`.
+      name == *callable
+        || (name.split('.').next() == callable.split('.').next()
+          && name.rsplit('.').next() == callable.rsplit('.').next())
+    })
+  }
+
+  /// Resolves `API` or `fastapi.FastAPI` to the name they were imported as.
+  fn qualified_name(&self, expr: &Expr) -> Option<String> {
+    match expr {
+      Expr::Name(name) => self.imports.get(name.id.as_str()).cloned(),
+      Expr::Attribute(attribute) => self
+        .qualified_name(&attribute.value)
+        .map(|value| format!("{}.{}", value, attribute.attr)),
+      _ => None,
+    }
+  }
+
+  fn line(&self, stmt: &Stmt) -> usize {
+    let offset = usize::from(rustpython_parser::ast::Ranged::start(stmt));
+    self.source[..offset].matches('\n').count() + 1
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const FASTAPI: &[&str] = &["fastapi.FastAPI"];
+
+  #[test]
+  fn test_python_inits_resolves_imports() {
+    let source = "\
+from fastapi import FastAPI
+import fastapi as fa
+
+app = FastAPI()
+api: FastAPI = fa.FastAPI(title='API')
+";
+    assert_eq!(
+      python_inits(source, FASTAPI),
+      Some(vec![("app".to_string(), 4), ("api".to_string(), 5)])
+    );
+  }
+
+  #[test]
+  fn test_python_inits_resolves_aliases_and_submodules() {
+    let source = "\
+from fastapi.applications import FastAPI as API
+import fastapi.applications
+
+app = API()
+other = fastapi.applications.FastAPI()
+";
+    assert_eq!(
+      python_inits(source, FASTAPI),
+      Some(vec![("app".to_string(), 4), ("other".to_string(), 5)])
+    );
+  }
+
+  #[test]
+  fn test_python_inits_ignores_comments_strings_and_other_imports() {
+    let source = "\
+from starlette.applications import FastAPI
+
+# app = FastAPI()
+doc = \"app = FastAPI()\"
+app = FastAPI()
+
+def create_app():
+  from fastapi import FastAPI
+  return FastAPI()
+";
+    assert_eq!(python_inits(source, FASTAPI), Some(vec![]));
+  }
+
+  #[test]
+  fn test_python_inits_invalid_source() {
+    assert_eq!(python_inits("app = FastAPI(", FASTAPI), None);
+  }
+}
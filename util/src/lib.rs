@@ -1,24 +1,60 @@
+pub mod dockerfile;
+pub mod framework;
 pub mod package_manager;
+pub mod python;
 
-use crate::package_manager::_resolve_package_manager;
+use crate::dockerfile::_generate_dockerfile;
+use crate::framework::FrameworkInit;
+use crate::package_manager::{_resolve_node_version, _resolve_package_manager};
+use crate::python::_resolve_python_project;
 use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
 use pyo3::PyErr;
 use regex::Regex;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use walkdir::WalkDir;
 
 #[pyfunction]
 fn find_framework_init(framework: String, path: String) -> Result<String, PyErr> {
-  let framework: Framework = Framework::from_str(&framework).map_err(|_| {
-    PyErr::new::<PyValueError, _>("Framework not supported, Choose Dosei or FastAPI")
-  })?;
+  let framework = parse_framework(&framework)?;
   let folder_path = Path::new(&path);
   _find_framework_init(&framework, folder_path).map_err(PyErr::new::<PyValueError, _>)
 }
 
+/// Returns the file, line and import string of every initialization of the framework.
+#[pyfunction]
+fn find_framework_inits(
+  framework: String,
+  path: String,
+) -> Result<Vec<(String, usize, String)>, PyErr> {
+  let framework = parse_framework(&framework)?;
+  let folder_path = Path::new(&path);
+  _find_framework_inits(&framework, folder_path)
+    .map(|inits| {
+      inits
+        .into_iter()
+        .map(|init| {
+          (
+            init.file.to_string_lossy().to_string(),
+            init.line,
+            init.to_string(),
+          )
+        })
+        .collect()
+    })
+    .map_err(PyErr::new::<PyValueError, _>)
+}
+
+fn parse_framework(framework: &str) -> Result<Framework, PyErr> {
+  Framework::from_str(framework).map_err(|_| {
+    PyErr::new::<PyValueError, _>(
+      "Framework not supported, Choose Dosei, FastAPI, Flask, Django, DoseiJS, Express or NextJS",
+    )
+  })
+}
+
 #[pyfunction]
 fn resolve_package_manager(path: String) -> Result<String, PyErr> {
   let folder_path = Path::new(&path);
@@ -27,10 +63,34 @@ fn resolve_package_manager(path: String) -> Result<String, PyErr> {
     .map_err(PyErr::new::<PyValueError, _>)
 }
 
+#[pyfunction]
+fn resolve_python_version(path: String) -> Result<String, PyErr> {
+  let folder_path = Path::new(&path);
+  _resolve_python_project(folder_path)
+    .map(|project| project.python_version)
+    .map_err(PyErr::new::<PyValueError, _>)
+}
+
+#[pyfunction]
+fn resolve_node_version(path: String) -> Result<String, PyErr> {
+  let folder_path = Path::new(&path);
+  _resolve_node_version(folder_path).map_err(PyErr::new::<PyValueError, _>)
+}
+
+#[pyfunction]
+fn generate_dockerfile(path: String) -> Result<String, PyErr> {
+  let folder_path = Path::new(&path);
+  _generate_dockerfile(folder_path).map_err(PyErr::new::<PyValueError, _>)
+}
+
 #[pymodule]
 fn dosei_util(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
   m.add_function(wrap_pyfunction!(find_framework_init, m)?)?;
+  m.add_function(wrap_pyfunction!(find_framework_inits, m)?)?;
   m.add_function(wrap_pyfunction!(resolve_package_manager, m)?)?;
+  m.add_function(wrap_pyfunction!(resolve_python_version, m)?)?;
+  m.add_function(wrap_pyfunction!(resolve_node_version, m)?)?;
+  m.add_function(wrap_pyfunction!(generate_dockerfile, m)?)?;
   Ok(())
 }
 
@@ -38,14 +98,36 @@ pub fn _find_framework_init(
   framework: &Framework,
   folder_path: &Path,
 ) -> Result<String, &'static str> {
-  let pattern = Regex::new(&format!(r"(\w+)\s*=\s*{}\(", framework.class_name())).unwrap();
+  _find_framework_inits(framework, folder_path)?
+    .first()
+    .map(|init| init.to_string())
+    .ok_or("No framework initialization found.")
+}
 
+/// Finds every initialization of the framework in the project, in the order of their files.
+pub fn _find_framework_inits(
+  framework: &Framework,
+  folder_path: &Path,
+) -> Result<Vec<FrameworkInit>, &'static str> {
   let folder_path = match fs::canonicalize(folder_path) {
     Ok(path) => path,
     Err(_) => return Err("Invalid folder path"),
   };
 
-  for entry in WalkDir::new(&folder_path) {
+  if let Framework::NextJS = framework {
+    return Ok(find_nextjs(&folder_path).into_iter().collect());
+  }
+
+  let mut inits = Vec::new();
+  let walker = WalkDir::new(&folder_path)
+    .sort_by_file_name()
+    .into_iter()
+    .filter_entry(|entry| {
+      // Skip installed dependencies and hidden folders such as `.git` or `.venv`.
+      let name = entry.file_name().to_string_lossy();
+      entry.depth() == 0 || !(name == "node_modules" || name.starts_with('.'))
+    });
+  for entry in walker {
     let entry = match entry {
       Ok(e) => e,
       Err(_) => continue,
@@ -53,37 +135,100 @@ pub fn _find_framework_init(
 
     let path = entry.path();
 
-    if path.is_file() && path.extension().map_or(false, |e| e == "py") {
+    if path.is_file()
+      && path.extension().map_or(false, |e| {
+        framework
+          .extensions()
+          .contains(&e.to_string_lossy().as_ref())
+      })
+    {
       let content = match fs::read_to_string(path) {
         Ok(c) => c,
         Err(_) => continue,
       };
 
-      if let Some(captures) = pattern.captures(&content) {
-        // Calculate the relative module path
-        let relative_path = match path.strip_prefix(&folder_path) {
-          Ok(rp) => rp,
-          Err(_) => continue,
-        }
-        .with_extension("");
+      // Calculate the relative module path
+      let relative_path = match path.strip_prefix(&folder_path) {
+        Ok(rp) => rp,
+        Err(_) => continue,
+      };
+
+      let found = if framework.is_node() {
+        let pattern = Regex::new(framework.init_pattern()).unwrap();
+        pattern
+          .captures_iter(&content)
+          .map(|captures| {
+            let line = content[..captures.get(0).unwrap().start()]
+              .matches('\n')
+              .count()
+              + 1;
+            (captures[1].to_string(), line)
+          })
+          .collect()
+      } else {
+        // Files that don't parse, such as Python 2 scripts, can't be imported as the app anyway.
+        framework::python_inits(&content, framework.callables()).unwrap_or_default()
+      };
 
-        let module_path = relative_path
+      let module_path = if framework.is_node() {
+        relative_path
+          .to_str()
+          .unwrap()
+          .replace(std::path::MAIN_SEPARATOR, "/")
+      } else {
+        relative_path
+          .with_extension("")
           .to_str()
           .unwrap()
-          .replace(std::path::MAIN_SEPARATOR, ".");
+          .replace(std::path::MAIN_SEPARATOR, ".")
+      };
+
+      inits.extend(found.into_iter().map(|(variable, line)| FrameworkInit {
+        file: relative_path.to_path_buf(),
+        line,
+        module: module_path.clone(),
+        variable,
+      }));
+    }
+  }
+  Ok(inits)
+}
 
-        return Ok(format!("{}:{}", module_path, &captures[1]));
-      }
+/// Next.js apps have no instance to find, they are detected by their config or dependency.
+fn find_nextjs(folder_path: &Path) -> Option<FrameworkInit> {
+  let init = |file: &str| FrameworkInit {
+    file: PathBuf::from(file),
+    line: 1,
+    module: file.to_string(),
+    variable: String::new(),
+  };
+  for config in ["next.config.js", "next.config.mjs", "next.config.ts"] {
+    if folder_path.join(config).exists() {
+      return Some(init(config));
     }
   }
-  Err("No framework initialization found.")
+  let package_json = fs::read_to_string(folder_path.join("package.json"))
+    .ok()
+    .and_then(|contents| serde_json::from_str::<serde_json::Value>(&contents).ok())?;
+  let depends_on_next = ["dependencies", "devDependencies"].iter().any(|key| {
+    package_json
+      .get(key)
+      .and_then(|deps| deps.get("next"))
+      .is_some()
+  });
+  depends_on_next.then(|| init("package.json"))
 }
 
-// TODO: Support Django and Flask
 #[derive(Debug)]
 pub enum Framework {
   Dosei,
   FastAPI,
+  Flask,
+  Django,
+  /// The `@dosei/dosei` Node.js SDK.
+  DoseiJS,
+  Express,
+  NextJS,
 }
 
 impl FromStr for Framework {
@@ -93,18 +238,53 @@ impl FromStr for Framework {
     match input {
       "Dosei" => Ok(Framework::Dosei),
       "FastAPI" => Ok(Framework::FastAPI),
+      "Flask" => Ok(Framework::Flask),
+      "Django" => Ok(Framework::Django),
+      "DoseiJS" => Ok(Framework::DoseiJS),
+      "Express" => Ok(Framework::Express),
+      "NextJS" => Ok(Framework::NextJS),
       _ => Err(()), // You can implement more sophisticated error handling
     }
   }
 }
 
 impl Framework {
-  fn class_name(&self) -> &str {
+  /// Qualified names of the callables initializing a Python app.
+  fn callables(&self) -> &[&str] {
+    match self {
+      Framework::Dosei => &["dosei_sdk.Dosei"],
+      Framework::FastAPI => &["fastapi.FastAPI"],
+      Framework::Flask => &["flask.Flask"],
+      Framework::Django => &[
+        "django.core.wsgi.get_wsgi_application",
+        "django.core.asgi.get_asgi_application",
+      ],
+      _ => &[],
+    }
+  }
+
+  fn init_pattern(&self) -> &str {
     match self {
-      Framework::Dosei => "Dosei",
-      Framework::FastAPI => "FastAPI",
+      Framework::DoseiJS => r"(\w+)\s*=\s*new\s+Dosei\(",
+      Framework::Express => r"(\w+)\s*=\s*express\(",
+      _ => unreachable!("Only Node.js apps are found by pattern"),
     }
   }
+
+  fn extensions(&self) -> &[&str] {
+    if self.is_node() {
+      &["js", "mjs", "cjs", "ts", "tsx"]
+    } else {
+      &["py"]
+    }
+  }
+
+  pub fn is_node(&self) -> bool {
+    matches!(
+      self,
+      Framework::DoseiJS | Framework::Express | Framework::NextJS
+    )
+  }
 }
 
 #[cfg(test)]
@@ -156,7 +336,11 @@ mod tests {
     let temp_dir = tempdir().unwrap();
     let nested_dir = temp_dir.path().join("nested");
     fs::create_dir(&nested_dir).unwrap();
-    create_file(&nested_dir, "test.py", "Dosei = Dosei()");
+    create_file(
+      &nested_dir,
+      "test.py",
+      "from dosei_sdk import Dosei\ndosei = Dosei()",
+    );
 
     let result = _find_framework_init(&Framework::Dosei, temp_dir.path());
     assert!(result.is_ok());
@@ -165,16 +349,124 @@ mod tests {
   #[test]
   fn test_dosei_framework_specific() {
     let temp_dir = tempdir().unwrap();
-    create_file(temp_dir.path(), "test.py", "Dosei = Dosei()");
+    create_file(
+      temp_dir.path(),
+      "test.py",
+      "from dosei_sdk import Dosei\ndosei = Dosei()",
+    );
     let result = _find_framework_init(&Framework::Dosei, temp_dir.path());
-    assert!(result.is_ok());
+    assert_eq!(result, Ok("test:dosei".to_string()));
+  }
+
+  #[test]
+  fn test_express_framework_specific() {
+    let temp_dir = tempdir().unwrap();
+    let src_dir = temp_dir.path().join("src");
+    fs::create_dir(&src_dir).unwrap();
+    create_file(&src_dir, "server.js", "const app = express();");
+    let result = _find_framework_init(&Framework::Express, temp_dir.path());
+    assert_eq!(result, Ok("src/server.js:app".to_string()));
+  }
+
+  #[test]
+  fn test_dosei_js_framework_specific() {
+    let temp_dir = tempdir().unwrap();
+    create_file(temp_dir.path(), "dosei.ts", "const dosei = new Dosei({});");
+    let result = _find_framework_init(&Framework::DoseiJS, temp_dir.path());
+    assert_eq!(result, Ok("dosei.ts:dosei".to_string()));
+    assert!(_find_framework_init(&Framework::Dosei, temp_dir.path()).is_err());
+  }
+
+  #[test]
+  fn test_skips_node_modules() {
+    let temp_dir = tempdir().unwrap();
+    let dependency_dir = temp_dir.path().join("node_modules").join("express");
+    fs::create_dir_all(&dependency_dir).unwrap();
+    create_file(&dependency_dir, "index.js", "const app = express();");
+    let result = _find_framework_init(&Framework::Express, temp_dir.path());
+    assert!(result.is_err());
+  }
+
+  #[test]
+  fn test_nextjs_framework_specific() {
+    let temp_dir = tempdir().unwrap();
+    create_file(
+      temp_dir.path(),
+      "package.json",
+      r#"{"dependencies": {"next": "14.1.0"}}"#,
+    );
+    let result = _find_framework_init(&Framework::NextJS, temp_dir.path());
+    assert_eq!(result, Ok("package.json".to_string()));
+
+    create_file(temp_dir.path(), "next.config.mjs", "export default {};");
+    let result = _find_framework_init(&Framework::NextJS, temp_dir.path());
+    assert_eq!(result, Ok("next.config.mjs".to_string()));
   }
 
   #[test]
   fn test_fastapi_framework_specific() {
     let temp_dir = tempdir().unwrap();
-    create_file(temp_dir.path(), "test.py", "FastAPI = FastAPI()");
+    create_file(
+      temp_dir.path(),
+      "test.py",
+      "from fastapi import FastAPI\napp = FastAPI()",
+    );
     let result = _find_framework_init(&Framework::FastAPI, temp_dir.path());
     assert!(result.is_ok());
   }
+
+  #[test]
+  fn test_framework_not_imported() {
+    let temp_dir = tempdir().unwrap();
+    create_file(temp_dir.path(), "test.py", "Dosei = Dosei()");
+    let result = _find_framework_init(&Framework::Dosei, temp_dir.path());
+    assert!(result.is_err());
+  }
+
+  #[test]
+  fn test_flask_framework_specific() {
+    let temp_dir = tempdir().unwrap();
+    create_file(
+      temp_dir.path(),
+      "app.py",
+      "import flask\n\napp: flask.Flask = flask.Flask(__name__)",
+    );
+    let result = _find_framework_init(&Framework::Flask, temp_dir.path());
+    assert_eq!(result, Ok("app:app".to_string()));
+  }
+
+  #[test]
+  fn test_find_every_framework_init() {
+    let temp_dir = tempdir().unwrap();
+    let project_dir = temp_dir.path().join("mysite");
+    fs::create_dir(&project_dir).unwrap();
+    create_file(
+      &project_dir,
+      "asgi.py",
+      "import os\n\nfrom django.core.asgi import get_asgi_application\n\napplication = get_asgi_application()",
+    );
+    create_file(
+      &project_dir,
+      "wsgi.py",
+      "from django.core.wsgi import get_wsgi_application as wsgi\n\napplication = wsgi()",
+    );
+    let result = _find_framework_inits(&Framework::Django, temp_dir.path()).unwrap();
+    assert_eq!(
+      result,
+      vec![
+        FrameworkInit {
+          file: PathBuf::from("mysite/asgi.py"),
+          line: 5,
+          module: "mysite.asgi".to_string(),
+          variable: "application".to_string(),
+        },
+        FrameworkInit {
+          file: PathBuf::from("mysite/wsgi.py"),
+          line: 3,
+          module: "mysite.wsgi".to_string(),
+          variable: "application".to_string(),
+        },
+      ]
+    );
+  }
 }
@@ -19,48 +19,100 @@ pub fn _resolve_package_manager(folder_path: &Path) -> Result<PackageManager, &'
     return Ok(PackageManager::Poetry);
   }
 
+  if folder_path.join("uv.lock").exists() {
+    return Ok(PackageManager::Uv);
+  }
+
+  if folder_path.join("pdm.lock").exists() {
+    return Ok(PackageManager::Pdm);
+  }
+
+  if folder_path.join("Pipfile.lock").exists() {
+    return Ok(PackageManager::Pipenv);
+  }
+
+  if folder_path.join("bun.lockb").exists() {
+    return Ok(PackageManager::Bun);
+  }
+
+  if folder_path.join("pnpm-lock.yaml").exists() {
+    return Ok(PackageManager::Pnpm);
+  }
+
+  if folder_path.join("yarn.lock").exists() {
+    return Ok(PackageManager::Yarn);
+  }
+
+  if folder_path.join("package-lock.json").exists() {
+    return Ok(PackageManager::Npm);
+  }
+
+  // Lockfiles are checked first, Node.js projects may ship a `requirements.txt` for tooling.
   if folder_path.join("requirements.txt").exists() {
     return Ok(PackageManager::Pip);
   }
+
+  if folder_path.join("package.json").exists() {
+    return Ok(PackageManager::Npm);
+  }
   Err("No supported package manager found")
 }
 
-pub fn _resolve_python_version(folder_path: &Path) -> Result<String, String> {
-  match _resolve_package_manager(folder_path) {
-    Ok(PackageManager::Poetry) => {
-      let file_path = folder_path.join("pyproject.toml");
-      let contents =
-        fs::read_to_string(file_path).map_err(|_| "Failed to read pyproject.toml".to_string())?;
-      let data: toml::Value = contents
-        .parse()
-        .map_err(|_| "Failed to parse TOML".to_string())?;
-
-      let version = data
-        .get("tool")
-        .and_then(|tool| tool.get("poetry"))
-        .and_then(|poetry| poetry.get("dependencies"))
-        .and_then(|deps| deps.get("python"))
-        .map(|version| version.to_string())
-        .ok_or_else(|| "Python version not found in pyproject.toml".to_string())?;
-
-      Ok(version)
+pub fn _resolve_node_version(folder_path: &Path) -> Result<String, String> {
+  if let Ok(contents) = fs::read_to_string(folder_path.join(".nvmrc")) {
+    let version = contents.trim().trim_start_matches('v');
+    if !version.is_empty() {
+      return Ok(version.to_string());
     }
-    Ok(PackageManager::Pip) => Err("Python version couldn't be auto resolved".to_string()),
-    _ => Err("Python version couldn't be auto resolved".to_string()),
   }
+
+  let contents = fs::read_to_string(folder_path.join("package.json"))
+    .map_err(|_| "Failed to read package.json".to_string())?;
+  let data: serde_json::Value =
+    serde_json::from_str(&contents).map_err(|_| "Failed to parse package.json".to_string())?;
+  data
+    .get("engines")
+    .and_then(|engines| engines.get("node"))
+    .and_then(|version| version.as_str())
+    .map(|version| version.to_string())
+    .ok_or_else(|| "Node version not found in .nvmrc or package.json".to_string())
 }
 
 #[derive(Debug, PartialEq)]
 pub enum PackageManager {
   Poetry,
+  Uv,
+  Pdm,
+  Pipenv,
   Pip,
+  Npm,
+  Yarn,
+  Pnpm,
+  Bun,
+}
+
+impl PackageManager {
+  /// Whether it manages Node.js dependencies, as opposed to Python ones.
+  pub fn is_node(&self) -> bool {
+    matches!(
+      self,
+      PackageManager::Npm | PackageManager::Yarn | PackageManager::Pnpm | PackageManager::Bun
+    )
+  }
 }
 
 impl fmt::Display for PackageManager {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     match self {
       PackageManager::Poetry => write!(f, "Poetry"),
+      PackageManager::Uv => write!(f, "uv"),
+      PackageManager::Pdm => write!(f, "PDM"),
+      PackageManager::Pipenv => write!(f, "Pipenv"),
       PackageManager::Pip => write!(f, "Pip"),
+      PackageManager::Npm => write!(f, "npm"),
+      PackageManager::Yarn => write!(f, "Yarn"),
+      PackageManager::Pnpm => write!(f, "pnpm"),
+      PackageManager::Bun => write!(f, "Bun"),
     }
   }
 }
@@ -98,6 +150,76 @@ mod tests {
     assert_eq!(result, Ok(PackageManager::Pip));
   }
 
+  #[test]
+  fn test_resolve_package_manager_with_python_lockfiles() {
+    for (lockfile, package_manager) in [
+      ("uv.lock", PackageManager::Uv),
+      ("pdm.lock", PackageManager::Pdm),
+      ("Pipfile.lock", PackageManager::Pipenv),
+    ] {
+      let temp_dir = Builder::new().prefix("example").tempdir().unwrap();
+      File::create(temp_dir.path().join("requirements.txt")).unwrap();
+      File::create(temp_dir.path().join(lockfile)).unwrap();
+
+      let result = _resolve_package_manager(temp_dir.path());
+      assert_eq!(result, Ok(package_manager));
+    }
+  }
+
+  #[test]
+  fn test_resolve_package_manager_with_node_lockfiles() {
+    for (lockfile, package_manager) in [
+      ("package-lock.json", PackageManager::Npm),
+      ("yarn.lock", PackageManager::Yarn),
+      ("pnpm-lock.yaml", PackageManager::Pnpm),
+      ("bun.lockb", PackageManager::Bun),
+    ] {
+      let temp_dir = Builder::new().prefix("example").tempdir().unwrap();
+      File::create(temp_dir.path().join("package.json")).unwrap();
+      File::create(temp_dir.path().join(lockfile)).unwrap();
+
+      let result = _resolve_package_manager(temp_dir.path());
+      assert_eq!(result, Ok(package_manager));
+    }
+  }
+
+  #[test]
+  fn test_resolve_package_manager_prefers_node_lockfiles_to_requirements() {
+    let temp_dir = Builder::new().prefix("example").tempdir().unwrap();
+    File::create(temp_dir.path().join("requirements.txt")).unwrap();
+    File::create(temp_dir.path().join("package.json")).unwrap();
+    assert_eq!(
+      _resolve_package_manager(temp_dir.path()),
+      Ok(PackageManager::Pip)
+    );
+
+    File::create(temp_dir.path().join("yarn.lock")).unwrap();
+    assert_eq!(
+      _resolve_package_manager(temp_dir.path()),
+      Ok(PackageManager::Yarn)
+    );
+  }
+
+  #[test]
+  fn test_resolve_node_version() {
+    let temp_dir = Builder::new().prefix("example").tempdir().unwrap();
+    fs::write(
+      temp_dir.path().join("package.json"),
+      r#"{"engines": {"node": ">=18"}}"#,
+    )
+    .unwrap();
+    assert_eq!(
+      _resolve_node_version(temp_dir.path()),
+      Ok(">=18".to_string())
+    );
+
+    fs::write(temp_dir.path().join(".nvmrc"), "v20.11.0\n").unwrap();
+    assert_eq!(
+      _resolve_node_version(temp_dir.path()),
+      Ok("20.11.0".to_string())
+    );
+  }
+
   #[test]
   fn test_resolve_package_manager_with_invalid_path() {
     let result = _resolve_package_manager(Path::new("/invalid/path"));
@@ -0,0 +1,389 @@
+use crate::package_manager::{PackageManager, _resolve_package_manager};
+use regex::Regex;
+use std::fs;
+use std::path::Path;
+
+/// Python version used when the project does not pin one.
+pub const DEFAULT_PYTHON_VERSION: &str = "3.11";
+
+/// Minor releases a version requirement can resolve to, newest last.
+const SUPPORTED_PYTHON_VERSIONS: [(u64, u64); 7] =
+  [(3, 8), (3, 9), (3, 10), (3, 11), (3, 12), (3, 13), (3, 14)];
+
+#[derive(Debug, PartialEq)]
+pub struct PythonProject {
+  pub package_manager: PackageManager,
+  /// Version requirement as declared by the project, such as `>=3.9,<3.13`.
+  pub requires_python: Option<String>,
+  /// Interpreter version satisfying the requirement, usable as tag of the `python` image.
+  pub python_version: String,
+}
+
+pub fn _resolve_python_project(folder_path: &Path) -> Result<PythonProject, String> {
+  let package_manager = _resolve_package_manager(folder_path)?;
+  if package_manager.is_node() {
+    return Err(format!(
+      "{} is not a Python package manager",
+      package_manager
+    ));
+  }
+  let folder_path = fs::canonicalize(folder_path).map_err(|_| "Invalid folder path".to_string())?;
+
+  // Pinned interpreters take precedence over the requirements of the package manager.
+  if let Some(version) = pinned_python_version(&folder_path) {
+    return Ok(PythonProject {
+      package_manager,
+      requires_python: None,
+      python_version: version,
+    });
+  }
+  let requires_python = requires_python(&folder_path, &package_manager);
+  let python_version = match &requires_python {
+    Some(requirement) => _resolve_python_requirement(requirement)?,
+    None => DEFAULT_PYTHON_VERSION.to_string(),
+  };
+  Ok(PythonProject {
+    package_manager,
+    requires_python,
+    python_version,
+  })
+}
+
+/// Reads the exact version from `.python-version` (pyenv) or `runtime.txt` (Heroku).
+fn pinned_python_version(folder_path: &Path) -> Option<String> {
+  let exact = Regex::new(r"^\d+\.\d+(\.\d+)?$").unwrap();
+  let python_version = fs::read_to_string(folder_path.join(".python-version"))
+    .ok()
+    .and_then(|contents| contents.lines().next().map(|line| line.trim().to_string()));
+  let runtime = fs::read_to_string(folder_path.join("runtime.txt"))
+    .ok()
+    .map(|contents| contents.trim().trim_start_matches("python-").to_string());
+  [python_version, runtime]
+    .into_iter()
+    .flatten()
+    .find(|version| exact.is_match(version))
+}
+
+fn requires_python(folder_path: &Path, package_manager: &PackageManager) -> Option<String> {
+  let read_toml = |file_name: &str| {
+    fs::read_to_string(folder_path.join(file_name))
+      .ok()
+      .and_then(|contents| contents.parse::<toml::Value>().ok())
+  };
+  let as_string = |value: &toml::Value| value.as_str().map(|value| value.to_string());
+
+  if let Some(pyproject) = read_toml("pyproject.toml") {
+    // PEP 621, also used by uv and PDM.
+    if let Some(requirement) = pyproject
+      .get("project")
+      .and_then(|project| project.get("requires-python"))
+      .and_then(as_string)
+    {
+      return Some(requirement);
+    }
+    if let Some(requirement) = pyproject
+      .get("tool")
+      .and_then(|tool| tool.get("poetry"))
+      .and_then(|poetry| poetry.get("dependencies"))
+      .and_then(|deps| deps.get("python"))
+      .and_then(as_string)
+    {
+      return Some(requirement);
+    }
+  }
+
+  match package_manager {
+    PackageManager::Uv => {
+      read_toml("uv.lock").and_then(|lock| lock.get("requires-python").and_then(as_string))
+    }
+    PackageManager::Pipenv => {
+      let requires = read_toml("Pipfile")
+        .and_then(|pipfile| pipfile.get("requires").cloned())
+        .and_then(|requires| {
+          requires
+            .get("python_full_version")
+            .or_else(|| requires.get("python_version"))
+            .and_then(as_string)
+        });
+      requires
+        .or_else(|| {
+          let lock = fs::read_to_string(folder_path.join("Pipfile.lock")).ok()?;
+          let lock: serde_json::Value = serde_json::from_str(&lock).ok()?;
+          let requires = lock.get("_meta")?.get("requires")?;
+          requires
+            .get("python_full_version")
+            .or_else(|| requires.get("python_version"))?
+            .as_str()
+            .map(|version| version.to_string())
+        })
+        .map(|version| format!("=={}", version))
+    }
+    _ => None,
+  }
+}
+
+/// Picks the interpreter version for a requirement, in the syntax of PEP 440 or Poetry: an exact
+/// version when pinned with `==`, or else the newest supported minor release it allows. When it
+/// allows none, e.g. a release newer than the supported ones, its lower bound is used instead,
+/// falling back to the default version.
+pub fn _resolve_python_requirement(requirement: &str) -> Result<String, String> {
+  let requirement = requirement.trim();
+  let exact = Regex::new(r"^(?:==)?\s*(\d+\.\d+\.\d+)$").unwrap();
+  if let Some(captures) = exact.captures(requirement) {
+    return Ok(captures[1].to_string());
+  }
+
+  // Allow whitespace between operators and versions, as in `>= 3.9`.
+  let requirement = Regex::new(r"([<>=!~^]+)\s+")
+    .unwrap()
+    .replace_all(requirement, "$1");
+  let alternatives = requirement
+    .split("||")
+    .map(|alternative| {
+      alternative
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .map(str::trim)
+        .filter(|specifier| !specifier.is_empty())
+        .map(Specifier::parse)
+        .collect::<Option<Vec<_>>>()
+    })
+    .collect::<Option<Vec<_>>>()
+    .ok_or_else(|| format!("Invalid Python version requirement `{}`", requirement))?;
+
+  let version = SUPPORTED_PYTHON_VERSIONS
+    .iter()
+    .rev()
+    .find(|version| {
+      alternatives.iter().any(|specifiers| {
+        specifiers
+          .iter()
+          .all(|specifier| specifier.allows(**version))
+      })
+    })
+    .copied()
+    .or_else(|| {
+      alternatives
+        .iter()
+        .flatten()
+        .filter_map(Specifier::lower_bound)
+        .min()
+    })
+    .map_or_else(
+      || DEFAULT_PYTHON_VERSION.to_string(),
+      |(major, minor)| format!("{}.{}", major, minor),
+    );
+  Ok(version)
+}
+
+#[derive(Debug, PartialEq)]
+enum Operator {
+  Equal,
+  NotEqual,
+  Greater,
+  GreaterEqual,
+  Less,
+  LessEqual,
+  Compatible,
+  Caret,
+  Tilde,
+  Any,
+}
+
+/// A single version constraint, compared against minor releases.
+#[derive(Debug)]
+struct Specifier {
+  operator: Operator,
+  version: Vec<u64>,
+}
+
+impl Specifier {
+  fn parse(specifier: &str) -> Option<Specifier> {
+    if specifier == "*" {
+      return Some(Specifier {
+        operator: Operator::Any,
+        version: vec![],
+      });
+    }
+    let pattern = Regex::new(r"^(===|==|!=|>=|<=|~=|>|<|\^|~)?\s*(\d+(?:\.\d+)*)(\.\*)?$").unwrap();
+    let captures = pattern.captures(specifier)?;
+    let operator = match captures.get(1).map_or("", |operator| operator.as_str()) {
+      "" | "==" | "===" => Operator::Equal,
+      "!=" => Operator::NotEqual,
+      ">" => Operator::Greater,
+      ">=" => Operator::GreaterEqual,
+      "<" => Operator::Less,
+      "<=" => Operator::LessEqual,
+      "~=" => Operator::Compatible,
+      "^" => Operator::Caret,
+      "~" => Operator::Tilde,
+      _ => return None,
+    };
+    let version = captures[2]
+      .split('.')
+      .map(|part| part.parse().ok())
+      .collect::<Option<Vec<u64>>>()?;
+    Some(Specifier { operator, version })
+  }
+
+  /// The oldest minor release the constraint allows, if it sets one.
+  fn lower_bound(&self) -> Option<(u64, u64)> {
+    let major = *self.version.first()?;
+    let minor = self.version.get(1).copied().unwrap_or_default();
+    match self.operator {
+      Operator::Greater if self.version.len() <= 2 => {
+        if self.version.len() == 1 {
+          Some((major + 1, 0))
+        } else {
+          Some((major, minor + 1))
+        }
+      }
+      Operator::Greater
+      | Operator::GreaterEqual
+      | Operator::Equal
+      | Operator::Compatible
+      | Operator::Caret
+      | Operator::Tilde => Some((major, minor)),
+      _ => None,
+    }
+  }
+
+  /// Whether any release of the given minor version satisfies the constraint.
+  fn allows(&self, (major, minor): (u64, u64)) -> bool {
+    let candidate = [major, minor];
+    // Compare at the precision of the constraint, patch levels only narrow it within a minor.
+    let precision = self.version.len().min(2);
+    let bound = &self.version[..precision];
+    let candidate = &candidate[..precision];
+    let has_patch = self.version.len() > 2;
+    match self.operator {
+      Operator::Any => true,
+      Operator::Equal => candidate == bound,
+      Operator::NotEqual => has_patch || candidate != bound,
+      Operator::Greater => {
+        if has_patch {
+          candidate >= bound
+        } else {
+          candidate > bound
+        }
+      }
+      Operator::GreaterEqual => candidate >= bound,
+      Operator::Less => {
+        if has_patch {
+          candidate <= bound
+        } else {
+          candidate < bound
+        }
+      }
+      Operator::LessEqual => candidate <= bound,
+      // `~=3.9` allows `3.*` from `3.9`, `~=3.9.1` allows `3.9.*` from `3.9.1`.
+      Operator::Compatible => {
+        let prefix = self.version.len().saturating_sub(1).max(1);
+        candidate >= bound && major == self.version[0] && (prefix < 2 || minor == self.version[1])
+      }
+      // `^3.9` allows `3.*` from `3.9`.
+      Operator::Caret => candidate >= bound && major == self.version[0],
+      // `~3.9` allows `3.9.*`, `~3` allows `3.*`.
+      Operator::Tilde => candidate == bound,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::tempdir;
+
+  #[test]
+  fn test_resolve_python_requirement() {
+    let cases = [
+      ("^3.11", "3.14"),
+      (">=3.9,<3.12", "3.11"),
+      (">=3.8 <3.11", "3.10"),
+      ("~=3.10", "3.14"),
+      ("~=3.10.2", "3.10"),
+      ("~3.9", "3.9"),
+      ("==3.12.*", "3.12"),
+      ("3.10", "3.10"),
+      ("==3.11.4", "3.11.4"),
+      ("<=3.10", "3.10"),
+      (">3.11", "3.14"),
+      ("<3.11.2", "3.11"),
+      ("!=3.14", "3.13"),
+      ("<3.9 || >=3.11,<3.12", "3.11"),
+      (">= 3.9, < 3.11", "3.10"),
+      ("*", "3.14"),
+      (">=3", "3.14"),
+      (">=3.15", "3.15"),
+      (">3.15", "3.16"),
+      ("^4", "4.0"),
+      (">=3.16 || >=3.15,<3.16", "3.15"),
+      ("<3.8", DEFAULT_PYTHON_VERSION),
+    ];
+    for (requirement, version) in cases {
+      assert_eq!(
+        _resolve_python_requirement(requirement),
+        Ok(version.to_string()),
+        "{}",
+        requirement
+      );
+    }
+    assert!(_resolve_python_requirement("latest").is_err());
+  }
+
+  #[test]
+  fn test_resolve_python_project_pep_621() {
+    let temp_dir = tempdir().unwrap();
+    fs::write(
+      temp_dir.path().join("pyproject.toml"),
+      "[project]\nname = \"app\"\nrequires-python = \">=3.9,<3.12\"\n",
+    )
+    .unwrap();
+    fs::write(temp_dir.path().join("uv.lock"), "version = 1\n").unwrap();
+
+    let project = _resolve_python_project(temp_dir.path()).unwrap();
+    assert_eq!(
+      project,
+      PythonProject {
+        package_manager: PackageManager::Uv,
+        requires_python: Some(">=3.9,<3.12".to_string()),
+        python_version: "3.11".to_string(),
+      }
+    );
+  }
+
+  #[test]
+  fn test_resolve_python_project_pinned() {
+    let temp_dir = tempdir().unwrap();
+    fs::write(temp_dir.path().join("requirements.txt"), "flask\n").unwrap();
+    fs::write(temp_dir.path().join("runtime.txt"), "python-3.10.13\n").unwrap();
+    let project = _resolve_python_project(temp_dir.path()).unwrap();
+    assert_eq!(project.python_version, "3.10.13");
+
+    fs::write(temp_dir.path().join(".python-version"), "3.12\n").unwrap();
+    let project = _resolve_python_project(temp_dir.path()).unwrap();
+    assert_eq!(project.python_version, "3.12");
+  }
+
+  #[test]
+  fn test_resolve_python_project_pipenv() {
+    let temp_dir = tempdir().unwrap();
+    fs::write(
+      temp_dir.path().join("Pipfile.lock"),
+      r#"{"_meta": {"requires": {"python_version": "3.9"}}}"#,
+    )
+    .unwrap();
+    let project = _resolve_python_project(temp_dir.path()).unwrap();
+    assert_eq!(project.package_manager, PackageManager::Pipenv);
+    assert_eq!(project.requires_python, Some("==3.9".to_string()));
+    assert_eq!(project.python_version, "3.9");
+  }
+
+  #[test]
+  fn test_resolve_python_project_default() {
+    let temp_dir = tempdir().unwrap();
+    fs::write(temp_dir.path().join("requirements.txt"), "flask\n").unwrap();
+    let project = _resolve_python_project(temp_dir.path()).unwrap();
+    assert_eq!(project.requires_python, None);
+    assert_eq!(project.python_version, DEFAULT_PYTHON_VERSION);
+  }
+}